@@ -0,0 +1,277 @@
+use quart_lib::{Game, Piece, Winner};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use crate::{AgentError, AiAgent, Control, HumanLikeAgent, Move, Placement};
+use crate::analysis::ScoredMove;
+use crate::search::SearchLimits;
+
+/// The first line of every skills file, followed by the format version
+const HEADER: &str = "quart-skills";
+/// The version of the skills format, increased on incompatible changes
+pub const VERSION: u32 = 1;
+/// The smallest change of the level after a game
+const MIN_STEP: f64 = 0.05;
+
+/// How well a human plays, with their results against the AI
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Skill {
+	/// The strength the AI plays at, from 0 (many blunders) to 1 (no blunders, deep search)
+	pub level: f64,
+	/// How often the human won
+	pub wins: u32,
+	/// How often the game was drawn
+	pub draws: u32,
+	/// How often the human lost
+	pub losses: u32,
+}
+impl Skill {
+	/// Number of games played
+	pub fn games(&self) -> u32 {
+		self.wins + self.draws + self.losses
+	}
+
+	/// Record a game the human won (`Some(true)`), lost (`Some(false)`) or drew (`None`).
+	/// A win makes the AI stronger, a loss weaker, so the human wins about half the games.
+	/// The level changes a lot in the first games and less once it has settled
+	pub fn record(&mut self, won: Option<bool>) {
+		let step = (0.3 / (1.0 + self.games() as f64 / 5.0)).max(MIN_STEP);
+		let result = match won {
+			Some(true) => {
+				self.wins += 1;
+				1.0
+			},
+			Some(false) => {
+				self.losses += 1;
+				-1.0
+			},
+			None => {
+				self.draws += 1;
+				0.0
+			},
+		};
+		self.level = (self.level + step * result).clamp(0.0, 1.0);
+	}
+
+	/// The agent playing at this level: searching 1 to 4 plies deep, and the weaker,
+	/// the more random its choices and the more often it blunders
+	pub fn agent(&self) -> HumanLikeAgent {
+		let weakness = 1.0 - self.level;
+		HumanLikeAgent::new()
+			.with_depth(1 + (self.level * 3.0).round() as u32)
+			.temperature(100.0 * weakness)
+			.miss_threats(0.5 * weakness)
+			.unsafe_gives(0.5 * weakness)
+	}
+}
+impl Default for Skill {
+	/// A beginner
+	fn default() -> Self {
+		Self { level: 0.3, wins: 0, draws: 0, losses: 0 }
+	}
+}
+
+/// The skills of all players, by name.
+///
+/// The file format is text, one player per line after the header line `quart-skills 1`:
+/// the level, the wins, draws and losses, and the name, like `0.45 3 0 2 Anton`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Skills {
+	players: BTreeMap<String, Skill>,
+}
+impl Skills {
+	/// No players yet
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// The skill of `name`, a beginner if they never played
+	pub fn get(&self, name: &str) -> Skill {
+		self.players.get(name).copied().unwrap_or_default()
+	}
+
+	/// Record a game of `name`, see `Skill::record`
+	pub fn record(&mut self, name: &str, won: Option<bool>) {
+		self.players.entry(name.to_string()).or_default().record(won);
+	}
+
+	/// Read skills
+	///
+	/// Errors:
+	/// - InvalidData: not a skills file, one of a different version or a malformed line
+	pub fn read<R: BufRead>(reader: R) -> io::Result<Self> {
+		let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+		let mut lines = reader.lines();
+		let header = lines.next().unwrap_or_else(|| Ok(String::new()))?;
+		if header != format!("{} {}", HEADER, VERSION) {
+			return Err(invalid(format!("unsupported skills header {:?}", header)));
+		}
+		let mut skills = Self::new();
+		for (nr, line) in lines.enumerate() {
+			let line = line?;
+			if line.trim().is_empty() {
+				continue;
+			}
+			let (name, skill) = parse_line(&line).ok_or_else(|| invalid(format!("line {}: malformed {:?}", nr + 2, line)))?;
+			skills.players.insert(name, skill);
+		}
+		Ok(skills)
+	}
+
+	/// Write the skills, sorted by name
+	pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+		writeln!(writer, "{} {}", HEADER, VERSION)?;
+		for (name, skill) in self.players.iter() {
+			writeln!(writer, "{} {} {} {} {}", skill.level, skill.wins, skill.draws, skill.losses, name)?;
+		}
+		writer.flush()
+	}
+
+	/// Read the skills at `path`, no players if there is no such file
+	pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		match File::open(path) {
+			Ok(file) => Self::read(BufReader::new(file)),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::new()),
+			Err(e) => Err(e),
+		}
+	}
+
+	/// Write the skills to the file at `path`
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+		self.write(BufWriter::new(File::create(path)?))
+	}
+}
+
+fn parse_line(line: &str) -> Option<(String, Skill)> {
+	let mut fields = line.splitn(5, ' ');
+	let level = fields.next()?.parse::<f64>().ok().filter(|level| (0.0..=1.0).contains(level))?;
+	let mut count = || fields.next()?.parse::<u32>().ok();
+	let skill = Skill { level, wins: count()?, draws: count()?, losses: count()? };
+	let name = fields.next().filter(|name| !name.is_empty())?;
+	Some((name.to_string(), skill))
+}
+
+/// Plays as strong as the human it plays against: the skill of every human is kept
+/// in a file (see `Skills`), updated after each game and used to choose the blunders
+/// and search depth of a `HumanLikeAgent`, so the human wins about half the games
+pub struct AdaptiveAgent {
+	name: String,
+	path: Option<PathBuf>,
+	skills: Skills,
+	agent: HumanLikeAgent,
+	player: Option<u32>,
+}
+impl AdaptiveAgent {
+	/// Play against `name` with the skills in the file at `path`, which is
+	/// created or updated after each game
+	pub fn load<P: AsRef<Path>>(path: P, name: &str) -> io::Result<Self> {
+		let mut agent = Self::new(Skills::load(&path)?, name);
+		agent.path = Some(path.as_ref().to_path_buf());
+		Ok(agent)
+	}
+
+	/// Play against `name` with `skills`, which aren't saved
+	pub fn new(skills: Skills, name: &str) -> Self {
+		let agent = skills.get(name).agent();
+		Self { name: name.to_string(), path: None, skills, agent, player: None }
+	}
+
+	/// The current skill of the human
+	pub fn skill(&self) -> Skill {
+		self.skills.get(&self.name)
+	}
+
+	/// The skills of all players
+	pub fn skills(&self) -> &Skills {
+		&self.skills
+	}
+}
+impl AiAgent for AdaptiveAgent {
+	fn choose_placement(&mut self, game: &Game) -> Option<Placement> {
+		self.player = Some(game.player_turn);
+		self.agent.choose_placement(game)
+	}
+
+	fn choose_give(&mut self, game: &Game, placement: Option<Placement>) -> Option<Piece> {
+		self.player = Some(game.player_turn);
+		self.agent.choose_give(game, placement)
+	}
+
+	fn play(&mut self, game: &Game) -> Result<Move, AgentError> {
+		self.player = Some(game.player_turn);
+		self.agent.play(game)
+	}
+
+	fn play_controlled(&mut self, game: &Game, control: &Control) -> Result<Move, AgentError> {
+		self.player = Some(game.player_turn);
+		self.agent.play_controlled(game, control)
+	}
+
+	fn analyze(&mut self, game: &Game, limits: &SearchLimits) -> Vec<ScoredMove> {
+		self.agent.analyze(game, limits)
+	}
+
+	fn game_over(&mut self, game: &Game) {
+		// only games the agent took part in count
+		let player = match self.player.take() {
+			Some(player) => player,
+			None => return,
+		};
+		let won = match game.winner() {
+			Some(Winner::Player(winner)) => Some(winner != player),
+			Some(Winner::Team(_, members)) => Some(!members.contains(&player)),
+			None => None,
+		};
+		self.skills.record(&self.name, won);
+		self.agent = self.skill().agent();
+		log::info!("Skill of {:?} is now {:?}", self.name, self.skill());
+		if let Some(path) = &self.path {
+			if let Err(e) = self.skills.save(path) {
+				log::warn!("Can't save skills to {:?}: {}", path, e);
+			}
+		}
+	}
+}
+
+#[test]
+fn test_adaptive_agent() {
+	use crate::DecisionTreeAgent;
+
+	let mut skills = Skills::new();
+	skills.record("Anton", Some(true));
+	assert!(skills.get("Anton").level > Skill::default().level);
+	skills.record("Hermann the Great", Some(false));
+	skills.record("Hermann the Great", None);
+	assert_eq!(skills.get("Hermann the Great").games(), 2);
+	let mut file = Vec::new();
+	skills.write(&mut file).unwrap();
+	assert_eq!(Skills::read(io::Cursor::new(&file)).unwrap(), skills);
+	assert!(Skills::read(io::Cursor::new("quart-skills 1\n2.5 1 0 0 Anton\n")).is_err());
+
+	// a greedy human plays a game, the AI adapts to the result
+	skills.players.insert("Beginner".to_string(), Skill { level: 0.1, ..Skill::default() });
+	let mut agent = AdaptiveAgent::new(skills, "Beginner");
+	let before = agent.skill();
+	let mut human = DecisionTreeAgent::with_depth(1).without_endgame();
+	let mut game = Game::new();
+	let ai_player = game.player_turn;
+	while !game.is_over() {
+		let mov = match game.player_turn == ai_player {
+			true => agent.play(&game),
+			false => human.play(&game),
+		};
+		mov.unwrap().apply(&mut game).unwrap();
+	}
+	agent.game_over(&game);
+	let after = agent.skill();
+	assert_eq!(after.games(), before.games() + 1);
+	match game.winner() {
+		Some(Winner::Player(winner)) if winner == ai_player => assert!(after.level < before.level),
+		Some(_) => assert!(after.level > before.level),
+		None => assert_eq!(after.level, before.level),
+	}
+	// a game only counts once
+	agent.game_over(&game);
+	assert_eq!(agent.skill().games(), after.games());
+}
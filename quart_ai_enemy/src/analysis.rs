@@ -0,0 +1,178 @@
+use crate::Move;
+use crate::clock::Clock;
+use crate::eval::Evaluator;
+use crate::position::Position;
+use crate::search::{Search, SearchLimits, WIN, WIN_THRESHOLD};
+use crate::solver::{Outcome, Solution, Solver, ENDGAME_EMPTY, ENDGAME_NODES};
+
+/// A move with its score and the expected continuation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScoredMove {
+	/// The move
+	pub mov: Move,
+	/// The score for the player making the move, see `search::WIN`
+	pub score: i32,
+	/// The result with perfect play, `None` if the score is just an evaluation
+	pub outcome: Option<Outcome>,
+	/// The expected moves after this one, starting with the opponent's
+	pub pv: Vec<Move>,
+}
+
+/// Every legal move in `position` with its score and expected continuation, best first.
+///
+/// Small end games (see `solver::ENDGAME_EMPTY`) are solved, other positions searched
+/// with iterative deepening as deep as `limits` allow. A depth once started is completed,
+/// so the limits may be exceeded by the time of one depth
+pub fn analyze(position: &Position, limits: &SearchLimits, clock: &dyn Clock, evaluator: Option<&dyn Evaluator>) -> Vec<ScoredMove> {
+	if position.empty_count() <= ENDGAME_EMPTY {
+		if let Some(solved) = solve(position) {
+			return solved;
+		}
+	}
+	let new_search = || match evaluator {
+		Some(evaluator) => Search::new().evaluator(evaluator),
+		None => Search::new(),
+	};
+	let start = clock.elapsed();
+	let remaining = remaining_plies(position);
+	let mut nodes = 0;
+	let mut depth = 1;
+	let scored = loop {
+		let mut search = new_search();
+		let scored = search.analyze(position, depth);
+		nodes += search.nodes();
+		let out_of_time = match limits.time {
+			Some(time) => clock.elapsed() >= start + time,
+			None => false,
+		};
+		let out_of_nodes = match limits.nodes {
+			Some(limit) => nodes >= limit,
+			None => false,
+		};
+		if depth >= limits.max_depth || depth >= remaining || out_of_time || out_of_nodes {
+			break scored;
+		}
+		depth += 1;
+	};
+
+	scored.into_iter()
+		.map(|(ply, score)| {
+			let mut pv = Vec::new();
+			let mut current = position.play(ply);
+			if !position.is_winning(ply) {
+				for depth in (1..depth).rev() {
+					let best = match new_search().run(&current, depth).best {
+						Some(best) => best,
+						None => break,
+					};
+					pv.push(best.into_move());
+					if current.is_winning(best) {
+						break;
+					}
+					current = current.play(best);
+				}
+			}
+			let outcome = if score >= WIN_THRESHOLD {
+				Some(Outcome::Win((WIN - score + 1) as u32))
+			} else if score <= -WIN_THRESHOLD {
+				Some(Outcome::Loss((WIN + score + 1) as u32))
+			} else if depth >= remaining {
+				// searched to the end of the game without anybody winning
+				Some(Outcome::Draw(remaining))
+			} else {
+				None
+			};
+			ScoredMove { mov: ply.into_move(), score, outcome, pv }
+		})
+		.collect()
+}
+
+/// How many plies the game can last at most, the give of the first turn included
+fn remaining_plies(position: &Position) -> u32 {
+	let first_give = position.in_hand().is_none() && !position.free_choice() && position.empty_count() == 16;
+	position.empty_count() + first_give as u32
+}
+
+/// Solve every move of `position`, `None` if the solver gives up
+fn solve(position: &Position) -> Option<Vec<ScoredMove>> {
+	let mut solver = Solver::new().node_limit(ENDGAME_NODES);
+	let mut scored = Vec::new();
+	for ply in position.legal_plies() {
+		let (outcome, pv) = if position.is_winning(ply) {
+			(Outcome::Win(1), Vec::new())
+		} else {
+			let child = position.play(ply);
+			let solution = solver.solve(&child)?;
+			let outcome = solution.outcome.before();
+			(outcome, solved_line(&mut solver, child, solution)?)
+		};
+		scored.push(ScoredMove { mov: ply.into_move(), score: outcome.score(), outcome: Some(outcome), pv });
+	}
+	scored.sort_by_key(|scored| -scored.score);
+	Some(scored)
+}
+
+/// The moves of perfect play from `position` with its `solution` on
+fn solved_line(solver: &mut Solver, mut position: Position, mut solution: Solution) -> Option<Vec<Move>> {
+	let mut line = Vec::new();
+	while let Some(best) = solution.best.first().copied() {
+		line.push(best.into_move());
+		if position.is_winning(best) {
+			break;
+		}
+		position = position.play(best);
+		solution = solver.solve(&position)?;
+	}
+	Some(line)
+}
+
+/// The ply of `mov` in `position`, for tests
+#[cfg(test)]
+fn ply_of(position: &Position, mov: Move) -> crate::position::Ply {
+	*position.legal_plies().iter().find(|ply| ply.into_move() == mov).unwrap()
+}
+
+#[test]
+fn test_analyze() {
+	use crate::clock::SystemClock;
+	use crate::position::Ply;
+
+	let clock = SystemClock::new();
+	// the top row holds 3 big pieces, a big piece is in hand
+	let position = Position::new(false)
+		.play(Ply { place: None, give: Some(8) })
+		.play(Ply { place: Some((0, 8)), give: Some(9) })
+		.play(Ply { place: Some((1, 9)), give: Some(10) })
+		.play(Ply { place: Some((2, 10)), give: Some(12) });
+	let scored = analyze(&position, &SearchLimits::depth(2), &clock, None);
+	assert_eq!(scored.len(), position.legal_plies().len());
+	assert_eq!(scored[0].outcome, Some(Outcome::Win(1)));
+	assert_eq!(scored[0].mov.placement.map(|p| p.pos.index()), Some(3));
+	assert!(scored.windows(2).all(|pair| pair[0].score >= pair[1].score));
+	// giving a big piece after not winning loses
+	let losing = scored.iter().find(|s| s.mov.placement.map(|p| p.pos.index()) == Some(4) && s.mov.give.map(|g| g.big) == Some(true)).unwrap();
+	assert_eq!(losing.outcome, Some(Outcome::Loss(2)));
+
+	// a small end game is solved, with a line for every move
+	let mut position = Position::new(false);
+	let plies = [(None, 3), (Some(0), 12), (Some(5), 6), (Some(10), 9), (Some(15), 0), (Some(1), 15), (Some(6), 5), (Some(11), 10), (Some(12), 2)];
+	let mut in_hand = None;
+	for (cell, give) in plies.iter() {
+		let place = cell.map(|cell| (cell, in_hand.unwrap()));
+		position = position.play(Ply { place, give: Some(*give) });
+		in_hand = Some(*give);
+	}
+	assert_eq!(position.empty_count(), 8);
+	let scored = analyze(&position, &SearchLimits::depth(1), &clock, None);
+	assert!(scored.iter().all(|s| s.outcome.is_some()));
+	// the line is legal
+	let best = &scored[0];
+	let mut line = position.play(ply_of(&position, best.mov));
+	for mov in best.pv.iter() {
+		let ply = ply_of(&line, *mov);
+		if line.is_winning(ply) {
+			break;
+		}
+		line = line.play(ply);
+	}
+}
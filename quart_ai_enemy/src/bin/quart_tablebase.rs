@@ -0,0 +1,53 @@
+#![warn(missing_docs)]
+//! Generates an end game tablebase file, see `quart_ai_enemy::tablebase`.
+//!
+//! The table holds the end games of random games: an exhaustive table, see
+//! `Tablebase::generate`, is out of reach for every useful number of empty cells
+
+use quart_ai_enemy::tablebase::Tablebase;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// How many random games are played without `--games`
+const DEFAULT_GAMES: u32 = 1000;
+
+/// The most empty cells a table is made for: every position below a root is stored,
+/// which are up to (n!)^2 for n empty cells
+const MAX_EMPTY: u32 = 6;
+
+const USAGE: &str = "usage: quart_tablebase [--free] [--games N] [--seed N] MAX_EMPTY FILE";
+
+/// Write the tablebase of the command line to its file:
+/// - `--free`: players choose their own pieces
+/// - `--games N`: the end games of N random games are stored, `DEFAULT_GAMES` by default
+/// - `--seed N`: seed for the random games, 0 by default
+/// - `MAX_EMPTY`: positions with up to this many empty cells are stored, at most `MAX_EMPTY`
+fn main() -> Result<()> {
+	let mut free_choice = false;
+	let mut games = DEFAULT_GAMES;
+	let mut seed = 0;
+	let mut positional = Vec::new();
+	let mut args = std::env::args().skip(1);
+	while let Some(arg) = args.next() {
+		let mut value = || args.next().ok_or_else(|| format!("{} needs a value\n{}", arg, USAGE));
+		match arg.as_str() {
+			"--free" => free_choice = true,
+			"--games" => games = value()?.parse()?,
+			"--seed" => seed = value()?.parse()?,
+			_ => positional.push(arg),
+		}
+	}
+	let (max_empty, path) = match positional.as_slice() {
+		[max_empty, path] => (max_empty.parse()?, path),
+		_ => return Err(USAGE.into()),
+	};
+	if max_empty > MAX_EMPTY {
+		return Err(format!("MAX_EMPTY {} is too large, tables are made for up to {} empty cells", max_empty, MAX_EMPTY).into());
+	}
+
+	let mut tablebase = Tablebase::new(max_empty);
+	tablebase.add_random(games, free_choice, seed);
+	tablebase.save(path)?;
+	eprintln!("{} positions written to {}", tablebase.len(), path);
+	Ok(())
+}
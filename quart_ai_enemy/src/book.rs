@@ -0,0 +1,357 @@
+use quart_lib::{Game, Piece};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use crate::{AgentError, AiAgent, Control, Move, Placement};
+use crate::analysis::ScoredMove;
+use crate::mcts_agent::{Budget, MctsAgent};
+use crate::position::{Ply, Position};
+use crate::search::SearchLimits;
+use crate::solver;
+
+/// The first line of every book file, followed by the format version
+const HEADER: &str = "quart-book";
+/// The version of the book format, increased on incompatible changes
+pub const VERSION: u32 = 1;
+
+/// An opening book: weighted candidate plies for positions, keyed by the
+/// canonical position (see `solver::canonical`), so symmetric positions share their entry.
+///
+/// The file format is text, one position per line after the header line `quart-book 1`:
+/// the key in hex, followed by candidates like `5,3,7=120`, which is
+/// "place piece 3 on cell 5, give piece 7" with weight 120 (`-` for nothing placed or given)
+#[derive(Debug)]
+pub struct Book {
+	entries: HashMap<u128, Vec<(Ply, u32)>>,
+	symmetries: Vec<[u8; 16]>,
+}
+
+impl Book {
+	/// An empty book
+	pub fn new() -> Self {
+		Self {
+			entries: HashMap::new(),
+			symmetries: solver::symmetries(),
+		}
+	}
+
+	/// Number of positions in the book
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	/// Whether the book has no positions
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	/// Add `ply` with `weight` to the candidates of `position`, adding up the weights
+	/// if it is there already
+	pub fn add(&mut self, position: &Position, ply: Ply, weight: u32) {
+		let (key, transform) = solver::canonical_transform(position, &self.symmetries);
+		let ply = transform.apply(ply);
+		let candidates = self.entries.entry(key).or_default();
+		match candidates.iter_mut().find(|(p, _)| *p == ply) {
+			Some((_, w)) => *w += weight,
+			None => candidates.push((ply, weight)),
+		}
+	}
+
+	/// The candidates for `position` with their weights, empty when out of book
+	pub fn candidates(&self, position: &Position) -> Vec<(Ply, u32)> {
+		let (key, transform) = solver::canonical_transform(position, &self.symmetries);
+		match self.entries.get(&key) {
+			Some(candidates) => candidates.iter().map(|(ply, w)| (transform.revert(*ply), *w)).collect(),
+			None => Vec::new(),
+		}
+	}
+
+	/// A candidate for `position`, chosen at random according to the weights
+	pub fn choose<R: Rng>(&self, position: &Position, rng: &mut R) -> Option<Ply> {
+		let candidates = self.candidates(position);
+		candidates.choose_weighted(rng, |(_, weight)| *weight).ok().map(|(ply, _)| *ply)
+	}
+
+	/// Read a book
+	///
+	/// Errors:
+	/// - InvalidData: not a book, one of a different version or a malformed line
+	pub fn read<R: BufRead>(reader: R) -> io::Result<Self> {
+		let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+		let mut lines = reader.lines();
+		let header = lines.next().unwrap_or_else(|| Ok(String::new()))?;
+		if header != format!("{} {}", HEADER, VERSION) {
+			return Err(invalid(format!("unsupported book header {:?}", header)));
+		}
+		let mut book = Self::new();
+		for (nr, line) in lines.enumerate() {
+			let line = line?;
+			let mut fields = line.split_whitespace();
+			let key = match fields.next() {
+				Some(key) => u128::from_str_radix(key, 16).map_err(|e| invalid(format!("line {}: {}", nr + 2, e)))?,
+				None => continue,
+			};
+			let candidates = fields
+				.map(|field| parse_candidate(field).ok_or_else(|| invalid(format!("line {}: bad candidate {:?}", nr + 2, field))))
+				.collect::<io::Result<Vec<(Ply, u32)>>>()?;
+			book.entries.insert(key, candidates);
+		}
+		Ok(book)
+	}
+
+	/// Write the book, positions sorted by key so the output is deterministic
+	pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+		writeln!(writer, "{} {}", HEADER, VERSION)?;
+		let mut keys: Vec<&u128> = self.entries.keys().collect();
+		keys.sort_unstable();
+		for key in keys {
+			write!(writer, "{:032x}", key)?;
+			for (ply, weight) in self.entries[key].iter() {
+				write!(writer, " {}={}", format_ply(*ply), weight)?;
+			}
+			writeln!(writer)?;
+		}
+		writer.flush()
+	}
+
+	/// Read the book at `path`
+	pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		Self::read(BufReader::new(File::open(path)?))
+	}
+
+	/// Write the book to the file at `path`
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+		self.write(BufWriter::new(File::create(path)?))
+	}
+}
+impl Default for Book {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+fn format_ply(ply: Ply) -> String {
+	let opt = |value: Option<u8>| value.map_or_else(|| "-".to_string(), |v| v.to_string());
+	format!("{},{},{}", opt(ply.place.map(|p| p.0)), opt(ply.place.map(|p| p.1)), opt(ply.give))
+}
+
+fn parse_candidate(field: &str) -> Option<(Ply, u32)> {
+	let mut parts = field.splitn(2, '=');
+	let ply = parts.next()?;
+	let weight = parts.next()?.parse().ok()?;
+	let opt = |value: &str| -> Option<Option<u8>> {
+		match value {
+			"-" => Some(None),
+			v => v.parse().ok().filter(|v| *v < 16).map(Some),
+		}
+	};
+	let values: Vec<Option<u8>> = ply.split(',').map(opt).collect::<Option<_>>()?;
+	let place = match (values.first()?, values.get(1)?) {
+		(Some(cell), Some(piece)) => Some((*cell, *piece)),
+		(None, None) => None,
+		_ => return None,
+	};
+	Some((Ply { place, give: *values.get(2)? }, weight))
+}
+
+/// Builds a book by searching the first plies of the game with a `MctsAgent`,
+/// weighting the candidates by how often the search visited them
+#[derive(Debug, Clone)]
+pub struct BookBuilder {
+	/// How many plies from the start of the game the book covers
+	pub plies: u32,
+	/// How many of the best candidates to keep, and to follow to deeper positions
+	pub width: usize,
+	/// The search budget per position
+	pub budget: Budget,
+	/// Seed for the searches
+	pub seed: u64,
+}
+impl BookBuilder {
+	/// A builder covering `plies` plies, keeping 3 candidates per position
+	pub fn new(plies: u32) -> Self {
+		Self {
+			plies,
+			width: 3,
+			budget: Budget::Iterations(crate::mcts_agent::DEFAULT_ITERATIONS),
+			seed: 0,
+		}
+	}
+
+	/// Build the book for the given turn structure
+	pub fn build(&self, free_choice: bool) -> Book {
+		let mut book = Book::new();
+		self.extend(&mut book, free_choice);
+		book
+	}
+
+	/// Add the positions for the given turn structure to `book`
+	pub fn extend(&self, book: &mut Book, free_choice: bool) {
+		let mut agent = MctsAgent::with_budget(self.budget).seed(self.seed);
+		let mut frontier = vec![Position::new(free_choice)];
+		for _ in 0..self.plies {
+			let mut next = Vec::new();
+			for position in frontier {
+				if !book.candidates(&position).is_empty() {
+					// a symmetric position was searched already
+					continue;
+				}
+				let best = match agent.search(&position) {
+					Some(best) => best,
+					None => continue,
+				};
+				let mut candidates = agent.root_visits();
+				if candidates.is_empty() {
+					candidates.push((best, 1));
+				}
+				candidates.sort_by_key(|(_, visits)| std::cmp::Reverse(*visits));
+				candidates.truncate(self.width);
+				for (ply, visits) in candidates {
+					book.add(&position, ply, visits.max(1));
+					if !position.is_winning(ply) {
+						next.push(position.play(ply));
+					}
+				}
+			}
+			frontier = next;
+		}
+	}
+}
+
+/// Plays from an opening book, and lets another agent play when out of book
+pub struct BookAgent {
+	book: Book,
+	fallback: Box<dyn AiAgent + Send>,
+	rng: StdRng,
+}
+impl BookAgent {
+	/// Play from `book`, or like `fallback` when out of book
+	pub fn new(book: Book, fallback: Box<dyn AiAgent + Send>) -> Self {
+		Self { book, fallback, rng: StdRng::from_entropy() }
+	}
+
+	/// Seed the random number generator choosing between candidates
+	pub fn seed(mut self, seed: u64) -> Self {
+		self.rng = StdRng::seed_from_u64(seed);
+		self
+	}
+}
+impl BookAgent {
+	/// A legal book ply for `position` which `keep` accepts, `None` when out of book
+	fn book_ply(&mut self, position: &Position, keep: impl Fn(&Ply) -> bool) -> Option<Ply> {
+		let legal = position.legal_plies();
+		let candidates: Vec<(Ply, u32)> = self.book.candidates(position).into_iter()
+			.filter(|(ply, _)| keep(ply) && legal.contains(ply))
+			.collect();
+		let (ply, _) = candidates.choose_weighted(&mut self.rng, |(_, weight)| *weight).ok()?;
+		log::debug!("Book move {:?}", ply);
+		Some(*ply)
+	}
+}
+impl AiAgent for BookAgent {
+	fn choose_placement(&mut self, game: &Game) -> Option<Placement> {
+		match self.book_ply(&Position::from_game(game), |_| true) {
+			Some(ply) => ply.placement(),
+			None => self.fallback.choose_placement(game),
+		}
+	}
+
+	/// The book has whole plies of the position before the placement,
+	/// their give is played if their placement was
+	fn choose_give(&mut self, game: &Game, placement: Option<Placement>) -> Option<Piece> {
+		let place = placement.map(Placement::indices);
+		match self.book_ply(&Position::from_game(game), |ply| ply.place == place) {
+			Some(ply) => ply.given_piece(),
+			None => self.fallback.choose_give(game, placement),
+		}
+	}
+
+	fn play(&mut self, game: &Game) -> Result<Move, AgentError> {
+		match self.book_ply(&Position::from_game(game), |_| true) {
+			Some(ply) => Ok(ply.into_move()),
+			None => self.fallback.play(game),
+		}
+	}
+
+	fn play_controlled(&mut self, game: &Game, control: &Control) -> Result<Move, AgentError> {
+		match self.book_ply(&Position::from_game(game), |_| true) {
+			Some(ply) => Ok(ply.into_move()),
+			None => self.fallback.play_controlled(game, control),
+		}
+	}
+
+	fn ponder(&mut self, game: &Game, control: &Control) {
+		self.fallback.ponder(game, control)
+	}
+
+	fn analyze(&mut self, game: &Game, limits: &SearchLimits) -> Vec<ScoredMove> {
+		self.fallback.analyze(game, limits)
+	}
+
+	fn game_over(&mut self, game: &Game) {
+		self.fallback.game_over(game)
+	}
+}
+
+#[test]
+fn test_book_roundtrip() {
+	let builder = BookBuilder { plies: 2, width: 2, budget: Budget::Iterations(300), seed: 3 };
+	let book = builder.build(false);
+	assert_eq!(book.candidates(&Position::new(false)).len(), 2);
+	// giving any piece first leads to the same position, after flipping attributes
+	assert_eq!(book.len(), 2);
+
+	let mut file = Vec::new();
+	book.write(&mut file).unwrap();
+	let read = Book::read(io::Cursor::new(&file)).unwrap();
+	assert_eq!(read.entries, book.entries);
+	assert!(Book::read(io::Cursor::new("quart-book 0\n")).is_err());
+	assert!(Book::read(io::Cursor::new("quart-book 1\n1f 1,2=3\n")).is_err());
+}
+
+#[test]
+fn test_book_symmetric_positions() {
+	let mut book = Book::new();
+	let position = Position::new(true).play(Ply { place: Some((0, 5)), give: None });
+	book.add(&position, Ply { place: Some((1, 6)), give: None }, 4);
+	// the same position, mirrored along the diagonal and with another piece
+	let mirrored = Position::new(true).play(Ply { place: Some((0, 9)), give: None });
+	let candidates = book.candidates(&mirrored);
+	assert_eq!(candidates.len(), 1);
+	let (ply, weight) = candidates[0];
+	assert_eq!(weight, 4);
+	assert!(mirrored.legal_plies().contains(&ply));
+	assert_eq!(ply.place.map(|p| p.1), Some(6 ^ 5 ^ 9));
+}
+
+#[test]
+fn test_book_agent_falls_back() {
+	let mut game = Game::new();
+	let mut agent = BookAgent::new(Book::new(), Box::new(crate::RandAgent::new(&game))).seed(1);
+	assert!(agent.play(&game).unwrap().apply(&mut game).is_ok());
+	// the agent moves to the thread of an `AgentRunner`
+	let mut runner = crate::AgentRunner::new(agent);
+	runner.start(&game);
+	assert!(runner.wait().unwrap().unwrap().apply(&mut game).is_ok());
+}
+
+#[test]
+fn test_book_agent_split_turn() {
+	let mut game = Game::new();
+	game.select_next_piece(Piece::from_index(0)).unwrap();
+	let position = Position::from_game(&game);
+	let mut book = Book::new();
+	book.add(&position, Ply { place: Some((5, 0)), give: Some(7) }, 1);
+	let mut agent = BookAgent::new(book, Box::new(crate::RandAgent::new(&game).seed(1))).seed(1);
+
+	let placement = agent.choose_placement(&game).unwrap();
+	assert_eq!(placement.indices(), (5, 0));
+	assert_eq!(agent.choose_give(&game, Some(placement)), Some(Piece::from_index(7)));
+	// another placement than the book's is out of book
+	let other = Ply { place: Some((6, 0)), give: None }.placement();
+	let give = agent.choose_give(&game, other).unwrap();
+	assert_ne!(give, Piece::from_index(0));
+}
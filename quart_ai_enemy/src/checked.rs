@@ -0,0 +1,121 @@
+use quart_lib::{Game, Piece};
+use crate::{AgentError, AiAgent, Control, Move, Placement};
+use crate::analysis::ScoredMove;
+use crate::search::SearchLimits;
+
+/// Validates the moves of an agent before they reach the game: an illegal move
+/// is logged and reported as `AgentError::IllegalMove`, or ends in a forfeit
+pub struct Checked<A> {
+	agent: A,
+	forfeit: bool,
+	forfeited: bool,
+}
+impl<A: AiAgent> Checked<A> {
+	/// Check the moves of `agent`
+	pub fn new(agent: A) -> Self {
+		Self { agent, forfeit: false, forfeited: false }
+	}
+
+	/// Forfeit the game on the first illegal move: from then on, every move
+	/// is `AgentError::Forfeit`
+	pub fn forfeit(mut self) -> Self {
+		self.forfeit = true;
+		self
+	}
+
+	/// Whether the agent forfeited the game
+	pub fn forfeited(&self) -> bool {
+		self.forfeited
+	}
+
+	/// The checked agent
+	pub fn into_inner(self) -> A {
+		self.agent
+	}
+}
+impl<A: AiAgent> AiAgent for Checked<A> {
+	fn choose_placement(&mut self, game: &Game) -> Option<Placement> {
+		self.agent.choose_placement(game)
+	}
+
+	fn choose_give(&mut self, game: &Game, placement: Option<Placement>) -> Option<Piece> {
+		self.agent.choose_give(game, placement)
+	}
+
+	fn analyze(&mut self, game: &Game, limits: &SearchLimits) -> Vec<ScoredMove> {
+		self.agent.analyze(game, limits)
+	}
+
+	fn game_over(&mut self, game: &Game) {
+		self.agent.game_over(game)
+	}
+
+	fn ponder(&mut self, game: &Game, control: &Control) {
+		self.agent.ponder(game, control)
+	}
+
+	fn play(&mut self, game: &Game) -> Result<Move, AgentError> {
+		if self.forfeited {
+			return Err(AgentError::Forfeit);
+		}
+		let mov = self.agent.play(game)?;
+		self.validate(game, mov)
+	}
+
+	fn play_controlled(&mut self, game: &Game, control: &Control) -> Result<Move, AgentError> {
+		if self.forfeited {
+			return Err(AgentError::Forfeit);
+		}
+		let mov = self.agent.play_controlled(game, control)?;
+		self.validate(game, mov)
+	}
+}
+impl<A> Checked<A> {
+	/// `mov` if it is legal in `game`
+	fn validate(&mut self, game: &Game, mov: Move) -> Result<Move, AgentError> {
+		match mov.validate(game) {
+			Ok(()) => Ok(mov),
+			Err(e) => {
+				log::warn!("Agent played the illegal move {:?}: {}", mov, e);
+				if self.forfeit {
+					self.forfeited = true;
+					Err(AgentError::Forfeit)
+				} else {
+					Err(AgentError::IllegalMove(mov, e))
+				}
+			},
+		}
+	}
+}
+
+#[test]
+fn test_checked_agent() {
+	use quart_lib::{BPos, GameError};
+
+	/// Always places on the top left cell
+	struct Stubborn;
+	impl AiAgent for Stubborn {
+		fn choose_placement(&mut self, game: &Game) -> Option<Placement> {
+			game.selected_piece.map(|piece| Placement { pos: BPos::new(0, 0), piece })
+		}
+
+		fn choose_give(&mut self, game: &Game, _placement: Option<Placement>) -> Option<Piece> {
+			Piece::all().find(|p| !game.board.contains(*p) && Some(*p) != game.selected_piece)
+		}
+	}
+
+	let mut game = Game::new();
+	let mut agent = Checked::new(Stubborn);
+	// nothing is selected in the first turn, the piece is given
+	agent.play(&game).unwrap().apply(&mut game).unwrap();
+	agent.play(&game).unwrap().apply(&mut game).unwrap();
+	match agent.play(&game) {
+		Err(AgentError::IllegalMove(_, GameError::CellOccupied)) => {},
+		other => panic!("unexpected {:?}", other),
+	}
+	assert_eq!(game.board.piece_count(), 1);
+
+	let mut agent = Checked::new(Stubborn).forfeit();
+	assert!(matches!(agent.play(&game), Err(AgentError::Forfeit)));
+	assert!(agent.forfeited());
+}
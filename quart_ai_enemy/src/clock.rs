@@ -0,0 +1,59 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// A source of time for searches with a time budget. Injectable,
+/// so tests can use a `StepClock` and stay deterministic
+pub trait Clock {
+	/// The time passed since some fixed point, e.g. the creation of the clock
+	fn elapsed(&self) -> Duration;
+}
+
+/// The real time
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+	start: Instant,
+}
+impl SystemClock {
+	/// A clock starting now
+	pub fn new() -> Self {
+		Self { start: Instant::now() }
+	}
+}
+impl Default for SystemClock {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+impl Clock for SystemClock {
+	fn elapsed(&self) -> Duration {
+		self.start.elapsed()
+	}
+}
+
+/// A fake clock which advances by `step` every time it is read
+#[derive(Debug, Clone)]
+pub struct StepClock {
+	now: Cell<Duration>,
+	step: Duration,
+}
+impl StepClock {
+	/// A clock starting at 0, advancing by `step` per reading
+	pub fn new(step: Duration) -> Self {
+		Self { now: Cell::new(Duration::from_secs(0)), step }
+	}
+}
+impl Clock for StepClock {
+	fn elapsed(&self) -> Duration {
+		let now = self.now.get();
+		self.now.set(now + self.step);
+		now
+	}
+}
+
+#[test]
+fn test_step_clock() {
+	let clock = StepClock::new(Duration::from_millis(5));
+	assert_eq!(clock.elapsed(), Duration::from_millis(0));
+	assert_eq!(clock.elapsed(), Duration::from_millis(5));
+	assert!(SystemClock::new().elapsed() < Duration::from_secs(1));
+}
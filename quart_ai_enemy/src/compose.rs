@@ -0,0 +1,94 @@
+use quart_lib::{Game, BPos, Piece};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use crate::{AiAgent, Placement};
+use crate::position::{bits, Ply, Position};
+
+/// Places pieces like one agent and gives them like another,
+/// e.g. a searching placer with a `HeuristicAgent` as giver
+pub struct Composed<P, G> {
+	placer: P,
+	giver: G,
+}
+impl<P: AiAgent, G: AiAgent> Composed<P, G> {
+	/// Let `placer` decide the placements and `giver` the pieces to give
+	pub fn new(placer: P, giver: G) -> Self {
+		Self { placer, giver }
+	}
+}
+impl<P: AiAgent, G: AiAgent> AiAgent for Composed<P, G> {
+	fn choose_placement(&mut self, game: &Game) -> Option<Placement> {
+		self.placer.choose_placement(game)
+	}
+
+	fn choose_give(&mut self, game: &Game, placement: Option<Placement>) -> Option<Piece> {
+		self.giver.choose_give(game, placement)
+	}
+}
+
+/// A cheap agent without search: places where it wins, otherwise where the most
+/// pieces stay safe to give, and gives a random piece the next player can't win with
+pub struct HeuristicAgent {
+	rng: StdRng,
+}
+impl HeuristicAgent {
+	/// Create the agent
+	pub fn new() -> Self {
+		Self { rng: StdRng::from_entropy() }
+	}
+
+	/// Seed the random number generator choosing between equally good decisions
+	pub fn seed(mut self, seed: u64) -> Self {
+		self.rng = StdRng::seed_from_u64(seed);
+		self
+	}
+}
+impl Default for HeuristicAgent {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+impl AiAgent for HeuristicAgent {
+	fn choose_placement(&mut self, game: &Game) -> Option<Placement> {
+		let position = Position::from_game(game);
+		let places: Vec<(u8, u8)> = position.legal_plies().into_iter().filter_map(|ply| ply.place).collect();
+		if let Some((cell, piece)) = places.iter().find(|(cell, piece)| position.completes_line(*cell, *piece)) {
+			return Some(Placement { pos: BPos::from_index(*cell), piece: Piece::from_index(*piece) });
+		}
+		let safe_count = |place: (u8, u8)| {
+			let next = position.play(Ply { place: Some(place), give: None });
+			(next.available() & !next.unsafe_pieces()).count_ones()
+		};
+		let most = places.iter().map(|place| safe_count(*place)).max()?;
+		let best: Vec<&(u8, u8)> = places.iter().filter(|place| safe_count(**place) == most).collect();
+		best.choose(&mut self.rng)
+			.map(|(cell, piece)| Placement { pos: BPos::from_index(*cell), piece: Piece::from_index(*piece) })
+	}
+
+	fn choose_give(&mut self, game: &Game, placement: Option<Placement>) -> Option<Piece> {
+		let position = Position::from_game(game).before_give(placement.map(Placement::indices))?;
+		let safe = position.available() & !position.unsafe_pieces();
+		let candidates = if safe != 0 { safe } else { position.available() };
+		bits(candidates).choose(&mut self.rng).map(Piece::from_index)
+	}
+}
+
+#[test]
+fn test_composed_agent() {
+	use crate::DecisionTreeAgent;
+
+	let mut game = Game::new();
+	for (x, piece) in Piece::all().filter(|p| p.big).take(3).enumerate() {
+		game.select_next_piece(piece).unwrap();
+		game.place_piece(BPos::new(x as u16, 0)).unwrap();
+	}
+	let mut agent = Composed::new(DecisionTreeAgent::with_depth(2).without_endgame(), HeuristicAgent::new().seed(1));
+	// only small pieces are safe to give
+	let give = agent.play(&game).unwrap().give.unwrap();
+	assert!(!give.big);
+
+	game.select_next_piece(Piece { big: true, dark: true, round: false, flat: false }).unwrap();
+	let mov = agent.play(&game).unwrap();
+	assert_eq!(mov.placement.map(|p| p.pos), Some(BPos::new(3, 0)));
+	assert_eq!(mov.give, None);
+}
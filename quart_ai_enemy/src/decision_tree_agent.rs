@@ -1,189 +1,237 @@
 use quart_lib::{
 	Game,
-	BPos,
 	Piece,
-	Board,
 };
-use crate::AiAgent;
-use itertools::{
-	// Itertools,
-	iproduct
-};
-
-/// An AI enemy that plans ahead
+use crate::{AgentError, AiAgent, Control, Move, Placement};
+use crate::analysis::{self, ScoredMove};
+use crate::position::{Ply, Position};
+use crate::clock::{Clock, SystemClock};
+use crate::eval::Evaluator;
+use crate::search::{ordered_plies, ParallelSearch, Search, SearchLimits, SearchResult, MAX_DEPTH};
+use crate::tablebase::{Endgame, TablebaseReader};
+use crate::transposition::TranspositionTable;
+
+/// How many plies the `DecisionTreeAgent` looks ahead by default
+pub const DEFAULT_DEPTH: u32 = 2;
+/// The size of the table pondering is remembered in, 2^20 entries
+const PONDER_TABLE_BITS: u32 = 20;
+
+/// An AI enemy that plans ahead, using a negamax search with alpha-beta pruning
+/// and iterative deepening
 pub struct DecisionTreeAgent {
-	// base: Board,
+	/// How deep and how long to search, one ply being a placement and the following give
+	limits: SearchLimits,
+	clock: Box<dyn Clock + Send>,
+	/// Searches on several threads, only used without time and node limits
+	parallel: Option<ParallelSearch>,
+	/// Plays perfectly once the end game is small enough
+	endgame: Endgame,
+	/// Scores the positions at the search horizon, `search::evaluate` if `None`
+	evaluator: Option<Box<dyn Evaluator>>,
+	/// Remembers the positions searched while pondering, created when pondering the first time.
+	/// The parallel search has a table of its own
+	table: Option<TranspositionTable>,
 }
 
 impl DecisionTreeAgent {
-	/// Create a `DecisionTreeAgent`
+	/// Create a `DecisionTreeAgent` looking `DEFAULT_DEPTH` plies ahead
 	pub fn new(_game: &Game) -> Self {
+		Self::with_depth(DEFAULT_DEPTH)
+	}
+
+	/// Create a `DecisionTreeAgent` looking `depth` plies ahead (at least 1)
+	pub fn with_depth(depth: u32) -> Self {
+		Self::with_limits(SearchLimits::depth(depth))
+	}
+
+	/// Create a `DecisionTreeAgent` searching as deep as `limits` allow per move
+	pub fn with_limits(limits: SearchLimits) -> Self {
 		Self {
-			// base: game.board.clone(),
+			limits,
+			clock: Box::new(SystemClock::new()),
+			parallel: None,
+			endgame: Endgame::new(),
+			evaluator: None,
+			table: None,
 		}
 	}
-}
-impl AiAgent for DecisionTreeAgent {
-	fn play(&mut self, game: &Game) -> (BPos, Piece) {
-		// moves the agent could do
-		let agent_moves: Vec<Move> = get_all_agent_moves(game);
-		log::trace!("Agent could put the piece at {} places", agent_moves.len());
-
-		// FIXME: We mixed up pieces here: sometimes, they are the pieces that are placed in a move (Move = place `piece` at `pos`)
-		// whereas sometimes we mean just the place (as we can't choose the piece we are given) and the piece refers to the
-		// piece we give the player.
-		//
-		if let Some(winning_move) = find_winning_move(&game.board, &agent_moves) {
-			// Place selected piece at `winning_move.pos`,
-			// the piece to select afterwards shouldn't be relevant anymore.
-			return (winning_move.pos, Piece::default());
-		}
 
-		// iterator of all moves that are safe to do without
-		// giving the player the oportunity to win
-		let mut not_losing_moves = agent_moves.iter().filter_map(|agent_move: &Move| {
-			// how the board would look after playing this move
-			let new_board: Board = apply_move(game.board.clone(), agent_move);
-
-			// what moves the player could do then
-			let new_board_moves: Vec<Move> = get_all_player_moves(&new_board);
-
-			match find_winning_move(&new_board, &new_board_moves) {
-				// if there is no winning move for the enemy after we do a move,
-				// this move is safe to do
-				None => Some(agent_move),
-				Some(_) => None,
-			}
-		});
-		// just do the first move that won't give the player the opportunity to win
-		if let Some(not_losing_move) = not_losing_moves.next() {
-			return (not_losing_move.pos, not_losing_move.piece);
-		}
+	/// Use `clock` for the time limit instead of the real time
+	pub fn clock(mut self, clock: Box<dyn Clock + Send>) -> Self {
+		self.clock = clock;
+		self
+	}
 
-		// no directly winning move, no move that can't lead to a loss
-		// => do random move and hope the player doesn't notice he can win
-		crate::rand_agent::valid_random_move(&game.board)
+	/// Search on `threads` threads. Only searches to a fixed depth (without
+	/// time and node limits) are split between threads
+	pub fn threads(mut self, threads: usize) -> Self {
+		self.parallel = if threads > 1 { Some(ParallelSearch::new(threads)) } else { None };
+		self
 	}
-}
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-struct Move {
-	pos: BPos,
-	piece: Piece,
-}
-impl Move {
-	fn new((x,y):(u16,u16), piece: Piece) -> Self {
-		Self {
-			pos: BPos::new(x,y),
-			piece
-		}
+	/// Score the positions at the search horizon with `evaluator`, instead of `search::evaluate`
+	pub fn evaluator(mut self, evaluator: Box<dyn Evaluator>) -> Self {
+		self.evaluator = Some(evaluator);
+		self
 	}
-}
 
-/// An iterator containing all 16 pieces in the game
-fn get_all_pieces() -> impl Iterator<Item=Piece> {
-	iproduct!(0..4, 0..4).map(|(a,b)| {
-        let big = a <= 1;
-        let dark = a % 2 == 0;
-        let round = b <= 1;
-        let flat = b % 2 == 0;
-        Piece {
-            big,
-            dark,
-            round,
-            flat,
-        }
-    })
-}
+	/// Look up end game positions in `tablebase`, before solving them
+	pub fn tablebase(mut self, tablebase: TablebaseReader) -> Self {
+		self.endgame.set_tablebase(tablebase);
+		self
+	}
 
-/// All moves the player could do on the given board
-fn get_all_player_moves(board: &Board) -> Vec<Move> {
-	iproduct!(0..4,0..4)
-		.filter(|(x,y)| board[(*x,*y)].is_none())
-		.flat_map(|pos| {
-			get_all_pieces()
-				.filter(|p| !board.contains(*p))
-				.map(move |piece| Move::new(pos, piece))
-		})
-		.collect()
-}
+	/// Solve end games with up to `max_empty` empty cells perfectly,
+	/// if it takes at most `node_limit` nodes
+	pub fn endgame_limits(mut self, max_empty: u32, node_limit: u64) -> Self {
+		self.endgame.set_limits(max_empty, node_limit);
+		self
+	}
 
+	/// Don't play end games perfectly, neither by solving nor with a tablebase
+	pub fn without_endgame(mut self) -> Self {
+		self.endgame = Endgame::off();
+		self
+	}
 
-/// All moves the agent could do with the given piece
-fn get_all_agent_moves(game: &Game) -> Vec<Move> {
-	assert!(game.selected_piece.is_some(), "No piece selected");
-	iproduct!(0..4,0..4)
-		.filter(|(x,y)| game.board[(*x,*y)].is_none())
-		.map(|pos| Move::new(pos, game.selected_piece.unwrap()))
-		.collect()
-}
+	/// How many plies this agent looks ahead at most
+	pub fn depth(&self) -> u32 {
+		self.limits.max_depth
+	}
 
-fn apply_move(mut board: Board, mov: &Move) -> Board {
-	assert!(board[mov.pos].is_none());
+	/// Search the best move for the player to move, together with its score
+	/// (see `search::WIN` for the meaning of the score)
+	pub fn best_move(&self, game: &Game) -> SearchResult {
+		self.search(&Position::from_game(game), None)
+	}
 
-	board[mov.pos] = Some(mov.piece);
+	/// The parallel search, if it is used with the limits
+	fn parallel(&self) -> Option<&ParallelSearch> {
+		self.parallel.as_ref().filter(|_| self.limits.time.is_none() && self.limits.nodes.is_none())
+	}
 
-	board
-}
-fn is_winning_move(board: Board, mov: &Move) -> bool {
-	apply_move(board, mov).check().is_some()
-}
-/// Find a winning move, if any
-fn find_winning_move<'m>(board: &Board, moves: &'m [Move]) -> Option<&'m Move> {
-	moves.iter().find(|mov: &&Move| is_winning_move(board.clone(), mov))
-}
-/// Partition moved into (winning moves, not winning moves)
-fn _partition_moves<'m>(board: &Board, moves: &'m [Move]) -> (Vec<Move>, Vec<Move>) {
-	moves.iter().partition(|mov: &&Move| is_winning_move(board.clone(), mov))
-}
+	/// The parallel search can't be stopped by `control`, and reports nothing
+	fn search(&self, position: &Position, control: Option<&Control>) -> SearchResult {
+		let evaluator = self.evaluator.as_deref();
+		match self.parallel() {
+			Some(parallel) => match evaluator {
+				Some(evaluator) => parallel.run_with_evaluator(position, self.limits.max_depth, evaluator),
+				None => parallel.run(position, self.limits.max_depth),
+			},
+			None => {
+				let search = match self.table {
+					Some(ref table) => Search::with_table(table),
+					None => Search::new(),
+				};
+				let search = match evaluator {
+					Some(evaluator) => search.evaluator(evaluator),
+					None => search,
+				};
+				let mut search = match control {
+					Some(control) => search.control(control),
+					None => search,
+				};
+				search.iterative(position, &self.limits, &*self.clock)
+			},
+		}
+	}
 
-#[test]
-fn test_get_all_pieces() {
-	let board = Board::full();
-	assert!(get_all_pieces().all(|p| board.contains(p)));
-	assert_eq!(get_all_pieces().count(), 16);
+	/// The best ply in `position`, from the end game if it is small enough.
+	/// `None` if there is nothing to play, e.g. the game is over already
+	fn best_ply(&mut self, position: &Position, control: Option<&Control>) -> Option<Ply> {
+		let best = self.endgame.best_ply(position, control).or_else(|| self.search(position, control).best);
+		// stopped before depth 1 was completed, any ply does
+		best.or_else(|| ordered_plies(position).first().copied())
+	}
 }
-#[test]
-fn test_apply_move() {
-	let empty_board = Board([
-		[None, None, None, None],
-		[None, None, None, None],
-		[None, None, None, None],
-		[None, None, None, None],
-	]);
-	let piece = Piece { big: true, dark: true, round: false, flat: false };
-	let board1 = Board([
-		[None, None, None, None],
-		[None, None, None, Some(piece)],
-		[None, None, None, None],
-		[None, None, None, None],
-	]);
-	let mov1 = Move::new((3,1), piece);
-	assert_eq!(apply_move(empty_board, &mov1), board1);
+impl AiAgent for DecisionTreeAgent {
+	fn choose_placement(&mut self, game: &Game) -> Option<Placement> {
+		self.best_ply(&Position::from_game(game), None).and_then(Ply::placement)
+	}
+
+	fn choose_give(&mut self, game: &Game, placement: Option<Placement>) -> Option<Piece> {
+		let position = Position::from_game(game).before_give(placement.map(Placement::indices))?;
+		self.best_ply(&position, None).and_then(Ply::given_piece)
+	}
+
+	fn play(&mut self, game: &Game) -> Result<Move, AgentError> {
+		self.best_ply(&Position::from_game(game), None).map(Ply::into_move).ok_or(AgentError::NoMove)
+	}
+
+	fn play_controlled(&mut self, game: &Game, control: &Control) -> Result<Move, AgentError> {
+		self.best_ply(&Position::from_game(game), Some(control)).map(Ply::into_move).ok_or(AgentError::NoMove)
+	}
+
+	fn analyze(&mut self, game: &Game, limits: &SearchLimits) -> Vec<ScoredMove> {
+		analysis::analyze(&Position::from_game(game), limits, &*self.clock, self.evaluator.as_deref())
+	}
+
+	/// Searches the opponent's position deeper and deeper, the positions after their
+	/// likely replies end up in the table the next move is searched with
+	fn ponder(&mut self, game: &Game, control: &Control) {
+		if game.is_over() {
+			return;
+		}
+		if self.parallel().is_none() && self.table.is_none() {
+			self.table = Some(TranspositionTable::new(PONDER_TABLE_BITS));
+		}
+		let table = match self.parallel() {
+			Some(parallel) => parallel.table(),
+			None => self.table.as_ref().expect("the table was just created"),
+		};
+		let search = Search::with_table(table).control(control);
+		let mut search = match self.evaluator.as_deref() {
+			Some(evaluator) => search.evaluator(evaluator),
+			None => search,
+		};
+		let result = search.iterative(&Position::from_game(game), &SearchLimits::depth(MAX_DEPTH), &*self.clock);
+		log::debug!("Pondered: depth {}, {} nodes", result.depth, result.nodes);
+	}
 }
+
 #[test]
-#[should_panic]
-fn test_apply_invalid_move() {
-	let piece = Piece { big: true, dark: true, round: false, flat: false };
-	let board1 = Board([
-		[None, None, None, None],
-		[None, None, None, Some(piece)],
-		[None, None, None, None],
-		[None, None, None, None],
-	]);
-	let mov1 = Move::new((3,1), piece);
-	apply_move(board1, &mov1);
+fn test_best_move_wins() {
+	use quart_lib::BPos;
+
+	let mut game = Game::new();
+	for (x, piece) in Piece::all().filter(|p| p.big).take(3).enumerate() {
+		game.select_next_piece(piece).unwrap();
+		game.place_piece(BPos::new(x as u16, 0)).unwrap();
+	}
+	game.select_next_piece(Piece { big: true, dark: true, round: false, flat: false }).unwrap();
+	let mut agent = DecisionTreeAgent::with_depth(3);
+	let result = agent.best_move(&game);
+	assert_eq!(result.score, crate::search::WIN);
+	let mov = agent.play(&game).unwrap();
+	assert_eq!(mov.placement.map(|p| p.pos), Some(BPos::new(3, 0)));
+	// the game is over, nothing is given
+	assert_eq!(mov.give, None);
+	assert!(mov.apply(&mut game).is_ok());
+	assert!(game.is_over());
+
+	// the first turn of a classic game gives a piece, and a safe one
+	let mut game = Game::new();
+	let mov = agent.play(&game).unwrap();
+	assert_eq!(mov.placement, None);
+	assert!(mov.give.is_some());
+	assert_eq!(agent.choose_give(&game, None), mov.give);
+	assert!(mov.apply(&mut game).is_ok());
 }
+
 #[test]
-fn test_is_winning_move() {
-	let p = |big,dark,round,flat| Some(Piece { big, dark, round, flat });
-	let (t,f) = (true, false);
-	let board1 = Board([
-		[None, None, None, p(t,t,t,t)],
-		[None, None, None, p(t,t,t,f)],
-		[None, None, None, p(t,t,f,t)],
-		[None, None, None, None],
-	]);
-	let move1 = Move::new((3,3), Piece { big: true, dark: false, round: false, flat: false });
-	assert!(is_winning_move(board1, &move1));
+fn test_pondering_is_reused() {
+	// the opponent is to move, 7 cells are empty
+	let position: Position = "classic bdrf,bdrF,bdRf,BDRF,BDrf,BDrF,bdRF,-,BDRf,-,bDrF,-,-,-,-,- BdRF".parse().unwrap();
+	let mut agent = DecisionTreeAgent::with_depth(3).without_endgame();
+	// unless stopped, pondering ends once the game is searched to its end
+	agent.ponder(&position.to_game(), &Control::new());
+
+	let mut game = position.to_game();
+	let reply = DecisionTreeAgent::with_depth(1).without_endgame().play(&game).unwrap();
+	reply.apply(&mut game).unwrap();
+	assert!(!game.is_over());
+	let fresh = DecisionTreeAgent::with_depth(3).without_endgame().best_move(&game);
+	let pondered = agent.best_move(&game);
+	assert!(pondered.best.is_some());
+	assert!(pondered.nodes < fresh.nodes, "{} nodes after pondering, {} without", pondered.nodes, fresh.nodes);
 }
@@ -0,0 +1,38 @@
+use quart_lib::GameError;
+use std::{error::Error, fmt, io};
+use crate::Move;
+
+/// Errors of an `AiAgent` playing a move
+#[derive(Debug)]
+pub enum AgentError {
+	/// The agent has nothing to play, e.g. as the game is over
+	NoMove,
+	/// The agent played a move the game doesn't accept
+	IllegalMove(Move, GameError),
+	/// The agent gave up the game after an illegal move, see `Checked::forfeit`
+	Forfeit,
+	/// An external engine crashed, timed out or replied nonsense, see `SubprocessAgent`
+	Engine(io::Error),
+	/// The agent was stopped before it decided, see `AgentRunner::cancel`
+	Cancelled,
+}
+impl fmt::Display for AgentError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			AgentError::NoMove              => write!(f, "The agent has nothing to play"),
+			AgentError::IllegalMove(mov, e) => write!(f, "The agent played the illegal move {:?}: {}", mov, e),
+			AgentError::Forfeit             => write!(f, "The agent forfeited the game"),
+			AgentError::Engine(e)           => write!(f, "The engine failed: {}", e),
+			AgentError::Cancelled           => write!(f, "The agent was stopped"),
+		}
+	}
+}
+impl Error for AgentError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			AgentError::IllegalMove(_, e) => Some(e),
+			AgentError::Engine(e)         => Some(e),
+			_ => None,
+		}
+	}
+}
@@ -0,0 +1,267 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use crate::position::{bits, Position};
+use crate::search::{self, WIN_THRESHOLD};
+use crate::selfplay::Dataset;
+
+/// The first line of every weights file, followed by the format version
+const HEADER: &str = "quart-eval";
+/// The version of the weights format, increased on incompatible changes
+pub const VERSION: u32 = 1;
+
+/// Scores positions the search doesn't look beyond
+pub trait Evaluator: Send + Sync {
+	/// The score of `position` for the player to move, who can't win right away.
+	/// Has to stay below `search::WIN_THRESHOLD`, which marks proven results
+	fn evaluate(&self, position: &Position) -> i32;
+}
+
+/// The hand written evaluation, see `search::evaluate`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultEvaluator;
+impl Evaluator for DefaultEvaluator {
+	fn evaluate(&self, position: &Position) -> i32 {
+		search::evaluate(position)
+	}
+}
+
+/// The names of the features of `features`, as used in weights files
+pub const FEATURES: [&str; 9] = [
+	"threats_flat",
+	"threats_round",
+	"threats_dark",
+	"threats_big",
+	"threat_lines_parity",
+	"safe_pieces",
+	"safe_pieces_parity",
+	"unsafe_pieces",
+	"parity",
+];
+
+/// Properties of `position`, see `FEATURES`: the lines threatening with each attribute,
+/// the pieces left that are safe or unsafe to give, and the parity of the empty cells
+/// (1 if even, -1 if odd), which tells who has to give when few pieces are safe
+pub fn features(position: &Position) -> [f64; 9] {
+	let threats = position.threats_by_attribute();
+	let unsafe_pieces = position.unsafe_pieces();
+	let available = position.available();
+	let safe = bits(available & !unsafe_pieces).count() as f64;
+	let parity = if position.empty_count() & 1 == 0 { 1.0 } else { -1.0 };
+	[
+		threats[0] as f64,
+		threats[1] as f64,
+		threats[2] as f64,
+		threats[3] as f64,
+		position.threat_count() as f64 * parity,
+		safe,
+		safe * parity,
+		bits(available & unsafe_pieces).count() as f64,
+		parity,
+	]
+}
+
+/// One weight per feature of `FEATURES`.
+///
+/// The file format is text: the header line `quart-eval 1`, followed by one
+/// `name weight` line per feature, like `safe_pieces 10.5`. Features which are
+/// left out keep their default weight, lines starting with `#` are comments
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weights(pub [f64; 9]);
+
+impl Weights {
+	/// Read weights
+	///
+	/// Errors:
+	/// - InvalidData: not a weights file, one of a different version, an unknown feature or a malformed line
+	pub fn read<R: BufRead>(reader: R) -> io::Result<Self> {
+		let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+		let mut lines = reader.lines();
+		let header = lines.next().unwrap_or_else(|| Ok(String::new()))?;
+		if header != format!("{} {}", HEADER, VERSION) {
+			return Err(invalid(format!("unsupported weights header {:?}", header)));
+		}
+		let mut weights = Self::default();
+		for (nr, line) in lines.enumerate() {
+			let line = line?;
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			let mut fields = line.split_whitespace();
+			let name = fields.next().unwrap_or_default();
+			let feature = FEATURES.iter().position(|f| *f == name)
+				.ok_or_else(|| invalid(format!("line {}: unknown feature {:?}", nr + 2, name)))?;
+			weights.0[feature] = fields.next().and_then(|w| w.parse().ok())
+				.ok_or_else(|| invalid(format!("line {}: no weight for {:?}", nr + 2, name)))?;
+		}
+		Ok(weights)
+	}
+
+	/// Write the weights
+	pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+		writeln!(writer, "{} {}", HEADER, VERSION)?;
+		for (name, weight) in FEATURES.iter().zip(self.0.iter()) {
+			writeln!(writer, "{} {}", name, weight)?;
+		}
+		writer.flush()
+	}
+
+	/// Read the weights at `path`
+	pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		Self::read(BufReader::new(File::open(path)?))
+	}
+
+	/// Write the weights to the file at `path`
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+		self.write(BufWriter::new(File::create(path)?))
+	}
+
+	/// The weighted sum of the features of `position`
+	pub fn score(&self, position: &Position) -> f64 {
+		features(position).iter().zip(self.0.iter()).map(|(f, w)| f * w).sum()
+	}
+}
+impl Default for Weights {
+	/// The same evaluation as `search::evaluate`
+	fn default() -> Self {
+		Weights([0.0, 0.0, 0.0, 0.0, -5.0, 10.0, 0.0, 0.0, 0.0])
+	}
+}
+
+/// An evaluation summing up weighted `features`
+#[derive(Debug, Clone, Default)]
+pub struct FeatureEvaluator {
+	/// The weight of each feature
+	pub weights: Weights,
+}
+impl FeatureEvaluator {
+	/// Evaluate with `weights`
+	pub fn new(weights: Weights) -> Self {
+		Self { weights }
+	}
+
+	/// Evaluate with the weights in the file at `path`, see `Weights`
+	pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		Weights::load(path).map(Self::new)
+	}
+}
+impl Evaluator for FeatureEvaluator {
+	fn evaluate(&self, position: &Position) -> i32 {
+		let limit = (WIN_THRESHOLD - 1) as f64;
+		self.weights.score(position).round().clamp(-limit, limit) as i32
+	}
+}
+
+/// How many points of score make winning e (2.718…) times as likely as losing
+const SCALE: f64 = 100.0;
+
+/// Fits `Weights` to the outcomes of self-play games by logistic regression: the
+/// chance of the player to move winning is modeled as `1 / (1 + e^(-score / 100))`
+#[derive(Debug, Clone, Copy)]
+pub struct Tuner {
+	/// How many passes of gradient descent over the data
+	pub epochs: u32,
+	/// The step size of gradient descent
+	pub learning_rate: f64,
+}
+impl Tuner {
+	/// A tuner doing 1000 passes
+	pub fn new() -> Self {
+		Self { epochs: 1000, learning_rate: 0.02 }
+	}
+
+	/// Weights fitting `dataset` better than `start`, each position weighted by its number of games
+	pub fn fit(&self, dataset: &Dataset, start: Weights) -> Weights {
+		let samples = training_set(dataset);
+		let games: f64 = samples.iter().map(|(_, _, games)| games).sum();
+		if games == 0.0 {
+			return start;
+		}
+		let mut scaled: Vec<f64> = start.0.iter().map(|w| w / SCALE).collect();
+		for _ in 0..self.epochs {
+			let mut gradient = [0.0; 9];
+			for (features, result, games) in samples.iter() {
+				let score: f64 = features.iter().zip(scaled.iter()).map(|(f, w)| f * w).sum();
+				let error = (sigmoid(score) - result) * games;
+				for (g, f) in gradient.iter_mut().zip(features.iter()) {
+					*g += error * f;
+				}
+			}
+			for (w, g) in scaled.iter_mut().zip(gradient.iter()) {
+				*w -= self.learning_rate * g / games;
+			}
+		}
+		let mut weights = Weights([0.0; 9]);
+		for (w, s) in weights.0.iter_mut().zip(scaled) {
+			*w = s * SCALE;
+		}
+		weights
+	}
+
+	/// The mean cross entropy between the outcomes in `dataset` and the predictions
+	/// of `weights`, lower is better
+	pub fn loss(&self, dataset: &Dataset, weights: &Weights) -> f64 {
+		let samples = training_set(dataset);
+		let games: f64 = samples.iter().map(|(_, _, games)| games).sum();
+		let total: f64 = samples.iter()
+			.map(|(features, result, games)| {
+				let score: f64 = features.iter().zip(weights.0.iter()).map(|(f, w)| f * w).sum();
+				let p = sigmoid(score / SCALE).clamp(1e-12, 1.0 - 1e-12);
+				-(result * p.ln() + (1.0 - result) * (1.0 - p).ln()) * games
+			})
+			.sum();
+		total / games.max(1.0)
+	}
+}
+impl Default for Tuner {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+fn sigmoid(x: f64) -> f64 {
+	1.0 / (1.0 + (-x).exp())
+}
+
+/// Features, outcome and number of games of every position in `dataset`
+fn training_set(dataset: &Dataset) -> Vec<([f64; 9], f64, f64)> {
+	dataset.iter()
+		.map(|(position, sample)| (features(&position), sample.result(), sample.games() as f64))
+		.collect()
+}
+
+#[test]
+fn test_default_weights_match_evaluate() {
+	use crate::position::Ply;
+
+	let position = Position::new(false)
+		.play(Ply { place: None, give: Some(8) })
+		.play(Ply { place: Some((0, 8)), give: Some(9) })
+		.play(Ply { place: Some((1, 9)), give: Some(10) })
+		.play(Ply { place: Some((2, 10)), give: Some(1) });
+	let evaluator = FeatureEvaluator::default();
+	assert_eq!(evaluator.evaluate(&position), search::evaluate(&position));
+	assert_eq!(position.threats_by_attribute(), [0, 0, 1, 1]);
+
+	let mut file = Vec::new();
+	Weights([1.5; 9]).write(&mut file).unwrap();
+	assert_eq!(Weights::read(io::Cursor::new(&file)).unwrap(), Weights([1.5; 9]));
+	let partial = Weights::read(io::Cursor::new("quart-eval 1\n# tuned\nparity 2\n")).unwrap();
+	assert_eq!(partial.0[8], 2.0);
+	assert_eq!(partial.0[5], Weights::default().0[5]);
+	assert!(Weights::read(io::Cursor::new("quart-eval 1\nluck 3\n")).is_err());
+}
+
+#[test]
+fn test_tuner_reduces_loss() {
+	use crate::selfplay::SelfPlay;
+	use crate::AgentConfig;
+
+	let agents = [AgentConfig::by_name("random").unwrap(), AgentConfig::by_name("greedy").unwrap()];
+	let dataset = SelfPlay { seed: 3, score_depth: 1, ..SelfPlay::new(agents, 6) }.run();
+	let tuner = Tuner { epochs: 200, ..Tuner::new() };
+	let start = Weights::default();
+	let tuned = tuner.fit(&dataset, start);
+	assert!(tuner.loss(&dataset, &tuned) < tuner.loss(&dataset, &start));
+}
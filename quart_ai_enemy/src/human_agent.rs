@@ -0,0 +1,144 @@
+use quart_lib::{Game, Piece};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use crate::{AgentError, AiAgent, Control, Move, Placement};
+use crate::position::{Ply, Position};
+use crate::search::{evaluate, opponent_wins_next, Search};
+
+/// An agent making human mistakes: it picks plies at random, preferring better ones
+/// (a softmax over their search scores), and now and then overlooks a line it could
+/// complete or doesn't check whether the piece it gives completes one for the opponent
+pub struct HumanLikeAgent {
+	depth: u32,
+	temperature: f64,
+	miss_threats: f64,
+	unsafe_gives: f64,
+	rng: StdRng,
+}
+impl HumanLikeAgent {
+	/// An agent searching 2 plies deep, with a temperature of 50 and no blunders
+	pub fn new() -> Self {
+		Self {
+			depth: 2,
+			temperature: 50.0,
+			miss_threats: 0.0,
+			unsafe_gives: 0.0,
+			rng: StdRng::from_entropy(),
+		}
+	}
+
+	/// Score plies searching `depth` plies deep (at least 1)
+	pub fn with_depth(mut self, depth: u32) -> Self {
+		self.depth = depth.max(1);
+		self
+	}
+
+	/// How random the choice is: a ply scoring `temperature` points less than another
+	/// is chosen e (2.718…) times less often. 0 always chooses the best ply
+	pub fn temperature(mut self, temperature: f64) -> Self {
+		self.temperature = temperature.max(0.0);
+		self
+	}
+
+	/// With probability `p` per turn, a line the agent could complete goes unnoticed
+	pub fn miss_threats(mut self, p: f64) -> Self {
+		self.miss_threats = p.clamp(0.0, 1.0);
+		self
+	}
+
+	/// With probability `p` per turn, the agent doesn't check whether the piece it gives
+	/// lets the opponent complete a line
+	pub fn unsafe_gives(mut self, p: f64) -> Self {
+		self.unsafe_gives = p.clamp(0.0, 1.0);
+		self
+	}
+
+	/// Seed the random number generator
+	pub fn seed(mut self, seed: u64) -> Self {
+		self.rng = StdRng::seed_from_u64(seed);
+		self
+	}
+
+	/// A ply for `position`, `None` if there is nothing to play. When `control` stops
+	/// the search, the plies not searched yet are judged by less than the full depth
+	fn choose_ply(&mut self, position: &Position, control: Option<&Control>) -> Option<Ply> {
+		let search = Search::new();
+		let mut search = match control {
+			Some(control) => search.control(control),
+			None => search,
+		};
+		let mut scored = search.analyze(position, self.depth);
+		let misses_threats = self.rng.gen_bool(self.miss_threats);
+		let careless = self.rng.gen_bool(self.unsafe_gives);
+		for (ply, score) in scored.iter_mut() {
+			let winning = position.is_winning(*ply);
+			let overlooked = match winning {
+				true => misses_threats,
+				false => careless && opponent_wins_next(&position.play(*ply)),
+			};
+			if overlooked {
+				// judged like any other ply, by the position alone
+				*score = -evaluate(&position.play(*ply));
+			}
+		}
+		let best = scored.iter().map(|(_, score)| *score).max()?;
+		if self.temperature == 0.0 {
+			return scored.iter().find(|(_, score)| *score == best).map(|(ply, _)| *ply);
+		}
+		let temperature = self.temperature;
+		scored.choose_weighted(&mut self.rng, |(_, score)| ((*score - best) as f64 / temperature).exp())
+			.ok()
+			.map(|(ply, _)| *ply)
+	}
+}
+impl Default for HumanLikeAgent {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+impl AiAgent for HumanLikeAgent {
+	fn choose_placement(&mut self, game: &Game) -> Option<Placement> {
+		self.choose_ply(&Position::from_game(game), None).and_then(Ply::placement)
+	}
+
+	fn choose_give(&mut self, game: &Game, placement: Option<Placement>) -> Option<Piece> {
+		let position = Position::from_game(game).before_give(placement.map(Placement::indices))?;
+		self.choose_ply(&position, None).and_then(Ply::given_piece)
+	}
+
+	fn play(&mut self, game: &Game) -> Result<Move, AgentError> {
+		self.choose_ply(&Position::from_game(game), None).map(Ply::into_move).ok_or(AgentError::NoMove)
+	}
+
+	fn play_controlled(&mut self, game: &Game, control: &Control) -> Result<Move, AgentError> {
+		self.choose_ply(&Position::from_game(game), Some(control)).map(Ply::into_move).ok_or(AgentError::NoMove)
+	}
+}
+
+#[test]
+fn test_human_like_agent() {
+	use quart_lib::BPos;
+
+	let mut game = Game::new();
+	for (x, piece) in Piece::all().filter(|p| p.big).take(3).enumerate() {
+		game.select_next_piece(piece).unwrap();
+		game.place_piece(BPos::new(x as u16, 0)).unwrap();
+	}
+	let position = Position::from_game(&game);
+	let safe = |agent: &mut HumanLikeAgent| {
+		let ply = agent.choose_ply(&position, None).unwrap();
+		!opponent_wins_next(&position.play(ply))
+	};
+	// without blunders, a safe piece is given
+	let mut careful = HumanLikeAgent::new().temperature(200.0).seed(1);
+	assert!((0..20).all(|_| safe(&mut careful)));
+	let mut careless = HumanLikeAgent::new().temperature(200.0).unsafe_gives(1.0).seed(1);
+	assert!(!(0..20).all(|_| safe(&mut careless)));
+
+	game.select_next_piece(Piece { big: true, dark: true, round: false, flat: false }).unwrap();
+	let wins = |agent: &mut HumanLikeAgent| agent.play(&game).unwrap().placement.map(|p| p.pos) == Some(BPos::new(3, 0));
+	let mut agent = HumanLikeAgent::new().seed(2);
+	assert!((0..20).all(|_| wins(&mut agent)));
+	let mut blind = HumanLikeAgent::new().miss_threats(1.0).seed(2);
+	assert!(!(0..20).all(|_| wins(&mut blind)));
+}
@@ -1,17 +1,269 @@
 mod rand_agent;
 mod decision_tree_agent;
+mod mcts_agent;
+mod book;
+mod compose;
+mod adaptive;
+mod human_agent;
+mod checked;
+mod subprocess;
+mod runner;
+mod error;
+/// Named agents and difficulty levels
+pub mod registry;
+/// Compact game positions for searching
+pub mod position;
+/// Game tree search
+pub mod search;
+/// Time sources for time-bounded searches
+pub mod clock;
+/// Transposition tables shared between search threads
+pub mod transposition;
+/// Exact solver for end games
+pub mod solver;
+/// Precomputed end game outcomes on disk
+pub mod tablebase;
+/// Training data from games of agents against each other
+pub mod selfplay;
+/// Tunable evaluation of positions
+pub mod eval;
+/// Scores and continuations of all moves, for hints and reviews
+pub mod analysis;
+/// The text protocol of external engines, see `SubprocessAgent`
+pub mod protocol;
 
-use quart_lib::{Game, BPos, Piece};
+pub use self::rand_agent::RandAgent;
+pub use self::decision_tree_agent::DecisionTreeAgent;
+pub use self::mcts_agent::{Budget, MctsAgent, Rollout};
+pub use self::book::{Book, BookAgent, BookBuilder};
+pub use self::compose::{Composed, HeuristicAgent};
+pub use self::human_agent::HumanLikeAgent;
+pub use self::adaptive::{AdaptiveAgent, Skill, Skills};
+pub use self::checked::Checked;
+pub use self::subprocess::SubprocessAgent;
+pub use self::runner::{AgentRunner, Control, Progress};
+pub use self::error::AgentError;
+pub use self::registry::{AgentConfig, AgentSetup, Difficulty};
 
-/// Some kind of AI agent the player can play against
+use quart_lib::{Game, GameError, BPos, Piece, TurnStructure};
+use std::fmt;
+use std::str::FromStr;
+use self::analysis::ScoredMove;
+use self::clock::SystemClock;
+use self::position::Position;
+use self::search::SearchLimits;
+
+/// Where to place which piece
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+	/// The cell to place the piece on
+	pub pos: BPos,
+	/// The piece to place: the selected one, or with `TurnStructure::FreeChoice`
+	/// the one the agent chose
+	pub piece: Piece,
+}
+impl Placement {
+	/// Cell and piece index, as in `position::Ply::place`
+	pub fn indices(self) -> (u8, u8) {
+		(self.pos.index(), self.piece.index())
+	}
+}
+
+/// A whole turn: place a piece, then give one to the next player
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Move {
+	/// What to place, `None` on the first turn of a classic game, when nothing is selected yet
+	pub placement: Option<Placement>,
+	/// The piece to give, `None` with `TurnStructure::FreeChoice` and if the game
+	/// ends with the placement (no line left open or no piece left)
+	pub give: Option<Piece>,
+}
+impl Move {
+	/// Check whether the move can be played in `game`, without changing it
+	///
+	/// Errors:
+	/// - GameIsOver: the game was over already
+	/// - NoPieceSelected: nothing is placed although something has to be,
+	///   or nothing is given although the game goes on
+	/// - PieceAlreadySelected: only a piece is given, but one is selected already
+	/// - CellOccupied: the piece is placed on an occupied cell
+	/// - PieceInUse: the placed or given piece is on the board already
+	pub fn validate(self, game: &Game) -> Result<(), GameError> {
+		if game.is_over() {
+			return Err(GameError::GameIsOver);
+		}
+		let free_choice = game.turn_structure == TurnStructure::FreeChoice;
+		let (placement, piece) = match self.placement {
+			_ if free_choice && game.selected_piece.is_some() => return Err(GameError::PieceAlreadySelected),
+			None if free_choice => return Err(GameError::NoPieceSelected),
+			// the first turn of a classic game, only a piece is given
+			None => {
+				if game.selected_piece.is_some() {
+					return Err(GameError::PieceAlreadySelected);
+				}
+				let give = self.give.ok_or(GameError::NoPieceSelected)?;
+				return if game.board.contains(give) { Err(GameError::PieceInUse) } else { Ok(()) };
+			},
+			Some(placement) if free_choice => (placement, placement.piece),
+			Some(placement) => (placement, game.selected_piece.ok_or(GameError::NoPieceSelected)?),
+		};
+		if game.board[placement.pos].is_some() {
+			return Err(GameError::CellOccupied);
+		}
+		if game.board.contains(piece) {
+			return Err(GameError::PieceInUse);
+		}
+		if free_choice {
+			return Ok(());
+		}
+		let mut board = game.board.clone();
+		board[placement.pos] = Some(piece);
+		let ends_game = board.check().is_some() || board.piece_count() == 16;
+		match self.give {
+			_ if ends_game => Ok(()),
+			None => Err(GameError::NoPieceSelected),
+			Some(give) if board.contains(give) => Err(GameError::PieceInUse),
+			Some(_) => Ok(()),
+		}
+	}
+
+	/// Play the move in `game`. Nothing is changed on error, see `validate` for the errors
+	pub fn apply(self, game: &mut Game) -> Result<(), GameError> {
+		self.validate(game)?;
+		match self.placement {
+			Some(placement) if game.turn_structure == TurnStructure::FreeChoice => {
+				game.select_next_piece(placement.piece)?;
+				game.place_piece(placement.pos)
+			},
+			Some(placement) => {
+				let transaction = game.probe_place_piece(placement.pos)?;
+				match self.give {
+					Some(give) => transaction.run_and_select(game, give),
+					None => {
+						transaction.run(game);
+						Ok(())
+					},
+				}
+			},
+			None => game.select_next_piece(self.give.ok_or(GameError::NoPieceSelected)?),
+		}
+	}
+}
+
+impl fmt::Display for Move {
+	/// The placed piece at its cell, then the given piece after a slash, like `BDRf@B3/bDrF`.
+	/// Missing parts are left out: `/bDrF` only gives, `BDRf@B3` only places
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if let Some(placement) = self.placement {
+			write!(f, "{}@{}", placement.piece, placement.pos)?;
+		}
+		if let Some(give) = self.give {
+			write!(f, "/{}", give)?;
+		}
+		Ok(())
+	}
+}
+impl FromStr for Move {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (place, give) = match s.find('/') {
+			Some(idx) => (&s[..idx], Some(&s[idx + 1..])),
+			None => (s, None),
+		};
+		let placement = match place {
+			"" => None,
+			_ => {
+				let idx = place.find('@').ok_or_else(|| format!("no cell in move {:?}", s))?;
+				Some(Placement { piece: place[..idx].parse()?, pos: place[idx + 1..].parse()? })
+			},
+		};
+		let give = give.map(str::parse).transpose()?;
+		if placement.is_none() && give.is_none() {
+			return Err(format!("empty move {:?}", s));
+		}
+		Ok(Move { placement, give })
+	}
+}
+
+/// Some kind of AI agent the player can play against.
+///
+/// A turn consists of two decisions, placing a piece and giving one to the next
+/// player, so agents good at one can be combined with others (see `Composed`)
 pub trait AiAgent {
-	/// Given the current game state, here the AI decides what to do.
-	/// First it outputs a `BPos` which indicates where the piece shall be placed,
-	/// Second it outputs a next piece for it's opponent to place
-	fn play(&mut self, game: &Game) -> (BPos, Piece);
+	/// Where to place which piece: the selected one, or with `TurnStructure::FreeChoice`
+	/// one the agent chooses. `None` if there is nothing to place, on the first turn
+	/// of a classic game
+	fn choose_placement(&mut self, game: &Game) -> Option<Placement>;
+
+	/// The piece to give to the next player, after `placement` in `game`.
+	/// `None` if nothing is given, see `Move::give`
+	fn choose_give(&mut self, game: &Game, placement: Option<Placement>) -> Option<Piece>;
+
+	/// Decide the whole turn in `game`. The move isn't validated, see `Checked` for that
+	///
+	/// Errors:
+	/// - NoMove: there is nothing to play, e.g. as the game is over
+	fn play(&mut self, game: &Game) -> Result<Move, AgentError> {
+		if game.is_over() {
+			return Err(AgentError::NoMove);
+		}
+		let placement = self.choose_placement(game);
+		let give = self.choose_give(game, placement);
+		Ok(Move { placement, give })
+	}
+
+	/// Like `play`, but stops early when `control` says so and reports the progress of
+	/// the search to it, see `AgentRunner`. By default, `play` runs to its end
+	fn play_controlled(&mut self, game: &Game, _control: &Control) -> Result<Move, AgentError> {
+		self.play(game)
+	}
+
+	/// Think ahead while the opponent decides what to play in `game`, until `control`
+	/// says to stop. The work is kept and reused for the next move, see `AgentRunner::ponder`.
+	/// By default, agents don't ponder
+	fn ponder(&mut self, _game: &Game, _control: &Control) {}
+
+	/// Every legal move in `game` with its score and expected continuation, best first,
+	/// searched within `limits`. See `analysis::analyze`
+	fn analyze(&mut self, game: &Game, limits: &SearchLimits) -> Vec<ScoredMove> {
+		analysis::analyze(&Position::from_game(game), limits, &SystemClock::new(), None)
+	}
+
+	/// Called once when `game` is over, so the agent can learn from it. Does nothing by default
+	fn game_over(&mut self, _game: &Game) {}
+}
+impl<A: AiAgent + ?Sized> AiAgent for Box<A> {
+	fn choose_placement(&mut self, game: &Game) -> Option<Placement> {
+		(**self).choose_placement(game)
+	}
+
+	fn choose_give(&mut self, game: &Game, placement: Option<Placement>) -> Option<Piece> {
+		(**self).choose_give(game, placement)
+	}
+
+	fn play(&mut self, game: &Game) -> Result<Move, AgentError> {
+		(**self).play(game)
+	}
+
+	fn play_controlled(&mut self, game: &Game, control: &Control) -> Result<Move, AgentError> {
+		(**self).play_controlled(game, control)
+	}
+
+	fn ponder(&mut self, game: &Game, control: &Control) {
+		(**self).ponder(game, control)
+	}
+
+	fn analyze(&mut self, game: &Game, limits: &SearchLimits) -> Vec<ScoredMove> {
+		(**self).analyze(game, limits)
+	}
+
+	fn game_over(&mut self, game: &Game) {
+		(**self).game_over(game)
+	}
 }
 
+/// The default agent, see `registry` to choose another one
 pub fn get_ai_agent(game: &Game) -> Box<dyn AiAgent> {
-	// Box::new(self::rand_agent::RandAgent::new(game))
-	Box::new(self::decision_tree_agent::DecisionTreeAgent::new(game))
+	registry::AgentConfig::default().create(game)
 }
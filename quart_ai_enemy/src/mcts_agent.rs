@@ -0,0 +1,495 @@
+use quart_lib::{Game, Piece};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::time::Duration;
+use crate::{AgentError, AiAgent, Control, Move, Placement, Progress};
+use crate::clock::{Clock, SystemClock};
+use crate::position::{bits, Ply, Position};
+use crate::search::opponent_wins_next;
+use crate::tablebase::{Endgame, TablebaseReader};
+
+/// How many iterations the `MctsAgent` runs per move by default
+pub const DEFAULT_ITERATIONS: u32 = 5000;
+/// The exploration constant of the UCT formula
+const EXPLORATION: f64 = 1.4;
+/// How many random placements a heuristic rollout tries to find one without a losing give
+const HEURISTIC_TRIES: u32 = 8;
+/// How many nodes the tree may grow to while pondering
+const PONDER_NODES: usize = 200_000;
+/// How many iterations pass between two progress reports
+const PROGRESS_INTERVAL: u32 = 1000;
+
+/// How long the `MctsAgent` may think about a move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+	/// Run a fixed number of iterations
+	Iterations(u32),
+	/// Run for (about) the given time
+	Time(Duration),
+}
+
+/// How the `MctsAgent` plays out positions to their end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rollout {
+	/// Both players play completely random moves
+	Random,
+	/// Both players take immediate wins and try not to give a winning piece away
+	Heuristic,
+}
+
+/// A node of the search tree
+#[derive(Debug)]
+struct Node {
+	position: Position,
+	parent: Option<usize>,
+	children: Vec<usize>,
+	/// The ply that lead to this node
+	ply: Option<Ply>,
+	/// The plies not expanded yet, `None` until this node is expanded the first time
+	untried: Option<Vec<Ply>>,
+	visits: u32,
+	/// Sum of the rewards for the player who played `ply`
+	reward: f64,
+	/// The reward for the player who played `ply`, if the game ended with it
+	terminal: Option<f64>,
+}
+impl Node {
+	fn new(position: Position, parent: Option<usize>, ply: Option<Ply>, terminal: Option<f64>) -> Self {
+		Self { position, parent, children: Vec::new(), ply, untried: None, visits: 0, reward: 0.0, terminal }
+	}
+}
+
+/// An AI enemy using Monte Carlo Tree Search with UCT selection.
+/// The tree is kept between moves, as long as the opponent's reply is in it,
+/// and grows further while pondering
+pub struct MctsAgent {
+	budget: Budget,
+	clock: Box<dyn Clock + Send>,
+	rollout: Rollout,
+	rng: StdRng,
+	/// The search tree, the root is always the first node
+	nodes: Vec<Node>,
+	/// Plays perfectly once the end game is small enough
+	endgame: Endgame,
+}
+
+impl MctsAgent {
+	/// Create a `MctsAgent` running `DEFAULT_ITERATIONS` heuristic iterations per move
+	pub fn new(_game: &Game) -> Self {
+		Self::with_budget(Budget::Iterations(DEFAULT_ITERATIONS))
+	}
+
+	/// Create a `MctsAgent` with the given budget per move
+	pub fn with_budget(budget: Budget) -> Self {
+		Self {
+			budget,
+			clock: Box::new(SystemClock::new()),
+			rollout: Rollout::Heuristic,
+			rng: StdRng::from_entropy(),
+			nodes: Vec::new(),
+			endgame: Endgame::new(),
+		}
+	}
+
+	/// Use `clock` for `Budget::Time` instead of the real time
+	pub fn clock(mut self, clock: Box<dyn Clock + Send>) -> Self {
+		self.clock = clock;
+		self
+	}
+
+	/// Use `rollout` to play out positions
+	pub fn rollout(mut self, rollout: Rollout) -> Self {
+		self.rollout = rollout;
+		self
+	}
+
+	/// Seed the random number generator, to get reproducible games
+	pub fn seed(mut self, seed: u64) -> Self {
+		self.rng = StdRng::seed_from_u64(seed);
+		self
+	}
+
+	/// Look up end game positions in `tablebase`, before solving them
+	pub fn tablebase(mut self, tablebase: TablebaseReader) -> Self {
+		self.endgame.set_tablebase(tablebase);
+		self
+	}
+
+	/// Solve end games with up to `max_empty` empty cells perfectly,
+	/// if it takes at most `node_limit` nodes
+	pub fn endgame_limits(mut self, max_empty: u32, node_limit: u64) -> Self {
+		self.endgame.set_limits(max_empty, node_limit);
+		self
+	}
+
+	/// Don't play end games perfectly, neither by solving nor with a tablebase
+	pub fn without_endgame(mut self) -> Self {
+		self.endgame = Endgame::off();
+		self
+	}
+
+	/// Search the best ply in `position`, `None` if there is nothing to play
+	pub fn search(&mut self, position: &Position) -> Option<Ply> {
+		self.run(position, None)
+	}
+
+	/// Search the best ply in `position` until the budget is used up or `control` stops
+	/// the search, reporting the progress to it every `PROGRESS_INTERVAL` iterations
+	fn run(&mut self, position: &Position, control: Option<&Control>) -> Option<Ply> {
+		let plies = position.legal_plies();
+		self.set_root(position);
+		if let Some(winning) = plies.iter().find(|ply| position.is_winning(**ply)) {
+			return Some(*winning);
+		}
+
+		let start = self.clock.elapsed();
+		let mut iterations = 0;
+		loop {
+			match self.budget {
+				Budget::Iterations(max) if iterations >= max => break,
+				Budget::Time(max) if self.clock.elapsed() >= start + max => break,
+				_ => {},
+			}
+			if let Some(control) = control {
+				if control.is_stopped() {
+					break;
+				}
+				if iterations > 0 && iterations % PROGRESS_INTERVAL == 0 {
+					control.report(self.progress(iterations));
+				}
+			}
+			self.iterate();
+			iterations += 1;
+		}
+		log::debug!("MCTS: {} iterations, {} nodes", iterations, self.nodes.len());
+
+		// stopped before anything was searched, any ply does
+		self.best_child(0).and_then(|child| self.nodes[child].ply).or_else(|| plies.first().copied())
+	}
+
+	/// The most visited child of `idx`
+	fn best_child(&self, idx: usize) -> Option<usize> {
+		self.nodes[idx].children.iter().copied().max_by_key(|child| self.nodes[*child].visits)
+	}
+
+	/// The progress after `iterations`: the length of the most visited line as depth,
+	/// and the win rate of the most visited ply as score, scaled to +-1000
+	fn progress(&self, iterations: u32) -> Progress {
+		let best = self.best_child(0);
+		let mut depth = 0;
+		let mut current = best;
+		while let Some(idx) = current {
+			depth += 1;
+			current = self.best_child(idx);
+		}
+		let score = best.map_or(0, |idx| {
+			let node = &self.nodes[idx];
+			let rate = node.reward / node.visits.max(1) as f64;
+			((rate * 2.0 - 1.0) * 1000.0) as i32
+		});
+		Progress::Info {
+			depth,
+			score,
+			nodes: iterations as u64,
+			best: best.and_then(|idx| self.nodes[idx].ply).map(Ply::into_move),
+		}
+	}
+
+	/// The best ply in `position`, from the end game if it is small enough.
+	/// `None` if there is nothing to play, e.g. the game is over already
+	fn best_ply(&mut self, position: &Position, control: Option<&Control>) -> Option<Ply> {
+		match self.endgame.best_ply(position, control) {
+			Some(ply) => Some(ply),
+			None => self.run(position, control),
+		}
+	}
+
+	/// The plies searched in the last position, with how often they were visited
+	pub fn root_visits(&self) -> Vec<(Ply, u32)> {
+		match self.nodes.first() {
+			Some(root) => root.children.iter()
+				.filter_map(|child| Some((self.nodes[*child].ply?, self.nodes[*child].visits)))
+				.collect(),
+			None => Vec::new(),
+		}
+	}
+
+	/// Make `position` the root, reusing the subtree of a previous search if possible
+	fn set_root(&mut self, position: &Position) {
+		// the position is the root itself, our ply away (when pondering),
+		// or at most the opponent's reply to our ply away
+		let mut found = None;
+		if let Some(root) = self.nodes.first() {
+			if root.position == *position {
+				found = Some(0);
+			}
+			for child in root.children.iter() {
+				if found.is_none() && self.nodes[*child].position == *position {
+					found = Some(*child);
+				}
+				for grandchild in self.nodes[*child].children.iter() {
+					if found.is_none() && self.nodes[*grandchild].position == *position {
+						found = Some(*grandchild);
+					}
+				}
+			}
+		}
+		match found {
+			Some(0) => {},
+			Some(new_root) => self.reroot(new_root),
+			None => {
+				self.nodes.clear();
+				self.nodes.push(Node::new(*position, None, None, None));
+			},
+		}
+	}
+
+	/// Throw away everything but the subtree below `new_root`, which becomes the root
+	fn reroot(&mut self, new_root: usize) {
+		let mut old = std::mem::take(&mut self.nodes);
+		let mut queue = vec![(new_root, None)];
+		while let Some((idx, parent)) = queue.pop() {
+			let new_idx = self.nodes.len();
+			let mut node = std::mem::replace(&mut old[idx], Node::new(Position::new(false), None, None, None));
+			queue.extend(node.children.drain(..).map(|child| (child, Some(new_idx))));
+			node.parent = parent;
+			self.nodes.push(node);
+			if let Some(parent) = parent {
+				self.nodes[parent].children.push(new_idx);
+			}
+		}
+	}
+
+	/// One iteration: selection, expansion, simulation and backpropagation
+	fn iterate(&mut self) {
+		// selection
+		let mut idx = 0;
+		loop {
+			let node = &self.nodes[idx];
+			if node.terminal.is_some() {
+				break;
+			}
+			match node.untried {
+				Some(ref untried) if untried.is_empty() && !node.children.is_empty() => {
+					idx = self.select_child(idx);
+				},
+				_ => break,
+			}
+		}
+
+		// expansion
+		if self.nodes[idx].terminal.is_none() {
+			if self.nodes[idx].untried.is_none() {
+				let mut plies = self.nodes[idx].position.legal_plies();
+				plies.shuffle(&mut self.rng);
+				self.nodes[idx].untried = Some(plies);
+			}
+			let ply = self.nodes[idx].untried.as_mut().and_then(|untried| untried.pop());
+			if let Some(ply) = ply {
+				idx = self.expand(idx, ply);
+			}
+		}
+
+		// simulation, from the point of view of the player who moved into the node
+		let node = &self.nodes[idx];
+		let mut reward = match node.terminal {
+			Some(reward) => reward,
+			None => 1.0 - rollout(&node.position, self.rollout, &mut self.rng),
+		};
+
+		// backpropagation
+		let mut current = Some(idx);
+		while let Some(idx) = current {
+			let node = &mut self.nodes[idx];
+			node.visits += 1;
+			node.reward += reward;
+			reward = 1.0 - reward;
+			current = node.parent;
+		}
+	}
+
+	/// The child of `idx` with the highest upper confidence bound
+	fn select_child(&self, idx: usize) -> usize {
+		let parent = &self.nodes[idx];
+		let log_visits = (parent.visits.max(1) as f64).ln();
+		let uct = |child: usize| {
+			let node = &self.nodes[child];
+			let visits = node.visits.max(1) as f64;
+			node.reward / visits + EXPLORATION * (log_visits / visits).sqrt()
+		};
+		let mut best = (parent.children[0], f64::NEG_INFINITY);
+		for child in parent.children.iter() {
+			let value = uct(*child);
+			if value > best.1 {
+				best = (*child, value);
+			}
+		}
+		best.0
+	}
+
+	/// Add the child reached by playing `ply` below `idx`
+	fn expand(&mut self, idx: usize, ply: Ply) -> usize {
+		let position = self.nodes[idx].position;
+		let child = position.play(ply);
+		let terminal = if position.is_winning(ply) {
+			Some(1.0)
+		} else if child.is_full() {
+			Some(0.5)
+		} else {
+			None
+		};
+		let child_idx = self.nodes.len();
+		self.nodes.push(Node::new(child, Some(idx), Some(ply), terminal));
+		self.nodes[idx].children.push(child_idx);
+		child_idx
+	}
+}
+impl AiAgent for MctsAgent {
+	fn choose_placement(&mut self, game: &Game) -> Option<Placement> {
+		self.best_ply(&Position::from_game(game), None).and_then(Ply::placement)
+	}
+
+	fn choose_give(&mut self, game: &Game, placement: Option<Placement>) -> Option<Piece> {
+		let position = Position::from_game(game).before_give(placement.map(Placement::indices))?;
+		self.best_ply(&position, None).and_then(Ply::given_piece)
+	}
+
+	fn play(&mut self, game: &Game) -> Result<Move, AgentError> {
+		self.best_ply(&Position::from_game(game), None).map(Ply::into_move).ok_or(AgentError::NoMove)
+	}
+
+	fn play_controlled(&mut self, game: &Game, control: &Control) -> Result<Move, AgentError> {
+		self.best_ply(&Position::from_game(game), Some(control)).map(Ply::into_move).ok_or(AgentError::NoMove)
+	}
+
+	/// Grows the tree below the opponent's position, the subtree of their reply is kept
+	fn ponder(&mut self, game: &Game, control: &Control) {
+		if game.is_over() {
+			return;
+		}
+		self.set_root(&Position::from_game(game));
+		let mut iterations = 0;
+		while !control.is_stopped() && self.nodes.len() < PONDER_NODES {
+			self.iterate();
+			iterations += 1;
+		}
+		log::debug!("MCTS pondered: {} iterations, {} nodes", iterations, self.nodes.len());
+	}
+}
+
+/// A random element of the non-empty bitmask `mask`
+fn random_bit(mask: u16, rng: &mut StdRng) -> u8 {
+	let n = rng.gen_range(0, mask.count_ones() as usize);
+	bits(mask).nth(n).expect("mask is not empty")
+}
+
+/// Play `position` out to its end, the reward for the player to move:
+/// 1 for a win, 0.5 for a draw and 0 for a loss
+fn rollout(position: &Position, rollout: Rollout, rng: &mut StdRng) -> f64 {
+	let mut position = *position;
+	let mut reward = 1.0;
+	loop {
+		if position.is_full() {
+			return 0.5;
+		}
+		let ply = match rollout {
+			Rollout::Random => random_ply(&position, rng),
+			Rollout::Heuristic => heuristic_ply(&position, rng),
+		};
+		if position.is_winning(ply) {
+			return reward;
+		}
+		position = position.play(ply);
+		reward = 1.0 - reward;
+	}
+}
+
+/// A random ply in the non-full `position`
+fn random_ply(position: &Position, rng: &mut StdRng) -> Ply {
+	let piece = match position.in_hand() {
+		Some(piece) => piece,
+		None if position.free_choice() => random_bit(position.available(), rng),
+		None => return Ply { place: None, give: Some(random_bit(position.available(), rng)) },
+	};
+	let cell = random_bit(position.empty_cells(), rng);
+	let available = position.available() & !(1 << piece);
+	let give = if position.free_choice() || available == 0 {
+		None
+	} else {
+		Some(random_bit(available, rng))
+	};
+	Ply { place: Some((cell, piece)), give }
+}
+
+/// A random ply in the non-full `position`, but winning if possible,
+/// and preferring plies after which the opponent can't win right away
+fn heuristic_ply(position: &Position, rng: &mut StdRng) -> Ply {
+	let pieces = match position.in_hand() {
+		Some(piece) => 1 << piece,
+		None if position.free_choice() => position.available(),
+		None => 0,
+	};
+	for piece in bits(pieces) {
+		for cell in bits(position.empty_cells()) {
+			if position.completes_line(cell, piece) {
+				return Ply { place: Some((cell, piece)), give: None };
+			}
+		}
+	}
+
+	let mut ply = random_ply(position, rng);
+	for _ in 0..HEURISTIC_TRIES {
+		if let Some(give) = ply.give {
+			// keep the placement, but give a safe piece if there is one
+			let placed = position.play(Ply { give: None, ..ply });
+			let safe = placed.available() & !placed.unsafe_pieces();
+			if safe & (1 << give) != 0 {
+				break;
+			} else if safe != 0 {
+				return Ply { give: Some(random_bit(safe, rng)), ..ply };
+			}
+		} else if !opponent_wins_next(&position.play(ply)) {
+			break;
+		}
+		ply = random_ply(position, rng);
+	}
+	ply
+}
+
+#[test]
+fn test_mcts_wins_immediately() {
+	use quart_lib::BPos;
+
+	let mut game = Game::new();
+	for (x, piece) in Piece::all().filter(|p| p.big).take(3).enumerate() {
+		game.select_next_piece(piece).unwrap();
+		game.place_piece(BPos::new(x as u16, 0)).unwrap();
+	}
+	game.select_next_piece(Piece { big: true, dark: true, round: false, flat: false }).unwrap();
+	let mut agent = MctsAgent::with_budget(Budget::Iterations(100)).seed(1);
+	assert_eq!(agent.play(&game).unwrap().placement.map(|p| p.pos), Some(BPos::new(3, 0)));
+}
+
+#[test]
+fn test_mcts_reuses_tree() {
+	let mut game = Game::new();
+	game.select_next_piece(Piece::from_index(0)).unwrap();
+	let mut agent = MctsAgent::with_budget(Budget::Iterations(2000)).rollout(Rollout::Random).seed(2);
+	let ply = agent.search(&Position::from_game(&game)).unwrap();
+
+	// let the opponent answer with the reply the tree knows best
+	let position = Position::from_game(&game).play(ply);
+	let child = agent.nodes[0].children.iter()
+		.find(|c| agent.nodes[**c].ply == Some(ply))
+		.map(|c| &agent.nodes[*c])
+		.unwrap();
+	let reply = child.children.iter().max_by_key(|c| agent.nodes[**c].visits).unwrap();
+	let expected_visits = agent.nodes[*reply].visits;
+	let reply = agent.nodes[*reply].ply.unwrap();
+	assert!(expected_visits > 0);
+
+	agent.set_root(&position.play(reply));
+	assert_eq!(agent.nodes[0].visits, expected_visits);
+	assert_eq!(agent.nodes[0].parent, None);
+	assert!(agent.nodes.iter().skip(1).all(|n| n.parent.is_some()));
+}
@@ -0,0 +1,493 @@
+use quart_lib::{BPos, Game, GameState, Piece, Players, TurnStructure};
+use std::fmt;
+use std::str::FromStr;
+use crate::{Move, Placement};
+
+/// Marks an empty cell
+const EMPTY: u8 = 16;
+
+/// All rows, columns and diagonals, as cell indices (see `BPos::index`)
+pub const LINES: [[u8; 4]; 10] = [
+	[0, 1, 2, 3],
+	[4, 5, 6, 7],
+	[8, 9, 10, 11],
+	[12, 13, 14, 15],
+	[0, 4, 8, 12],
+	[1, 5, 9, 13],
+	[2, 6, 10, 14],
+	[3, 7, 11, 15],
+	[0, 5, 10, 15],
+	[3, 6, 9, 12],
+];
+
+/// For each cell, a bitmask of the `LINES` going through it
+const LINES_THROUGH: [u16; 16] = lines_through();
+
+const fn lines_through() -> [u16; 16] {
+	let mut masks = [0u16; 16];
+	let mut line = 0;
+	while line < LINES.len() {
+		let mut i = 0;
+		while i < 4 {
+			masks[LINES[line][i] as usize] |= 1 << line;
+			i += 1;
+		}
+		line += 1;
+	}
+	masks
+}
+
+/// For each of the 4 property bits, a bitmask of the pieces that have it
+const PIECES_WITH: [u16; 4] = pieces_with();
+
+const fn pieces_with() -> [u16; 4] {
+	let mut masks = [0u16; 4];
+	let mut piece = 0;
+	while piece < 16 {
+		let mut bit = 0;
+		while bit < 4 {
+			if piece & (1 << bit) != 0 {
+				masks[bit] |= 1 << piece;
+			}
+			bit += 1;
+		}
+		piece += 1;
+	}
+	masks
+}
+
+/// A compact representation of a game position, cheap to copy, used for searching.
+/// Pieces are identified by `Piece::index`, cells by `BPos::index`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+	cells: [u8; 16],
+	/// Bitmask of the pieces that are neither on the board nor in hand
+	available: u16,
+	/// The piece the player to move has to place
+	in_hand: Option<u8>,
+	/// Whether players choose their own pieces, see `TurnStructure::FreeChoice`
+	free_choice: bool,
+}
+
+/// One turn of the player to move: place a piece, then give one to the opponent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Ply {
+	/// Cell and piece to place, `None` for the very first turn of a classic game
+	pub place: Option<(u8, u8)>,
+	/// The piece to give, `None` in the free choice variant and if the game ends
+	pub give: Option<u8>,
+}
+impl Ply {
+	/// Where to place the piece, if anything is placed
+	pub fn pos(self) -> Option<BPos> {
+		self.place.map(|(cell, _)| BPos::from_index(cell))
+	}
+
+	/// The piece that is placed, if anything is placed
+	pub fn placed_piece(self) -> Option<Piece> {
+		self.place.map(|(_, piece)| Piece::from_index(piece))
+	}
+
+	/// The piece given to the opponent, if any
+	pub fn given_piece(self) -> Option<Piece> {
+		self.give.map(Piece::from_index)
+	}
+
+	/// The placement of this ply, if anything is placed
+	pub fn placement(self) -> Option<Placement> {
+		self.place.map(|(cell, piece)| Placement { pos: BPos::from_index(cell), piece: Piece::from_index(piece) })
+	}
+
+	/// Convert into the `Move` returned by `AiAgent::play`
+	pub fn into_move(self) -> Move {
+		Move { placement: self.placement(), give: self.given_piece() }
+	}
+}
+
+impl Position {
+	/// An empty board, with the given turn structure
+	pub fn new(free_choice: bool) -> Self {
+		Self {
+			cells: [EMPTY; 16],
+			available: 0xFFFF,
+			in_hand: None,
+			free_choice,
+		}
+	}
+
+	/// The position of `game`, as seen by the player to move
+	pub fn from_game(game: &Game) -> Self {
+		let mut position = Self::new(game.turn_structure == TurnStructure::FreeChoice);
+		for cell in 0..16 {
+			if let Some(piece) = game.board[BPos::from_index(cell)] {
+				position.cells[cell as usize] = piece.index();
+				position.available &= !(1 << piece.index());
+			}
+		}
+		if game.state == GameState::PlacePiece {
+			if let Some(piece) = game.selected_piece {
+				// in the free choice variant, the player chose a piece himself
+				position.in_hand = Some(piece.index());
+				position.available &= !(1 << piece.index());
+			}
+		}
+		position
+	}
+
+	/// A two player game in this position. The game history doesn't show
+	/// the actual turns, the pieces are placed cell by cell
+	pub fn to_game(&self) -> Game {
+		let turn_structure = if self.free_choice { TurnStructure::FreeChoice } else { TurnStructure::GivePiece };
+		let mut game = Game::with_players(turn_structure, Players::two());
+		for cell in 0..16 {
+			if let Some(piece) = self.piece_at(cell) {
+				// with a complete line the game ends early
+				if game.select_next_piece(Piece::from_index(piece)).and_then(|()| game.place_piece(BPos::from_index(cell))).is_err() {
+					return game;
+				}
+			}
+		}
+		if let Some(piece) = self.in_hand {
+			let _ = game.select_next_piece(Piece::from_index(piece));
+		}
+		game
+	}
+
+	/// The piece on `cell`, if any
+	pub fn piece_at(&self, cell: u8) -> Option<u8> {
+		match self.cells[cell as usize] {
+			EMPTY => None,
+			piece => Some(piece),
+		}
+	}
+
+	/// Bitmask of the pieces that are neither on the board nor in hand
+	pub fn available(&self) -> u16 {
+		self.available
+	}
+
+	/// The piece the player to move has to place
+	pub fn in_hand(&self) -> Option<u8> {
+		self.in_hand
+	}
+
+	/// Whether players choose their own pieces
+	pub fn free_choice(&self) -> bool {
+		self.free_choice
+	}
+
+	/// Bitmask of the empty cells
+	pub fn empty_cells(&self) -> u16 {
+		(0..16).filter(|cell| self.cells[*cell] == EMPTY).fold(0, |mask, cell| mask | 1 << cell)
+	}
+
+	/// Number of empty cells
+	pub fn empty_count(&self) -> u32 {
+		self.empty_cells().count_ones()
+	}
+
+	/// Whether all 16 pieces are on the board
+	pub fn is_full(&self) -> bool {
+		self.cells.iter().all(|c| *c != EMPTY)
+	}
+
+	/// Whether some row, column or diagonal is complete
+	pub fn is_won(&self) -> bool {
+		(0..LINES.len()).any(|line| self.line_complete(line, None))
+	}
+
+	/// Whether placing `piece` at the empty `cell` completes a line
+	pub fn completes_line(&self, cell: u8, piece: u8) -> bool {
+		let mut lines = LINES_THROUGH[cell as usize];
+		while lines != 0 {
+			let line = lines.trailing_zeros() as usize;
+			lines &= lines - 1;
+			if self.line_complete(line, Some((cell, piece))) {
+				return true;
+			}
+		}
+		false
+	}
+
+	/// Whether all pieces on `line` (with `extra` placed) share a property
+	fn line_complete(&self, line: usize, extra: Option<(u8, u8)>) -> bool {
+		let (mut all, mut none) = (0xF, 0xF);
+		for cell in LINES[line].iter() {
+			let piece = match extra {
+				Some((extra_cell, piece)) if extra_cell == *cell => piece,
+				_ => self.cells[*cell as usize],
+			};
+			if piece == EMPTY {
+				return false;
+			}
+			all &= piece;
+			none &= !piece;
+		}
+		(all | none) & 0xF != 0
+	}
+
+	/// Number of lines with 3 pieces sharing a property
+	pub fn threat_count(&self) -> u32 {
+		(0..LINES.len()).filter(|line| self.threat_mask(*line) != 0).count() as u32
+	}
+
+	/// For each attribute bit of `Piece::index`, how many lines hold 3 pieces sharing it
+	pub fn threats_by_attribute(&self) -> [u32; 4] {
+		let mut threats = [0; 4];
+		for line in LINES.iter() {
+			let (mut all, mut none, mut count) = (0xF, 0xF, 0);
+			for cell in line.iter() {
+				let piece = self.cells[*cell as usize];
+				if piece != EMPTY {
+					all &= piece;
+					none &= !piece;
+					count += 1;
+				}
+			}
+			if count == 3 {
+				for (bit, threat) in threats.iter_mut().enumerate() {
+					*threat += ((all | none) >> bit) as u32 & 1;
+				}
+			}
+		}
+		threats
+	}
+
+	/// Bitmask of all pieces which complete a line when placed on the right cell
+	pub fn unsafe_pieces(&self) -> u16 {
+		(0..LINES.len()).fold(0, |mask, line| mask | self.threat_mask(line))
+	}
+
+	/// If `line` holds 3 pieces sharing some property, a bitmask of the pieces
+	/// which complete it, otherwise 0
+	fn threat_mask(&self, line: usize) -> u16 {
+		let (mut all, mut none, mut count) = (0xF, 0xF, 0);
+		for cell in LINES[line].iter() {
+			let piece = self.cells[*cell as usize];
+			if piece != EMPTY {
+				all &= piece;
+				none &= !piece;
+				count += 1;
+			}
+		}
+		if count != 3 {
+			return 0;
+		}
+		let mut mask = 0;
+		for (bit, with) in PIECES_WITH.iter().enumerate() {
+			if all & (1 << bit) != 0 {
+				mask |= with;
+			}
+			if none & (1 << bit) != 0 {
+				mask |= !with;
+			}
+		}
+		mask
+	}
+
+	/// Whether `ply` places a piece which completes a line
+	pub fn is_winning(&self, ply: Ply) -> bool {
+		match ply.place {
+			Some((cell, piece)) => self.completes_line(cell, piece),
+			None => false,
+		}
+	}
+
+	/// All plies the player to move can play
+	pub fn legal_plies(&self) -> Vec<Ply> {
+		let mut plies = Vec::new();
+		let empty = self.empty_cells();
+		if self.free_choice {
+			for cell in bits(empty) {
+				for piece in bits(self.available) {
+					plies.push(Ply { place: Some((cell, piece)), give: None });
+				}
+			}
+		} else if let Some(piece) = self.in_hand {
+			for cell in bits(empty) {
+				let place = Some((cell, piece));
+				if self.available == 0 || self.completes_line(cell, piece) {
+					// nothing left to give, or nothing has to be given anymore
+					plies.push(Ply { place, give: None });
+				} else {
+					plies.extend(bits(self.available).map(|give| Ply { place, give: Some(give) }));
+				}
+			}
+		} else {
+			// the very first turn, there is nothing to place yet
+			plies.extend(bits(self.available).map(|give| Ply { place: None, give: Some(give) }));
+		}
+		plies
+	}
+
+	/// The position after placing `place` (see `Ply::place`), in which the same player
+	/// still has to give a piece: its `legal_plies` are the pieces to give.
+	/// `None` if nothing is given (see `Move::give`) or `place` isn't legal
+	pub fn before_give(&self, place: Option<(u8, u8)>) -> Option<Position> {
+		if self.free_choice {
+			return None;
+		}
+		let next = match place {
+			Some((cell, piece)) => {
+				if self.in_hand != Some(piece) || self.cells[cell as usize] != EMPTY || self.completes_line(cell, piece) {
+					return None;
+				}
+				self.play(Ply { place, give: None })
+			},
+			None if self.in_hand.is_none() => *self,
+			None => return None,
+		};
+		if next.available == 0 {
+			None
+		} else {
+			Some(next)
+		}
+	}
+
+	/// The position after `ply`, as seen by the opponent.
+	/// Panics if `ply` places a piece on an occupied cell
+	pub fn play(&self, ply: Ply) -> Position {
+		let mut next = *self;
+		if let Some((cell, piece)) = ply.place {
+			assert_eq!(next.cells[cell as usize], EMPTY, "cell {} is occupied", cell);
+			next.cells[cell as usize] = piece;
+			next.available &= !(1 << piece);
+			next.in_hand = None;
+		}
+		if let Some(give) = ply.give {
+			next.available &= !(1 << give);
+			next.in_hand = Some(give);
+		}
+		next
+	}
+}
+
+impl fmt::Display for Position {
+	/// The turn structure, the 16 cells row by row starting at A4 with `-` for empty
+	/// ones, and the piece in hand if there is one, like `classic BDRf,-,…,- bDrF`
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let cells: Vec<String> = (0..16)
+			.map(|cell| match self.piece_at(cell) {
+				Some(piece) => Piece::from_index(piece).to_string(),
+				None => "-".to_string(),
+			})
+			.collect();
+		write!(f, "{} {}", if self.free_choice { "free" } else { "classic" }, cells.join(","))?;
+		if let Some(piece) = self.in_hand {
+			write!(f, " {}", Piece::from_index(piece))?;
+		}
+		Ok(())
+	}
+}
+impl FromStr for Position {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut fields = s.split_whitespace();
+		let mut position = match fields.next() {
+			Some("classic") => Position::new(false),
+			Some("free") => Position::new(true),
+			_ => return Err(format!("unknown turn structure in {:?}", s)),
+		};
+		let cells: Vec<&str> = fields.next().unwrap_or_default().split(',').collect();
+		if cells.len() != 16 {
+			return Err(format!("{} cells instead of 16 in {:?}", cells.len(), s));
+		}
+		let mut take = |piece: Piece| {
+			let index = piece.index();
+			match position.available & 1 << index {
+				0 => Err(format!("{} is used twice in {:?}", piece, s)),
+				_ => {
+					position.available &= !(1 << index);
+					Ok(index)
+				},
+			}
+		};
+		let mut placed = [EMPTY; 16];
+		for (cell, text) in placed.iter_mut().zip(cells) {
+			if text != "-" {
+				*cell = take(text.parse()?)?;
+			}
+		}
+		let in_hand = fields.next().map(|text| text.parse().and_then(&mut take)).transpose()?;
+		if fields.next().is_some() {
+			return Err(format!("trailing fields in {:?}", s));
+		}
+		position.cells = placed;
+		position.in_hand = in_hand;
+		Ok(position)
+	}
+}
+
+/// The indices of the set bits in `mask`
+pub fn bits(mut mask: u16) -> impl Iterator<Item = u8> {
+	std::iter::from_fn(move || {
+		if mask == 0 {
+			None
+		} else {
+			let bit = mask.trailing_zeros() as u8;
+			mask &= mask - 1;
+			Some(bit)
+		}
+	})
+}
+
+#[test]
+fn test_from_game() {
+	let mut game = Game::new();
+	let piece = Piece { big: true, dark: true, round: false, flat: false };
+	game.select_next_piece(piece).unwrap();
+	let position = Position::from_game(&game);
+	assert_eq!(position.in_hand(), Some(piece.index()));
+	assert_eq!(position.available().count_ones(), 15);
+
+	game.place_piece(BPos::new(3, 1)).unwrap();
+	let position = Position::from_game(&game);
+	assert_eq!(position.piece_at(BPos::new(3, 1).index()), Some(piece.index()));
+	assert_eq!(position.in_hand(), None);
+	assert_eq!(position.empty_count(), 15);
+}
+#[test]
+fn test_position_text() {
+	let position = Position::new(false)
+		.play(Ply { place: None, give: Some(12) })
+		.play(Ply { place: Some((7, 12)), give: Some(3) });
+	let text = position.to_string();
+	assert_eq!(text, "classic -,-,-,-,-,-,-,BDrf,-,-,-,-,-,-,-,- bdRF");
+	assert_eq!(text.parse(), Ok(position));
+	assert_eq!(Position::from_game(&position.to_game()), position);
+	let before_give = position.before_give(Some((0, 3))).unwrap();
+	assert_eq!(Position::from_game(&before_give.to_game()), before_give);
+	assert!("classic -,-".parse::<Position>().is_err());
+	assert!("free BDrf,BDrf,-,-,-,-,-,-,-,-,-,-,-,-,-,-".parse::<Position>().is_err());
+}
+#[test]
+#[should_panic]
+fn test_play_on_occupied_cell() {
+	let ply = Ply { place: Some((7, 0)), give: None };
+	Position::new(true).play(ply).play(ply);
+}
+#[test]
+fn test_completes_line() {
+	let p = |big, dark, round, flat| Piece { big, dark, round, flat }.index();
+	let (t, f) = (true, false);
+	let mut position = Position::new(true);
+	for (y, piece) in [p(t, t, t, t), p(t, t, t, f), p(t, t, f, t)].iter().enumerate() {
+		position = position.play(Ply { place: Some((BPos::new(3, y as u16).index(), *piece)), give: None });
+	}
+	let last = BPos::new(3, 3).index();
+	assert!(position.completes_line(last, p(t, f, f, f)));
+	assert!(!position.completes_line(last, p(f, f, f, f)));
+	assert_eq!(position.threat_count(), 1);
+	// all big or dark pieces that are left
+	assert_eq!(position.unsafe_pieces() & position.available(), 0b1111_1111_1111_0000 & position.available());
+	assert!(!position.is_won());
+}
+#[test]
+fn test_legal_plies() {
+	let position = Position::new(false);
+	assert_eq!(position.legal_plies().len(), 16, "the first turn only gives a piece");
+	let position = position.play(position.legal_plies()[0]);
+	assert_eq!(position.legal_plies().len(), 16 * 15);
+	assert_eq!(Position::new(true).legal_plies().len(), 16 * 16);
+}
@@ -0,0 +1,349 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use crate::Move;
+use crate::analysis::ScoredMove;
+use crate::position::Position;
+use crate::search::{SearchLimits, MAX_DEPTH};
+use crate::solver::Outcome;
+
+/// The protocol version, sent in the handshake
+pub const VERSION: u32 = 1;
+
+/// A line the program using an engine sends to it.
+///
+/// The engine reads one command per line from stdin and answers on stdout, see `Reply`.
+/// Moves are written as in `Move`'s `Display`, like `BDRf@B3/bDrF`, positions as in
+/// `Position`'s, like `classic BDRf,-,…,- bDrF`. A session goes like:
+///
+/// ```text
+/// > qei                        engine: id name …, option name … default …, qeiok
+/// > setoption name depth value 4
+/// > isready                    engine: readyok, once all commands before are done
+/// > newgame
+/// > position classic -,-,…,- bDrF
+/// > go movetime 1000           engine: info …, then bestmove bDrF@A4/BdRf
+/// > analyze depth 3            engine: a scored line per move, then bestmove …
+/// > quit
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+	/// `qei`: start the handshake
+	Qei,
+	/// `isready`: wait for the engine to be done with the commands before
+	IsReady,
+	/// `setoption name NAME value VALUE`: configure the engine, see `Reply::Option`
+	SetOption {
+		/// The option, without spaces
+		name: String,
+		/// The new value, may contain spaces
+		value: String,
+	},
+	/// `newgame`: the next position is from another game
+	NewGame,
+	/// `position POSITION`: the position to search from now on
+	Position(Position),
+	/// `go [depth N] [movetime MS] [nodes N]`: find the move to play, within the
+	/// limits if there are any
+	Go(Option<SearchLimits>),
+	/// `analyze [depth N] [movetime MS] [nodes N]`: score every move
+	Analyze(SearchLimits),
+	/// `stop`: reply with the best move found so far as soon as possible
+	Stop,
+	/// `quit`: exit the engine
+	Quit,
+}
+
+/// A line the engine sends in reply to a `Command`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+	/// `id name TEXT`, `id author TEXT` or `id protocol VERSION`: about the engine, after `qei`
+	Id {
+		/// What the text is about
+		key: String,
+		/// The text, may contain spaces
+		value: String,
+	},
+	/// `option name NAME default VALUE`: a setting of the engine, after `qei`
+	Option {
+		/// The option, without spaces
+		name: String,
+		/// The default value, may contain spaces
+		default: String,
+	},
+	/// `qeiok`: the handshake is done
+	QeiOk,
+	/// `readyok`: all commands before `isready` are done
+	ReadyOk,
+	/// `info depth D score S nodes N [pv MOVE…]`: progress while searching
+	Info {
+		/// The completed depth
+		depth: u32,
+		/// The score of the best move, see `search::WIN`
+		score: i32,
+		/// The nodes visited so far
+		nodes: u64,
+		/// The best move and the expected continuation
+		pv: Vec<Move>,
+	},
+	/// `scored MOVE score S [outcome win|loss|draw N] [pv MOVE…]`: a move of `analyze`
+	Scored(ScoredMove),
+	/// `bestmove MOVE` or `bestmove none`: the end of `go` and `analyze`
+	BestMove(Option<Move>),
+	/// `error TEXT`: a command couldn't be understood or carried out
+	Error(String),
+}
+
+/// The error of a malformed line
+fn malformed(line: &str) -> String {
+	format!("malformed line {:?}", line)
+}
+
+/// The rest of `words` joined by spaces
+fn rest<'a>(words: impl Iterator<Item = &'a str>) -> String {
+	words.collect::<Vec<_>>().join(" ")
+}
+
+/// Parse `key value` pairs of limits, `None` if there are none
+fn parse_limits<'a>(mut words: impl Iterator<Item = &'a str>, line: &str) -> Result<Option<SearchLimits>, String> {
+	let mut limits = None;
+	while let Some(key) = words.next() {
+		let value = words.next().ok_or_else(|| malformed(line))?;
+		let number: u64 = value.parse().map_err(|_| malformed(line))?;
+		let limits = limits.get_or_insert(SearchLimits { max_depth: MAX_DEPTH, time: None, nodes: None });
+		match key {
+			"depth" => limits.max_depth = (number as u32).clamp(1, MAX_DEPTH),
+			"movetime" => limits.time = Some(Duration::from_millis(number)),
+			"nodes" => limits.nodes = Some(number),
+			_ => return Err(malformed(line)),
+		}
+	}
+	Ok(limits)
+}
+
+/// Write `limits` as `key value` pairs, each after a space
+fn write_limits(f: &mut fmt::Formatter, limits: &SearchLimits) -> fmt::Result {
+	if limits.max_depth < MAX_DEPTH {
+		write!(f, " depth {}", limits.max_depth)?;
+	}
+	if let Some(time) = limits.time {
+		write!(f, " movetime {}", time.as_millis())?;
+	}
+	if let Some(nodes) = limits.nodes {
+		write!(f, " nodes {}", nodes)?;
+	}
+	Ok(())
+}
+
+/// Write ` pv` and the moves, nothing without moves
+fn write_pv(f: &mut fmt::Formatter, pv: &[Move]) -> fmt::Result {
+	if !pv.is_empty() {
+		write!(f, " pv")?;
+		for mov in pv {
+			write!(f, " {}", mov)?;
+		}
+	}
+	Ok(())
+}
+
+fn parse_pv<'a>(words: impl Iterator<Item = &'a str>) -> Result<Vec<Move>, String> {
+	words.map(str::parse).collect()
+}
+
+impl fmt::Display for Command {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Command::Qei                      => write!(f, "qei"),
+			Command::IsReady                  => write!(f, "isready"),
+			Command::SetOption { name, value } => write!(f, "setoption name {} value {}", name, value),
+			Command::NewGame                  => write!(f, "newgame"),
+			Command::Position(position)       => write!(f, "position {}", position),
+			Command::Go(limits) => {
+				write!(f, "go")?;
+				match limits {
+					Some(limits) => write_limits(f, limits),
+					None => Ok(()),
+				}
+			},
+			Command::Analyze(limits) => {
+				write!(f, "analyze")?;
+				write_limits(f, limits)
+			},
+			Command::Stop                     => write!(f, "stop"),
+			Command::Quit                     => write!(f, "quit"),
+		}
+	}
+}
+impl FromStr for Command {
+	type Err = String;
+
+	fn from_str(line: &str) -> Result<Self, Self::Err> {
+		let mut words = line.split_whitespace();
+		let command = match words.next() {
+			Some("qei") => Command::Qei,
+			Some("isready") => Command::IsReady,
+			Some("setoption") => {
+				if words.next() != Some("name") {
+					return Err(malformed(line));
+				}
+				let name = words.next().ok_or_else(|| malformed(line))?.to_string();
+				if words.next() != Some("value") {
+					return Err(malformed(line));
+				}
+				Command::SetOption { name, value: rest(words) }
+			},
+			Some("newgame") => Command::NewGame,
+			Some("position") => Command::Position(rest(words).parse()?),
+			Some("go") => Command::Go(parse_limits(words, line)?),
+			Some("analyze") => Command::Analyze(parse_limits(words, line)?.unwrap_or_else(|| SearchLimits::depth(MAX_DEPTH))),
+			Some("stop") => Command::Stop,
+			Some("quit") => Command::Quit,
+			_ => return Err(format!("unknown command {:?}", line)),
+		};
+		Ok(command)
+	}
+}
+
+impl fmt::Display for Reply {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Reply::Id { key, value }        => write!(f, "id {} {}", key, value),
+			Reply::Option { name, default } => write!(f, "option name {} default {}", name, default),
+			Reply::QeiOk                    => write!(f, "qeiok"),
+			Reply::ReadyOk                  => write!(f, "readyok"),
+			Reply::Info { depth, score, nodes, pv } => {
+				write!(f, "info depth {} score {} nodes {}", depth, score, nodes)?;
+				write_pv(f, pv)
+			},
+			Reply::Scored(scored) => {
+				write!(f, "scored {} score {}", scored.mov, scored.score)?;
+				match scored.outcome {
+					Some(Outcome::Win(distance)) => write!(f, " outcome win {}", distance)?,
+					Some(Outcome::Loss(distance)) => write!(f, " outcome loss {}", distance)?,
+					Some(Outcome::Draw(distance)) => write!(f, " outcome draw {}", distance)?,
+					None => {},
+				}
+				write_pv(f, &scored.pv)
+			},
+			Reply::BestMove(Some(mov))      => write!(f, "bestmove {}", mov),
+			Reply::BestMove(None)           => write!(f, "bestmove none"),
+			Reply::Error(text)              => write!(f, "error {}", text),
+		}
+	}
+}
+impl FromStr for Reply {
+	type Err = String;
+
+	fn from_str(line: &str) -> Result<Self, Self::Err> {
+		let mut words = line.split_whitespace();
+		let mut expect = |word: &str| match words.next() {
+			Some(w) if w == word => Ok(()),
+			_ => Err(malformed(line)),
+		};
+		let reply = match line.split_whitespace().next() {
+			Some("id") => {
+				expect("id")?;
+				let key = words.next().ok_or_else(|| malformed(line))?.to_string();
+				Reply::Id { key, value: rest(words) }
+			},
+			Some("option") => {
+				expect("option")?;
+				expect("name")?;
+				let name = words.next().ok_or_else(|| malformed(line))?.to_string();
+				if words.next() != Some("default") {
+					return Err(malformed(line));
+				}
+				Reply::Option { name, default: rest(words) }
+			},
+			Some("qeiok") => Reply::QeiOk,
+			Some("readyok") => Reply::ReadyOk,
+			Some("info") => {
+				expect("info")?;
+				let mut number = |key: &str| match (words.next(), words.next()) {
+					(Some(k), Some(value)) if k == key => value.parse::<i64>().map_err(|_| malformed(line)),
+					_ => Err(malformed(line)),
+				};
+				let depth = number("depth")? as u32;
+				let score = number("score")? as i32;
+				let nodes = number("nodes")? as u64;
+				let pv = match words.next() {
+					Some("pv") => parse_pv(words)?,
+					Some(_) => return Err(malformed(line)),
+					None => Vec::new(),
+				};
+				Reply::Info { depth, score, nodes, pv }
+			},
+			Some("scored") => {
+				expect("scored")?;
+				let mov = words.next().ok_or_else(|| malformed(line))?.parse()?;
+				if words.next() != Some("score") {
+					return Err(malformed(line));
+				}
+				let score = words.next().and_then(|s| s.parse().ok()).ok_or_else(|| malformed(line))?;
+				let mut outcome = None;
+				let mut pv = Vec::new();
+				while let Some(word) = words.next() {
+					match word {
+						"outcome" => {
+							let kind = words.next();
+							let distance = words.next().and_then(|d| d.parse().ok()).ok_or_else(|| malformed(line))?;
+							outcome = Some(match kind {
+								Some("win") => Outcome::Win(distance),
+								Some("loss") => Outcome::Loss(distance),
+								Some("draw") => Outcome::Draw(distance),
+								_ => return Err(malformed(line)),
+							});
+						},
+						"pv" => pv = parse_pv(&mut words)?,
+						_ => return Err(malformed(line)),
+					}
+				}
+				Reply::Scored(ScoredMove { mov, score, outcome, pv })
+			},
+			Some("bestmove") => match line.split_whitespace().nth(1) {
+				Some("none") => Reply::BestMove(None),
+				Some(mov) => Reply::BestMove(Some(mov.parse()?)),
+				None => return Err(malformed(line)),
+			},
+			Some("error") => Reply::Error(rest(line.split_whitespace().skip(1))),
+			_ => return Err(format!("unknown reply {:?}", line)),
+		};
+		Ok(reply)
+	}
+}
+
+#[test]
+fn test_protocol_lines() {
+	use crate::position::Ply;
+
+	let position = Position::new(false).play(Ply { place: None, give: Some(3) });
+	let mov = Ply { place: Some((5, 3)), give: Some(9) }.into_move();
+	let commands = [
+		Command::Qei,
+		Command::SetOption { name: "agent".to_string(), value: "human like".to_string() },
+		Command::Position(position),
+		Command::Go(None),
+		Command::Go(Some(SearchLimits::time(Duration::from_millis(1500)).nodes(1000))),
+		Command::Analyze(SearchLimits::depth(3)),
+		Command::Quit,
+	];
+	for command in commands.iter() {
+		assert_eq!(command.to_string().parse::<Command>().as_ref(), Ok(command));
+	}
+	let replies = [
+		Reply::Id { key: "name".to_string(), value: "Quart Engine".to_string() },
+		Reply::Option { name: "depth".to_string(), default: "3".to_string() },
+		Reply::Info { depth: 2, score: -40, nodes: 1234, pv: vec![mov, mov] },
+		Reply::Scored(ScoredMove { mov, score: 9999, outcome: Some(Outcome::Win(2)), pv: vec![mov] }),
+		Reply::Scored(ScoredMove { mov, score: 12, outcome: None, pv: Vec::new() }),
+		Reply::BestMove(Some(mov)),
+		Reply::BestMove(None),
+		Reply::Error("unknown command".to_string()),
+	];
+	for reply in replies.iter() {
+		assert_eq!(reply.to_string().parse::<Reply>().as_ref(), Ok(reply));
+	}
+	assert_eq!(mov.to_string(), "bdRF@B3/BdrF");
+	assert!("go depth".parse::<Command>().is_err());
+	assert!("bestmove X@B3".parse::<Reply>().is_err());
+}
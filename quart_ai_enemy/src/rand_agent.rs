@@ -1,44 +1,41 @@
-use quart_lib::{Game, BPos, Piece, Board};
+use quart_lib::{Game, BPos, Piece};
 use rand::prelude::*;
-use crate::AiAgent;
+use rand::rngs::StdRng;
+use crate::{AiAgent, Placement};
+use crate::position::{bits, Position};
 
 /// An AI enemy
-pub struct RandAgent;
+pub struct RandAgent {
+	rng: StdRng,
+}
 
 impl RandAgent {
 	/// Create a new AI enemy
 	pub fn new(_game: &Game) -> Self {
-		Self
+		Self { rng: StdRng::from_entropy() }
 	}
-}
-impl AiAgent for RandAgent {
-	fn play(&mut self, game: &Game) -> (BPos, Piece) {
-		valid_random_move(&game.board)
+
+	/// Seed the random number generator, to get reproducible games
+	pub fn seed(mut self, seed: u64) -> Self {
+		self.rng = StdRng::seed_from_u64(seed);
+		self
 	}
 }
-
-/// Generates a random move thats valid on the given board
-pub(crate) fn valid_random_move(board: &Board) -> (BPos, Piece) {
-	assert!(board.piece_count() < 16);
-	let mut pos = random_bpos();
-	while board[pos].is_some() {
-		pos = random_bpos();
+impl AiAgent for RandAgent {
+	fn choose_placement(&mut self, game: &Game) -> Option<Placement> {
+		let position = Position::from_game(game);
+		let rng = &mut self.rng;
+		let piece = match position.in_hand() {
+			Some(piece) => piece,
+			None if position.free_choice() => bits(position.available()).choose(rng)?,
+			None => return None,
+		};
+		let cell = bits(position.empty_cells()).choose(rng)?;
+		Some(Placement { pos: BPos::from_index(cell), piece: Piece::from_index(piece) })
 	}
 
-	let mut piece = random_piece();
-	while board.contains(piece) {
-		piece = random_piece();
+	fn choose_give(&mut self, game: &Game, placement: Option<Placement>) -> Option<Piece> {
+		let position = Position::from_game(game).before_give(placement.map(Placement::indices))?;
+		bits(position.available()).choose(&mut self.rng).map(Piece::from_index)
 	}
-
-	(pos, piece)
-}
-fn random_bpos() -> BPos {
-	let mut rng = thread_rng();
-	let x = rng.gen_range(0, 4);
-	let y = rng.gen_range(0, 4);
-	BPos::new(x, y)
-}
-fn random_piece() -> Piece {
-	let (big, dark, round, flat) = random();
-	Piece { big, dark, round, flat }
 }
@@ -0,0 +1,357 @@
+use quart_lib::Game;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use crate::{AiAgent, DecisionTreeAgent, HumanLikeAgent, MctsAgent, RandAgent};
+use crate::eval::{FeatureEvaluator, Weights};
+use crate::mcts_agent::{Budget, Rollout, DEFAULT_ITERATIONS};
+use crate::search::{SearchLimits, MAX_DEPTH};
+use crate::solver::{ENDGAME_EMPTY, ENDGAME_NODES};
+use crate::tablebase::TablebaseReader;
+
+/// Config of the agent playing random moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RandomConfig;
+
+/// Config of the agent which wins if it can and doesn't give a winning piece away,
+/// but doesn't look further ahead
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GreedyConfig;
+
+/// Config of the `DecisionTreeAgent`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchConfig {
+	/// How many plies to look ahead (at most, with a time limit)
+	pub depth: u32,
+	/// How long to think per move, `None` to always search `depth` plies deep
+	pub time: Option<Duration>,
+	/// How many nodes to visit per move at most
+	pub nodes: Option<u64>,
+	/// How many threads search, without `time`
+	pub threads: usize,
+	/// Whether to solve small end games perfectly
+	pub perfect_endgame: bool,
+}
+impl Default for SearchConfig {
+	fn default() -> Self {
+		Self { depth: crate::decision_tree_agent::DEFAULT_DEPTH, time: None, nodes: None, threads: 1, perfect_endgame: true }
+	}
+}
+
+/// Config of the `MctsAgent`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MctsConfig {
+	/// How long to think per move
+	pub budget: Budget,
+	/// How to play out positions
+	pub rollout: Rollout,
+	/// Whether to solve small end games perfectly
+	pub perfect_endgame: bool,
+}
+impl Default for MctsConfig {
+	fn default() -> Self {
+		Self {
+			budget: Budget::Iterations(DEFAULT_ITERATIONS),
+			rollout: Rollout::Heuristic,
+			perfect_endgame: true,
+		}
+	}
+}
+
+/// Config of the strongest agent: a deeper search, and solving larger end games
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerfectConfig {
+	/// How many plies to look ahead before the end game
+	pub depth: u32,
+	/// End games with up to this many empty cells are solved
+	pub max_empty: u32,
+	/// How many nodes solving may take
+	pub node_limit: u64,
+}
+impl Default for PerfectConfig {
+	fn default() -> Self {
+		Self { depth: 3, max_empty: ENDGAME_EMPTY + 2, node_limit: ENDGAME_NODES * 10 }
+	}
+}
+
+/// Config of the `HumanLikeAgent`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HumanConfig {
+	/// How many plies to look ahead
+	pub depth: u32,
+	/// How random the choice is, see `HumanLikeAgent::temperature`
+	pub temperature: u32,
+	/// How often (in percent) a line the agent could complete goes unnoticed
+	pub miss_threats: u32,
+	/// How often (in percent) the agent gives a piece without checking it's safe
+	pub unsafe_gives: u32,
+}
+impl Default for HumanConfig {
+	fn default() -> Self {
+		Self { depth: 2, temperature: 50, miss_threats: 10, unsafe_gives: 10 }
+	}
+}
+
+/// What the agents created by `AgentConfig::create_with` use besides their config
+#[derive(Debug, Clone, Default)]
+pub struct AgentSetup {
+	/// The weights the searching agents score positions with, `search::evaluate` if `None`
+	pub weights: Option<Weights>,
+	/// The tablebase file the agents playing end games perfectly look positions up in
+	pub tablebase: Option<PathBuf>,
+	/// Seed for the agents choosing at random, to get reproducible games. `None` for a random seed
+	pub seed: Option<u64>,
+}
+impl AgentSetup {
+	/// The setup given by the environment variables `QUART_EVAL`, the path of a weights
+	/// file (see `Weights`), and `QUART_TABLEBASE`, the path of a tablebase file.
+	/// Meant for binaries, weights which can't be read are logged and left out
+	pub fn from_env() -> Self {
+		let weights = std::env::var_os("QUART_EVAL").and_then(|path| {
+			Weights::load(&path)
+				.map_err(|e| log::warn!("Can't load weights {:?}: {}", path, e))
+				.ok()
+		});
+		Self { weights, tablebase: std::env::var_os("QUART_TABLEBASE").map(PathBuf::from), seed: None }
+	}
+
+	/// A `DecisionTreeAgent` with the weights and the tablebase
+	fn decision_tree_agent(&self, limits: SearchLimits) -> DecisionTreeAgent {
+		let agent = DecisionTreeAgent::with_limits(limits);
+		let agent = match self.weights {
+			Some(weights) => agent.evaluator(Box::new(FeatureEvaluator::new(weights))),
+			None => agent,
+		};
+		match self.open_tablebase() {
+			Some(tablebase) => agent.tablebase(tablebase),
+			None => agent,
+		}
+	}
+
+	/// The tablebase, if there is one and it can be opened
+	fn open_tablebase(&self) -> Option<TablebaseReader> {
+		let path = self.tablebase.as_ref()?;
+		TablebaseReader::open(path)
+			.map_err(|e| log::warn!("Can't open tablebase {:?}: {}", path, e))
+			.ok()
+	}
+}
+
+/// A registered agent together with its config
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgentConfig {
+	/// Random moves, see `RandAgent`
+	Random(RandomConfig),
+	/// Greedy moves, see `GreedyConfig`
+	Greedy(GreedyConfig),
+	/// Alpha-beta search, see `DecisionTreeAgent`
+	Search(SearchConfig),
+	/// Monte Carlo Tree Search, see `MctsAgent`
+	Mcts(MctsConfig),
+	/// Deep search and perfect end games, see `PerfectConfig`
+	Perfect(PerfectConfig),
+	/// Human-like mistakes, see `HumanLikeAgent`
+	Human(HumanConfig),
+}
+
+/// The names of all registered agents, with a short description
+pub const AGENTS: [(&str, &str); 6] = [
+	("random", "plays random moves"),
+	("greedy", "wins if it can and doesn't give winning pieces away"),
+	("search", "looks a few moves ahead"),
+	("mcts", "Monte Carlo Tree Search"),
+	("perfect", "looks further ahead and plays end games perfectly"),
+	("human", "makes human mistakes now and then"),
+];
+
+impl AgentConfig {
+	/// The registered agent `name` with its default config, see `AGENTS`.
+	/// Difficulties (see `Difficulty`) are accepted as well
+	pub fn by_name(name: &str) -> Option<Self> {
+		let name = name.to_lowercase();
+		let config = match name.as_str() {
+			"random" => AgentConfig::Random(RandomConfig),
+			"greedy" => AgentConfig::Greedy(GreedyConfig),
+			"search" => AgentConfig::Search(SearchConfig::default()),
+			"mcts" => AgentConfig::Mcts(MctsConfig::default()),
+			"perfect" => AgentConfig::Perfect(PerfectConfig::default()),
+			"human" => AgentConfig::Human(HumanConfig::default()),
+			_ => return name.parse::<Difficulty>().ok().map(Difficulty::config),
+		};
+		Some(config)
+	}
+
+	/// The name the agent is registered with
+	pub fn name(&self) -> &'static str {
+		match self {
+			AgentConfig::Random(_) => "random",
+			AgentConfig::Greedy(_) => "greedy",
+			AgentConfig::Search(_) => "search",
+			AgentConfig::Mcts(_) => "mcts",
+			AgentConfig::Perfect(_) => "perfect",
+			AgentConfig::Human(_) => "human",
+		}
+	}
+
+	/// The config searching within `limits`, as far as the agent has them: the search
+	/// takes all of them, the MCTS the time or the nodes as iterations, and the others
+	/// the depth. Limits the agent doesn't have are left to the caller, e.g. by
+	/// stopping the agent in time with its `Control`
+	pub fn with_limits(&self, limits: &SearchLimits) -> Self {
+		// without a depth limit, the depth of the config is kept
+		let depth = |depth: u32| if limits.max_depth < MAX_DEPTH { limits.max_depth } else { depth };
+		match *self {
+			AgentConfig::Search(config) => AgentConfig::Search(SearchConfig {
+				depth: limits.max_depth,
+				time: limits.time,
+				nodes: limits.nodes,
+				..config
+			}),
+			AgentConfig::Mcts(config) => {
+				let budget = match (limits.time, limits.nodes) {
+					(Some(time), _) => Budget::Time(time),
+					(None, Some(nodes)) => Budget::Iterations(nodes.min(u32::MAX as u64) as u32),
+					(None, None) => config.budget,
+				};
+				AgentConfig::Mcts(MctsConfig { budget, ..config })
+			},
+			AgentConfig::Perfect(config) => AgentConfig::Perfect(PerfectConfig { depth: depth(config.depth), ..config }),
+			AgentConfig::Human(config) => AgentConfig::Human(HumanConfig { depth: depth(config.depth), ..config }),
+			config => config,
+		}
+	}
+
+	/// Create the agent for `game`, without weights or a tablebase
+	pub fn create(&self, game: &Game) -> Box<dyn AiAgent + Send> {
+		self.create_with(game, &AgentSetup::default())
+	}
+
+	/// Create the agent for `game` with `setup`
+	pub fn create_with(&self, game: &Game, setup: &AgentSetup) -> Box<dyn AiAgent + Send> {
+		match *self {
+			AgentConfig::Random(RandomConfig) => match setup.seed {
+				Some(seed) => Box::new(RandAgent::new(game).seed(seed)),
+				None => Box::new(RandAgent::new(game)),
+			},
+			AgentConfig::Greedy(GreedyConfig) => Box::new(setup.decision_tree_agent(SearchLimits::depth(1)).without_endgame()),
+			AgentConfig::Search(config) => {
+				let limits = SearchLimits { max_depth: config.depth.max(1), time: config.time, nodes: config.nodes };
+				let agent = setup.decision_tree_agent(limits).threads(config.threads);
+				if config.perfect_endgame {
+					Box::new(agent)
+				} else {
+					Box::new(agent.without_endgame())
+				}
+			},
+			AgentConfig::Mcts(config) => {
+				let agent = MctsAgent::with_budget(config.budget).rollout(config.rollout);
+				let agent = match setup.seed {
+					Some(seed) => agent.seed(seed),
+					None => agent,
+				};
+				if !config.perfect_endgame {
+					return Box::new(agent.without_endgame());
+				}
+				match setup.open_tablebase() {
+					Some(tablebase) => Box::new(agent.tablebase(tablebase)),
+					None => Box::new(agent),
+				}
+			},
+			AgentConfig::Perfect(config) => Box::new(
+				setup.decision_tree_agent(SearchLimits::depth(config.depth)).endgame_limits(config.max_empty, config.node_limit)
+			),
+			AgentConfig::Human(config) => {
+				let agent = HumanLikeAgent::new()
+					.with_depth(config.depth)
+					.temperature(config.temperature as f64)
+					.miss_threats(config.miss_threats as f64 / 100.0)
+					.unsafe_gives(config.unsafe_gives as f64 / 100.0);
+				match setup.seed {
+					Some(seed) => Box::new(agent.seed(seed)),
+					None => Box::new(agent),
+				}
+			},
+		}
+	}
+}
+impl Default for AgentConfig {
+	fn default() -> Self {
+		AgentConfig::Search(SearchConfig::default())
+	}
+}
+
+/// Create the registered agent `name` (or difficulty) with its default config
+pub fn create_agent(name: &str, game: &Game) -> Option<Box<dyn AiAgent + Send>> {
+	AgentConfig::by_name(name).map(|config| config.create(game))
+}
+
+/// How strong the AI plays
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Difficulty {
+	/// Doesn't blunder immediate wins and losses, but nothing more
+	Easy,
+	/// Looks a few moves ahead
+	Medium,
+	/// Searches thoroughly and plays end games perfectly
+	Hard,
+	/// The strongest agent available
+	Perfect,
+}
+impl Difficulty {
+	/// All difficulties, from easy to perfect
+	pub const ALL: [Difficulty; 4] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard, Difficulty::Perfect];
+
+	/// The agent playing at this difficulty
+	pub fn config(self) -> AgentConfig {
+		match self {
+			Difficulty::Easy => AgentConfig::Greedy(GreedyConfig),
+			Difficulty::Medium => AgentConfig::Search(SearchConfig { depth: 2, time: None, nodes: None, threads: 1, perfect_endgame: false }),
+			Difficulty::Hard => AgentConfig::Mcts(MctsConfig::default()),
+			Difficulty::Perfect => AgentConfig::Perfect(PerfectConfig::default()),
+		}
+	}
+}
+impl fmt::Display for Difficulty {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match self {
+			Difficulty::Easy => "easy",
+			Difficulty::Medium => "medium",
+			Difficulty::Hard => "hard",
+			Difficulty::Perfect => "perfect",
+		};
+		write!(f, "{}", name)
+	}
+}
+impl FromStr for Difficulty {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Difficulty::ALL.iter()
+			.find(|d| d.to_string().eq_ignore_ascii_case(s))
+			.copied()
+			.ok_or_else(|| format!("unknown difficulty {:?}", s))
+	}
+}
+
+#[test]
+fn test_registry() {
+	let game = Game::new();
+	for (name, _) in AGENTS.iter() {
+		let config = AgentConfig::by_name(name).unwrap();
+		assert_eq!(config.name(), *name);
+		let _agent = config.create(&game);
+	}
+	assert_eq!(AgentConfig::by_name("Easy"), Some(AgentConfig::Greedy(GreedyConfig)));
+	assert_eq!("hard".parse::<Difficulty>(), Ok(Difficulty::Hard));
+	// "perfect" is both an agent and a difficulty, and means the same
+	assert_eq!(AgentConfig::by_name("perfect"), Some(Difficulty::Perfect.config()));
+	assert!(create_agent("deep blue", &game).is_none());
+
+	let limits = SearchLimits::time(Duration::from_millis(100));
+	let mcts = AgentConfig::by_name("mcts").unwrap().with_limits(&limits);
+	assert!(matches!(mcts, AgentConfig::Mcts(MctsConfig { budget: Budget::Time(_), .. })));
+	let human = AgentConfig::by_name("human").unwrap();
+	assert_eq!(human.with_limits(&limits), human);
+	assert!(matches!(human.with_limits(&SearchLimits::depth(4)), AgentConfig::Human(HumanConfig { depth: 4, .. })));
+}
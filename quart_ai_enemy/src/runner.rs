@@ -0,0 +1,313 @@
+use quart_lib::Game;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use crate::{AgentError, AiAgent, Move};
+
+/// What an agent reports while it thinks, see `AiAgent::play_controlled`
+#[derive(Debug)]
+pub enum Progress {
+	/// The search is done with a depth
+	Info {
+		/// The completed depth
+		depth: u32,
+		/// The score of the best move, see `search::WIN`
+		score: i32,
+		/// The nodes visited so far
+		nodes: u64,
+		/// The best move so far
+		best: Option<Move>,
+	},
+	/// The agent decided, or failed to
+	Done(Result<Move, AgentError>),
+}
+
+/// Lets the caller stop the search of an agent early and follow its progress
+#[derive(Debug, Default)]
+pub struct Control {
+	stop: AtomicBool,
+	cancel: AtomicBool,
+	progress: Option<Sender<Progress>>,
+}
+impl Control {
+	/// Nothing is reported, and the search isn't stopped
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Report the progress to `progress`
+	pub fn with_progress(progress: Sender<Progress>) -> Self {
+		Self { stop: AtomicBool::new(false), cancel: AtomicBool::new(false), progress: Some(progress) }
+	}
+
+	/// Ask the search to stop as soon as possible, and to decide with what it found so far
+	pub fn stop(&self) {
+		self.stop.store(true, Ordering::Relaxed);
+	}
+
+	/// Ask the search to stop as soon as possible, its result isn't wanted anymore
+	pub fn cancel(&self) {
+		self.cancel.store(true, Ordering::Relaxed);
+		self.stop();
+	}
+
+	/// Whether the search should stop
+	pub fn is_stopped(&self) -> bool {
+		self.stop.load(Ordering::Relaxed)
+	}
+
+	/// Whether the result of the search isn't wanted anymore
+	pub fn is_cancelled(&self) -> bool {
+		self.cancel.load(Ordering::Relaxed)
+	}
+
+	/// Report `progress`, if anybody listens
+	pub fn report(&self, progress: Progress) {
+		if let Some(sender) = &self.progress {
+			let _ = sender.send(progress);
+		}
+	}
+}
+
+/// A move being searched, or pondering, on another thread
+struct Task<A> {
+	handle: JoinHandle<A>,
+	control: Arc<Control>,
+	progress: Receiver<Progress>,
+}
+
+/// Runs an agent on a thread of its own, so the caller isn't blocked while it thinks:
+/// `start` a move, `poll` for progress and the move, or `cancel` it, e.g. when the
+/// user quits or undoes. The agent comes back once it is done, so it keeps its state
+/// (like the tree of an `MctsAgent`) between moves. Between its moves, the agent may
+/// `ponder` on the opponent's time
+pub struct AgentRunner<A> {
+	agent: Option<A>,
+	task: Option<Task<A>>,
+	/// A cancelled move or pondering, to get the agent back once it is stopped
+	background: Option<Task<A>>,
+}
+impl<A: AiAgent + Send + 'static> AgentRunner<A> {
+	/// Run `agent`
+	pub fn new(agent: A) -> Self {
+		Self { agent: Some(agent), task: None, background: None }
+	}
+
+	/// Whether the agent is thinking about a move
+	pub fn is_thinking(&self) -> bool {
+		self.task.is_some()
+	}
+
+	/// Whether the agent ponders, see `ponder`
+	pub fn is_pondering(&self) -> bool {
+		self.background.as_ref().is_some_and(|task| !task.control.is_stopped())
+	}
+
+	/// Start thinking about the move in `game`. Stops pondering, waits for a cancelled
+	/// move to be stopped, and cancels the move thought about before. Panics if the
+	/// agent panicked before
+	pub fn start(&mut self, game: &Game) {
+		self.cancel();
+		let mut agent = self.wait_for_agent();
+		let (sender, progress) = mpsc::channel();
+		let control = Arc::new(Control::with_progress(sender));
+		let task_control = Arc::clone(&control);
+		let game = game.clone();
+		let handle = thread::spawn(move || {
+			let result = agent.play_controlled(&game, &task_control);
+			let result = if task_control.is_cancelled() { Err(AgentError::Cancelled) } else { result };
+			task_control.report(Progress::Done(result));
+			agent
+		});
+		self.task = Some(Task { handle, control, progress });
+	}
+
+	/// Think ahead while the opponent decides what to play in `game`, until the next
+	/// `start`, `cancel` or `agent_mut`. Nothing is reported, the agent reuses what it
+	/// found for its next move, see `AiAgent::ponder`. Cancels the move thought about
+	pub fn ponder(&mut self, game: &Game) {
+		self.cancel();
+		let mut agent = self.wait_for_agent();
+		let (_, progress) = mpsc::channel();
+		let control = Arc::new(Control::new());
+		let task_control = Arc::clone(&control);
+		let game = game.clone();
+		let handle = thread::spawn(move || {
+			agent.ponder(&game, &task_control);
+			agent
+		});
+		self.background = Some(Task { handle, control, progress });
+	}
+
+	/// Ask the agent to decide now, with what it found so far. The move is reported as usual
+	pub fn stop(&mut self) {
+		if let Some(task) = &self.task {
+			task.control.stop();
+		}
+	}
+
+	/// Stop thinking about the move, nothing is reported anymore. Stops pondering as well
+	pub fn cancel(&mut self) {
+		if let Some(task) = self.task.take() {
+			// `start` and `ponder` waited for the task in the background before
+			self.background = Some(task);
+		}
+		if let Some(task) = &self.background {
+			task.control.cancel();
+		}
+	}
+
+	/// The next report of the agent, `None` if there is none yet or it isn't thinking.
+	/// After `Progress::Done`, the agent is ready for the next move
+	pub fn poll(&mut self) -> Option<Progress> {
+		let received = self.task.as_ref()?.progress.try_recv();
+		self.handle(received.map_err(|e| e == TryRecvError::Disconnected))
+	}
+
+	/// Wait for the next report of the agent, `None` if it isn't thinking
+	pub fn next_progress(&mut self) -> Option<Progress> {
+		loop {
+			let received = self.task.as_ref()?.progress.recv();
+			if let Some(progress) = self.handle(received.map_err(|_| true)) {
+				return Some(progress);
+			}
+		}
+	}
+
+	/// Wait for the agent to decide, `None` if it isn't thinking
+	pub fn wait(&mut self) -> Option<Result<Move, AgentError>> {
+		loop {
+			if let Progress::Done(result) = self.next_progress()? {
+				return Some(result);
+			}
+		}
+	}
+
+	/// The agent while it isn't thinking, e.g. to tell it that the game is over.
+	/// Stops pondering, and waits for a cancelled move to be stopped
+	pub fn agent_mut(&mut self) -> Option<&mut A> {
+		if self.task.is_some() {
+			return None;
+		}
+		let agent = self.wait_for_agent();
+		self.agent = Some(agent);
+		self.agent.as_mut()
+	}
+
+	/// Take a report of the current task, `Err(true)` if the thread is gone
+	fn handle(&mut self, received: Result<Progress, bool>) -> Option<Progress> {
+		match received {
+			Ok(Progress::Done(result)) => {
+				self.agent = self.task.take().map(join);
+				Some(Progress::Done(result))
+			},
+			Ok(progress) => Some(progress),
+			Err(false) => None,
+			// the agent panicked
+			Err(true) => {
+				if let Some(task) = self.task.take() {
+					let _ = task.handle.join();
+				}
+				Some(Progress::Done(Err(AgentError::NoMove)))
+			},
+		}
+	}
+
+	/// Take the agent, stopping the task in the background. Panics if the agent panicked before
+	fn wait_for_agent(&mut self) -> A {
+		match self.agent.take() {
+			Some(agent) => agent,
+			None => {
+				let task = self.background.take().expect("the agent panicked");
+				task.control.cancel();
+				join(task)
+			},
+		}
+	}
+}
+impl<A> Drop for AgentRunner<A> {
+	fn drop(&mut self) {
+		// the threads finish on their own
+		for task in self.task.iter().chain(self.background.iter()) {
+			task.control.cancel();
+		}
+	}
+}
+
+/// The agent of `task`, once it is done
+fn join<A>(task: Task<A>) -> A {
+	match task.handle.join() {
+		Ok(agent) => agent,
+		Err(panic) => std::panic::resume_unwind(panic),
+	}
+}
+
+#[test]
+fn test_agent_runner() {
+	use std::time::Duration;
+	use crate::DecisionTreeAgent;
+	use crate::search::SearchLimits;
+
+	// searching as deep as possible takes long, unless cancelled
+	let agent = DecisionTreeAgent::with_limits(SearchLimits::time(Duration::from_secs(60))).without_endgame();
+	let mut runner = AgentRunner::new(agent);
+	let mut game = Game::new();
+	runner.start(&game);
+	assert!(runner.is_thinking());
+	loop {
+		match runner.poll() {
+			Some(Progress::Info { depth, best, .. }) => {
+				assert!(depth >= 1 && best.is_some());
+				break;
+			},
+			Some(Progress::Done(result)) => panic!("done early: {:?}", result),
+			None => thread::sleep(Duration::from_millis(1)),
+		}
+	}
+	runner.cancel();
+	assert!(!runner.is_thinking());
+	assert!(runner.poll().is_none());
+
+	// the agent is back for the next move
+	let agent = runner.agent_mut().unwrap();
+	*agent = DecisionTreeAgent::with_depth(1).without_endgame();
+	runner.start(&game);
+	let mov = runner.wait().unwrap().unwrap();
+	assert!(mov.apply(&mut game).is_ok());
+	assert!(!runner.is_thinking());
+	assert!(runner.wait().is_none());
+
+	// pondering as deep as possible on the opponent's time, until their reply arrives
+	runner.ponder(&game);
+	assert!(runner.is_pondering() && !runner.is_thinking());
+	thread::sleep(Duration::from_millis(20));
+	let reply = DecisionTreeAgent::with_depth(1).without_endgame().play(&game).unwrap();
+	assert!(reply.apply(&mut game).is_ok());
+	runner.start(&game);
+	assert!(!runner.is_pondering());
+	let mov = runner.wait().unwrap().unwrap();
+	assert!(mov.apply(&mut game).is_ok());
+}
+
+#[test]
+fn test_cancel_mcts() {
+	use std::time::{Duration, Instant};
+	use crate::mcts_agent::{Budget, MctsAgent};
+
+	// without being cancelled, the search would take forever
+	let agent = MctsAgent::with_budget(Budget::Iterations(u32::MAX)).without_endgame().seed(1);
+	let mut runner = AgentRunner::new(agent);
+	runner.start(&Game::new());
+	match runner.next_progress() {
+		Some(Progress::Info { nodes, best, .. }) => assert!(nodes > 0 && best.is_some()),
+		progress => panic!("no progress: {:?}", progress),
+	}
+	let cancelled = Instant::now();
+	runner.cancel();
+	let task = runner.background.take().unwrap();
+	let result = task.progress.recv_timeout(Duration::from_secs(1));
+	assert!(matches!(result, Ok(Progress::Done(Err(AgentError::Cancelled)))), "not cancelled: {:?}", result);
+	join(task);
+	assert!(cancelled.elapsed() < Duration::from_secs(1));
+}
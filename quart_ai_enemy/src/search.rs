@@ -0,0 +1,487 @@
+use std::time::Duration;
+use std::sync::atomic::{AtomicI32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use crate::clock::Clock;
+use crate::eval::Evaluator;
+use crate::position::{bits, Ply, Position};
+use crate::runner::{Control, Progress};
+use crate::transposition::{self, from_table, to_table, Bound, Entry, TranspositionTable};
+
+/// Score of winning right now. Wins further in the future score a bit less,
+/// so the fastest win (and the slowest loss) is preferred
+pub const WIN: i32 = 10_000;
+/// Scores above this (or below its negation) are proven wins (losses)
+pub const WIN_THRESHOLD: i32 = WIN - 100;
+/// Larger than any score
+const INFINITY: i32 = WIN + 1;
+/// Deeper than any game lasts
+pub const MAX_DEPTH: u32 = 17;
+/// How many nodes are visited between two looks at the clock, a power of 2
+pub(crate) const CHECK_INTERVAL: u64 = 256;
+
+/// The result of a search
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchResult {
+	/// The best ply found, `None` if there was nothing to play
+	pub best: Option<Ply>,
+	/// Its score, from the point of view of the player to move
+	pub score: i32,
+	/// How many positions were visited
+	pub nodes: u64,
+	/// The depth of the last completed iteration the result is from
+	pub depth: u32,
+}
+
+/// When an iterative deepening search stops: after `max_depth`,
+/// at the deadline `time` after the start or after visiting `nodes` nodes,
+/// whatever comes first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchLimits {
+	/// The deepest iteration
+	pub max_depth: u32,
+	/// How long the search may take
+	pub time: Option<Duration>,
+	/// How many nodes the search may visit
+	pub nodes: Option<u64>,
+}
+impl SearchLimits {
+	/// Search exactly `depth` plies deep
+	pub fn depth(depth: u32) -> Self {
+		Self { max_depth: depth.max(1), time: None, nodes: None }
+	}
+
+	/// Search as deep as possible within `time`
+	pub fn time(time: Duration) -> Self {
+		Self { max_depth: MAX_DEPTH, time: Some(time), nodes: None }
+	}
+
+	/// Also stop after visiting `nodes` nodes
+	pub fn nodes(mut self, nodes: u64) -> Self {
+		self.nodes = Some(nodes);
+		self
+	}
+}
+
+/// Negamax search with alpha-beta pruning over all (placement, give) plies.
+///
+/// Assumes the two sides take turns alternately, with more than two players
+/// all opponents are treated as one
+#[derive(Default)]
+pub struct Search<'c> {
+	nodes: u64,
+	/// Where the time of the deadline comes from, `None` to search without limits
+	clock: Option<&'c dyn Clock>,
+	deadline: Option<Duration>,
+	node_limit: Option<u64>,
+	aborted: bool,
+	table: Option<&'c TranspositionTable>,
+	/// Scores the leaves, `evaluate` if `None`
+	evaluator: Option<&'c dyn Evaluator>,
+	/// Stops the search early and gets its progress
+	control: Option<&'c Control>,
+}
+impl<'c> Search<'c> {
+	/// Create a new search
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Create a new search remembering positions in `table`, which may be shared
+	pub fn with_table(table: &'c TranspositionTable) -> Self {
+		Self { table: Some(table), ..Self::default() }
+	}
+
+	/// Score the leaves of the search with `evaluator` instead of `evaluate`
+	pub fn evaluator(mut self, evaluator: &'c dyn Evaluator) -> Self {
+		self.evaluator = Some(evaluator);
+		self
+	}
+
+	/// Stop when `control` says so, and report every completed depth of `iterative` to it
+	pub fn control(mut self, control: &'c Control) -> Self {
+		self.control = Some(control);
+		self
+	}
+
+	/// Search `position` `depth` plies deep
+	pub fn run(&mut self, position: &Position, depth: u32) -> SearchResult {
+		self.nodes = 0;
+		self.aborted = false;
+		self.clock = None;
+		let depth = depth.max(1);
+		let (best, score) = self.root(position, depth, None);
+		SearchResult { best, score, nodes: self.nodes, depth }
+	}
+
+	/// Every legal ply of `position` with its exact score `depth` plies deep, best first
+	pub fn analyze(&mut self, position: &Position, depth: u32) -> Vec<(Ply, i32)> {
+		self.nodes = 0;
+		self.clock = None;
+		let depth = depth.max(1);
+		let mut scored: Vec<(Ply, i32)> = position.legal_plies().into_iter()
+			.map(|ply| (ply, self.score_ply(position, ply, depth, -INFINITY, INFINITY, 0)))
+			.collect();
+		scored.sort_by_key(|(_, score)| -score);
+		scored
+	}
+
+	/// How many nodes the last search visited
+	pub fn nodes(&self) -> u64 {
+		self.nodes
+	}
+
+	/// Search `position` with iterative deepening until one of `limits` is reached,
+	/// the result is the one of the last completed depth. Depth 1 is always completed,
+	/// unless stopped by the `control`
+	pub fn iterative(&mut self, position: &Position, limits: &SearchLimits, clock: &'c dyn Clock) -> SearchResult {
+		self.nodes = 0;
+		self.aborted = false;
+		self.deadline = limits.time.map(|time| clock.elapsed() + time);
+		self.node_limit = limits.nodes;
+		let mut result = SearchResult { best: None, score: 0, nodes: 0, depth: 0 };
+		for depth in 1..=limits.max_depth.max(1) {
+			// without limits, depth 1 can't be aborted
+			self.clock = if depth > 1 { Some(clock) } else { None };
+			let (best, score) = self.root(position, depth, result.best);
+			if self.aborted {
+				break;
+			}
+			result = SearchResult { best, score, nodes: self.nodes, depth };
+			if let Some(control) = self.control {
+				control.report(Progress::Info { depth, score, nodes: self.nodes, best: best.map(Ply::into_move) });
+			}
+			// proven results, and searching beyond the end of the game, don't change anymore
+			if score.abs() > WIN_THRESHOLD || depth > position.empty_count() {
+				break;
+			}
+		}
+		log::debug!("Iterative deepening: depth {}, {} nodes", result.depth, self.nodes);
+		result.nodes = self.nodes;
+		result
+	}
+
+	/// Whether the deadline or the node limit is reached, or the search was stopped
+	fn out_of_budget(&mut self) -> bool {
+		if self.aborted {
+			return true;
+		}
+		if let Some(control) = self.control {
+			if self.nodes & (CHECK_INTERVAL - 1) == 0 && control.is_stopped() {
+				self.aborted = true;
+				return true;
+			}
+		}
+		let clock = match self.clock {
+			Some(clock) => clock,
+			None => return false,
+		};
+		match (self.node_limit, self.deadline) {
+			(Some(limit), _) if self.nodes >= limit => self.aborted = true,
+			(_, Some(deadline)) if self.nodes & (CHECK_INTERVAL - 1) == 0 => self.aborted = clock.elapsed() >= deadline,
+			_ => {},
+		}
+		self.aborted
+	}
+
+	/// The best ply and its score, `first` is tried first (e.g. the best ply of a shallower search)
+	fn root(&mut self, position: &Position, depth: u32, first: Option<Ply>) -> (Option<Ply>, i32) {
+		let mut plies = ordered_plies(position);
+		if let Some(idx) = first.and_then(|first| plies.iter().position(|ply| *ply == first)) {
+			let first = plies.remove(idx);
+			plies.insert(0, first);
+		}
+		let mut best = (None, -INFINITY);
+		let mut alpha = -INFINITY;
+		for ply in plies {
+			if self.aborted {
+				break;
+			}
+			let score = self.score_ply(position, ply, depth, alpha, INFINITY, 0);
+			if score > best.1 {
+				best = (Some(ply), score);
+			}
+			alpha = alpha.max(score);
+		}
+		if best.0.is_none() {
+			// no ply left, the board is full
+			best.1 = 0;
+		}
+		best
+	}
+
+	/// The score of playing `ply` in `position`, `height` plies below the root
+	fn score_ply(&mut self, position: &Position, ply: Ply, depth: u32, alpha: i32, beta: i32, height: i32) -> i32 {
+		if position.is_winning(ply) {
+			return WIN - height;
+		}
+		let child = position.play(ply);
+		if opponent_wins_next(&child) {
+			return -(WIN - height - 1);
+		}
+		-self.negamax(&child, depth - 1, -beta, -alpha, height + 1)
+	}
+
+	fn negamax(&mut self, position: &Position, depth: u32, mut alpha: i32, beta: i32, height: i32) -> i32 {
+		self.nodes += 1;
+		if self.out_of_budget() || position.is_full() {
+			return 0;
+		}
+		if depth == 0 {
+			return match self.evaluator {
+				Some(evaluator) => evaluator.evaluate(position),
+				None => evaluate(position),
+			};
+		}
+
+		let mut beta = beta;
+		let key = self.table.map(|_| transposition::hash(position));
+		if let (Some(table), Some(key)) = (self.table, key) {
+			match table.get(key) {
+				Some(entry) if entry.depth >= depth => {
+					let score = from_table(entry.score, height);
+					match entry.bound {
+						Bound::Exact => return score,
+						Bound::Lower => alpha = alpha.max(score),
+						Bound::Upper => beta = beta.min(score),
+					}
+					if alpha >= beta {
+						return score;
+					}
+				},
+				_ => {},
+			}
+		}
+
+		let alpha_orig = alpha;
+		let mut best = -INFINITY;
+		for ply in ordered_plies(position) {
+			let score = self.score_ply(position, ply, depth, alpha, beta, height);
+			best = best.max(score);
+			alpha = alpha.max(score);
+			if alpha >= beta {
+				break;
+			}
+		}
+
+		if let (Some(table), Some(key), false) = (self.table, key, self.aborted) {
+			let bound = if best <= alpha_orig {
+				Bound::Upper
+			} else if best >= beta {
+				Bound::Lower
+			} else {
+				Bound::Exact
+			};
+			table.insert(key, Entry { score: to_table(best, height), bound, depth });
+		}
+		best
+	}
+}
+
+/// Searches the plies of the root position on several threads at once (root splitting),
+/// sharing a lock-free transposition table. The best ply is searched first on its own,
+/// the others are then split between the threads, which share the best score found.
+/// With one thread the result is the one of a `Search` with a table, so it is deterministic
+#[derive(Debug)]
+pub struct ParallelSearch {
+	threads: usize,
+	table: TranspositionTable,
+}
+impl ParallelSearch {
+	/// A search on `threads` threads (at least 1) with a table of 2^20 entries
+	pub fn new(threads: usize) -> Self {
+		Self::with_table(threads, TranspositionTable::new(20))
+	}
+
+	/// A search on `threads` threads (at least 1) using `table`
+	pub fn with_table(threads: usize, table: TranspositionTable) -> Self {
+		Self { threads: threads.max(1), table }
+	}
+
+	/// Number of threads searching
+	pub fn threads(&self) -> usize {
+		self.threads
+	}
+
+	/// The table shared by the threads, kept between searches
+	pub fn table(&self) -> &TranspositionTable {
+		&self.table
+	}
+
+	/// Search `position` `depth` plies deep
+	pub fn run(&self, position: &Position, depth: u32) -> SearchResult {
+		self.search(position, depth, None)
+	}
+
+	/// Search `position` `depth` plies deep, scoring the leaves with `evaluator`
+	pub fn run_with_evaluator(&self, position: &Position, depth: u32, evaluator: &dyn Evaluator) -> SearchResult {
+		self.search(position, depth, Some(evaluator))
+	}
+
+	fn search(&self, position: &Position, depth: u32, evaluator: Option<&dyn Evaluator>) -> SearchResult {
+		let depth = depth.max(1);
+		let new_search = || {
+			let search = Search::with_table(&self.table);
+			match evaluator {
+				Some(evaluator) => search.evaluator(evaluator),
+				None => search,
+			}
+		};
+		if self.threads == 1 {
+			return new_search().run(position, depth);
+		}
+
+		let plies = ordered_plies(position);
+		let first = match plies.first() {
+			Some(first) => *first,
+			None => return new_search().run(position, depth),
+		};
+		let mut search = new_search();
+		let first_score = search.score_ply(position, first, depth, -INFINITY, INFINITY, 0);
+
+		let alpha = AtomicI32::new(first_score);
+		let next = AtomicUsize::new(1);
+		let nodes = AtomicU64::new(search.nodes);
+		let scores = Mutex::new(vec![(0, first_score)]);
+		std::thread::scope(|scope| {
+			for _ in 0..self.threads {
+				scope.spawn(|| {
+					let mut search = new_search();
+					loop {
+						let idx = next.fetch_add(1, Ordering::Relaxed);
+						let ply = match plies.get(idx) {
+							Some(ply) => *ply,
+							None => break,
+						};
+						let bound = alpha.load(Ordering::Relaxed);
+						let score = search.score_ply(position, ply, depth, bound, INFINITY, 0);
+						// otherwise the score is just an upper bound, not better than the best ply
+						if score > bound {
+							alpha.fetch_max(score, Ordering::Relaxed);
+							scores.lock().unwrap().push((idx, score));
+						}
+					}
+					nodes.fetch_add(search.nodes, Ordering::Relaxed);
+				});
+			}
+		});
+
+		// the highest score wins, on equal scores the ply ordered first
+		let scores = scores.into_inner().unwrap();
+		let (idx, score) = scores.iter()
+			.fold((0, -INFINITY), |best, (idx, score)| {
+				if *score > best.1 || (*score == best.1 && *idx < best.0) { (*idx, *score) } else { best }
+			});
+		SearchResult { best: Some(plies[idx]), score, nodes: nodes.into_inner(), depth }
+	}
+}
+
+/// Whether the player to move in `position` can complete a line right away
+pub(crate) fn opponent_wins_next(position: &Position) -> bool {
+	let unsafe_pieces = position.unsafe_pieces();
+	if position.free_choice() {
+		unsafe_pieces & position.available() != 0
+	} else {
+		match position.in_hand() {
+			Some(piece) => unsafe_pieces & (1 << piece) != 0,
+			None => false,
+		}
+	}
+}
+
+/// All legal plies, best candidates first: a winning ply if there is one,
+/// then plies after which the opponent can't win right away
+pub fn ordered_plies(position: &Position) -> Vec<Ply> {
+	let plies = position.legal_plies();
+	if let Some(winning) = plies.iter().find(|ply| position.is_winning(**ply)) {
+		// nothing beats winning right now
+		return vec![*winning];
+	}
+	let (mut safe, losing): (Vec<Ply>, Vec<Ply>) =
+		plies.into_iter().partition(|ply| !opponent_wins_next(&position.play(*ply)));
+	safe.extend(losing);
+	safe
+}
+
+/// Heuristic evaluation of a position without a winning ply for the player
+/// to move, from his point of view. Having few safe pieces left is bad for the
+/// player who has to give the next piece
+pub fn evaluate(position: &Position) -> i32 {
+	let unsafe_pieces = position.unsafe_pieces();
+	let available = position.available();
+	let safe = bits(available & !unsafe_pieces).count() as i32;
+	let threats = position.threat_count() as i32;
+	// after placing, the player to move has to give one of the safe pieces
+	let parity = if position.empty_count() & 1 == 0 { 1 } else { -1 };
+	safe * 10 - threats * 5 * parity
+}
+
+/// A game where the top row holds 3 big, light pieces and `piece` was given
+#[cfg(test)]
+fn three_big_pieces(piece: quart_lib::Piece) -> quart_lib::Game {
+	use quart_lib::{BPos, Game, Piece};
+	let mut game = Game::new();
+	for (x, big) in Piece::all().filter(|p| p.big).take(3).enumerate() {
+		game.select_next_piece(big).unwrap();
+		game.place_piece(BPos::new(x as u16, 0)).unwrap();
+	}
+	game.select_next_piece(piece).unwrap();
+	game
+}
+
+#[test]
+fn test_finds_immediate_win() {
+	use quart_lib::{BPos, Piece};
+	let game = three_big_pieces(Piece { big: true, dark: true, round: false, flat: false });
+	let result = Search::new().run(&Position::from_game(&game), 2);
+	assert_eq!(result.best.and_then(|ply| ply.pos()), Some(BPos::new(3, 0)));
+	assert_eq!(result.score, WIN);
+}
+
+#[test]
+fn test_avoids_losing_give() {
+	use quart_lib::Piece;
+	// a small, dark piece can't complete the row
+	let game = three_big_pieces(Piece { big: false, dark: true, round: false, flat: false });
+	let position = Position::from_game(&game);
+	for depth in 1..=2 {
+		let result = Search::new().run(&position, depth);
+		let ply = result.best.unwrap();
+		assert!(!opponent_wins_next(&position.play(ply)));
+		assert!(result.score > -WIN_THRESHOLD);
+	}
+}
+
+#[test]
+fn test_iterative_deepening_is_deterministic() {
+	use crate::clock::StepClock;
+	use quart_lib::Piece;
+	let game = three_big_pieces(Piece { big: false, dark: true, round: false, flat: false });
+	let position = Position::from_game(&game);
+	let limits = SearchLimits::time(Duration::from_millis(20));
+	let search = |limits: &SearchLimits| {
+		let clock = StepClock::new(Duration::from_millis(1));
+		Search::new().iterative(&position, limits, &clock)
+	};
+	let result = search(&limits);
+	assert!(result.depth >= 1 && result.depth < MAX_DEPTH);
+	assert!(result.best.is_some());
+	assert_eq!(search(&limits), result);
+
+	// the node limit stops right after depth 1
+	let result = search(&SearchLimits::depth(5).nodes(1));
+	assert_eq!(result.depth, 1);
+	assert_eq!(result.best, Search::new().run(&position, 1).best);
+}
+
+#[test]
+fn test_parallel_search() {
+	use quart_lib::Piece;
+	let game = three_big_pieces(Piece { big: false, dark: true, round: false, flat: false });
+	let position = Position::from_game(&game);
+	let single = ParallelSearch::new(1).run(&position, 3);
+	assert_eq!(ParallelSearch::new(1).run(&position, 3), single, "one thread is deterministic");
+
+	let parallel = ParallelSearch::new(4).run(&position, 3);
+	assert_eq!(parallel.score, single.score);
+	assert!(!opponent_wins_next(&position.play(parallel.best.unwrap())));
+}
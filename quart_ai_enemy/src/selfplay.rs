@@ -0,0 +1,275 @@
+use quart_lib::{Game, Players, TurnStructure, Winner};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use crate::{AgentConfig, AgentSetup, AiAgent, Checked};
+use crate::position::Position;
+use crate::search::Search;
+use crate::solver;
+
+/// The first line of every data file, followed by the format version
+const HEADER: &str = "quart-selfplay";
+/// The version of the data format, increased on incompatible changes
+pub const VERSION: u32 = 1;
+
+/// What self-play found out about a position, from the point of view of the player to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Sample {
+	/// The search score, see `search::WIN`, averaged over the games (rounded toward 0)
+	pub score: i32,
+	/// How often the game went on to be won
+	pub wins: u32,
+	/// How often the game went on to be drawn
+	pub draws: u32,
+	/// How often the game went on to be lost
+	pub losses: u32,
+}
+impl Sample {
+	/// Number of games the position occurred in
+	pub fn games(&self) -> u32 {
+		self.wins + self.draws + self.losses
+	}
+
+	/// The average outcome, from 0 (always lost) to 1 (always won)
+	pub fn result(&self) -> f64 {
+		(self.wins as f64 + self.draws as f64 / 2.0) / self.games().max(1) as f64
+	}
+}
+
+/// Positions of self-play games with their samples, keyed by the canonical position
+/// (see `solver::canonical`), so symmetric positions are stored only once.
+///
+/// The file format is text, one position per line after the header line `quart-selfplay 1`:
+/// the key in hex, the average search score, and how often the player to move won, drew and lost,
+/// like `0a5…3f 12 3 1 0`. `solver::from_canonical` turns the key back into a position
+#[derive(Debug)]
+pub struct Dataset {
+	samples: BTreeMap<u128, Sample>,
+	symmetries: Vec<[u8; 16]>,
+}
+
+impl Dataset {
+	/// An empty data set
+	pub fn new() -> Self {
+		Self {
+			samples: BTreeMap::new(),
+			symmetries: solver::symmetries(),
+		}
+	}
+
+	/// Number of distinct positions
+	pub fn len(&self) -> usize {
+		self.samples.len()
+	}
+
+	/// Whether there are no positions
+	pub fn is_empty(&self) -> bool {
+		self.samples.is_empty()
+	}
+
+	/// Record that `position` with the search `score` occurred in a game, which the
+	/// player to move went on to win (`Some(true)`), lose (`Some(false)`) or draw (`None`).
+	/// The score of the sample is the average of the scores of all games
+	pub fn add(&mut self, position: &Position, score: i32, won: Option<bool>) {
+		let key = solver::canonical(position, &self.symmetries);
+		let sample = self.samples.entry(key).or_default();
+		let games = sample.games() as i64;
+		sample.score = ((sample.score as i64 * games + score as i64) / (games + 1)) as i32;
+		match won {
+			Some(true) => sample.wins += 1,
+			Some(false) => sample.losses += 1,
+			None => sample.draws += 1,
+		}
+	}
+
+	/// The sample of `position`, if it occurred
+	pub fn get(&self, position: &Position) -> Option<Sample> {
+		self.samples.get(&solver::canonical(position, &self.symmetries)).copied()
+	}
+
+	/// All canonical positions with their samples, sorted by key
+	pub fn iter(&self) -> impl Iterator<Item = (Position, Sample)> + '_ {
+		self.samples.iter().map(|(key, sample)| (solver::from_canonical(*key), *sample))
+	}
+
+	/// Read a data set
+	///
+	/// Errors:
+	/// - InvalidData: not a data set, one of a different version or a malformed line
+	pub fn read<R: BufRead>(reader: R) -> io::Result<Self> {
+		let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+		let mut lines = reader.lines();
+		let header = lines.next().unwrap_or_else(|| Ok(String::new()))?;
+		if header != format!("{} {}", HEADER, VERSION) {
+			return Err(invalid(format!("unsupported self-play header {:?}", header)));
+		}
+		let mut dataset = Self::new();
+		for (nr, line) in lines.enumerate() {
+			let line = line?;
+			if line.trim().is_empty() {
+				continue;
+			}
+			let (key, sample) = parse_line(&line).ok_or_else(|| invalid(format!("line {}: malformed {:?}", nr + 2, line)))?;
+			dataset.samples.insert(key, sample);
+		}
+		Ok(dataset)
+	}
+
+	/// Write the data set, sorted by key so the output is deterministic
+	pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+		writeln!(writer, "{} {}", HEADER, VERSION)?;
+		for (key, sample) in self.samples.iter() {
+			writeln!(writer, "{:032x} {} {} {} {}", key, sample.score, sample.wins, sample.draws, sample.losses)?;
+		}
+		writer.flush()
+	}
+
+	/// Read the data set at `path`
+	pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		Self::read(BufReader::new(File::open(path)?))
+	}
+
+	/// Write the data set to the file at `path`
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+		self.write(BufWriter::new(File::create(path)?))
+	}
+}
+impl Default for Dataset {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+fn parse_line(line: &str) -> Option<(u128, Sample)> {
+	let mut fields = line.split_whitespace();
+	let key = u128::from_str_radix(fields.next()?, 16).ok()?;
+	let score = fields.next()?.parse().ok()?;
+	let mut count = || fields.next()?.parse::<u32>().ok();
+	let sample = Sample { score, wins: count()?, draws: count()?, losses: count()? };
+	Some((key, sample))
+}
+
+/// Lets two agents play games against each other, recording every position
+/// with its search score and the outcome of the game
+#[derive(Debug, Clone)]
+pub struct SelfPlay {
+	/// The agents, taking turns in who moves first
+	pub agents: [AgentConfig; 2],
+	/// How many games to play
+	pub games: u32,
+	/// Seed for the random opening plies and the agents, so the same games are played every time
+	pub seed: u64,
+	/// How many plies at the start of every game are played at random, so the games differ
+	pub random_plies: u32,
+	/// How many plies deep positions are searched for their score
+	pub score_depth: u32,
+	/// Whether players choose their own pieces, see `TurnStructure::FreeChoice`
+	pub free_choice: bool,
+}
+impl SelfPlay {
+	/// `games` games between `agents`, with 2 random opening plies
+	pub fn new(agents: [AgentConfig; 2], games: u32) -> Self {
+		Self {
+			agents,
+			games,
+			seed: 0,
+			random_plies: 2,
+			score_depth: 2,
+			free_choice: false,
+		}
+	}
+
+	/// Play the games, the data of all of them
+	pub fn run(&self) -> Dataset {
+		let mut dataset = Dataset::new();
+		self.extend(&mut dataset);
+		dataset
+	}
+
+	/// Play the games, adding their data to `dataset`.
+	/// Games in which an agent fails to move are left out
+	pub fn extend(&self, dataset: &mut Dataset) {
+		let mut rng = StdRng::seed_from_u64(self.seed);
+		for nr in 0..self.games {
+			let first = nr as usize & 1;
+			let samples = match self.play(first, &mut rng) {
+				Some(samples) => samples,
+				None => continue,
+			};
+			for (position, score, won) in samples {
+				dataset.add(&position, score, won);
+			}
+		}
+	}
+
+	/// Play a game with `agents[first]` moving first, the positions with their score
+	/// and whether the player to move won
+	fn play(&self, first: usize, rng: &mut StdRng) -> Option<Vec<(Position, i32, Option<bool>)>> {
+		let turn_structure = if self.free_choice { TurnStructure::FreeChoice } else { TurnStructure::GivePiece };
+		let mut game = Game::with_players(turn_structure, Players::two());
+		let mut agents: Vec<Checked<Box<dyn AiAgent + Send>>> = [self.agents[first], self.agents[1 - first]].iter()
+			.map(|config| Checked::new(config.create_with(&game, &AgentSetup { seed: Some(rng.gen()), ..AgentSetup::default() })))
+			.collect();
+		let mut positions = Vec::new();
+		while !game.is_over() {
+			let position = Position::from_game(&game);
+			let score = Search::new().run(&position, self.score_depth).score;
+			positions.push((position, score, game.player_turn));
+			let mov = if positions.len() as u32 <= self.random_plies {
+				position.legal_plies().choose(rng)?.into_move()
+			} else {
+				match agents[game.player_turn as usize - 1].play(&game) {
+					Ok(mov) => mov,
+					Err(e) => {
+						log::warn!("Self-play game aborted: {}", e);
+						return None;
+					},
+				}
+			};
+			mov.apply(&mut game).ok()?;
+		}
+		let winner = match game.winner() {
+			Some(Winner::Player(player)) => Some(player),
+			_ => None,
+		};
+		Some(positions.into_iter()
+			.map(|(position, score, player)| (position, score, winner.map(|winner| winner == player)))
+			.collect())
+	}
+}
+
+#[test]
+fn test_selfplay() {
+	let agents = [AgentConfig::by_name("random").unwrap(), AgentConfig::by_name("greedy").unwrap()];
+	let selfplay = SelfPlay { seed: 7, ..SelfPlay::new(agents, 4) };
+	let dataset = selfplay.run();
+	// the first turn of every game gives a piece, which is the same position up to symmetry
+	let start = dataset.get(&Position::new(false)).unwrap();
+	assert_eq!(start.games(), 4);
+	assert!(dataset.len() > 10);
+	for (position, sample) in dataset.iter() {
+		assert_eq!(dataset.get(&position), Some(sample));
+	}
+
+	let mut file = Vec::new();
+	dataset.write(&mut file).unwrap();
+	let read = Dataset::read(io::Cursor::new(&file)).unwrap();
+	assert_eq!(read.samples, dataset.samples);
+	assert!(Dataset::read(io::Cursor::new("quart-selfplay 1\n1f 3 1\n")).is_err());
+
+	let mut dataset = Dataset::new();
+	for (score, won) in [(30, Some(true)), (-10, None), (40, Some(false))].iter() {
+		dataset.add(&Position::new(false), *score, *won);
+	}
+	assert_eq!(dataset.get(&Position::new(false)), Some(Sample { score: 20, wins: 1, draws: 1, losses: 1 }));
+}
+
+#[test]
+fn test_selfplay_is_reproducible() {
+	let agents = [AgentConfig::by_name("random").unwrap(), AgentConfig::by_name("human").unwrap()];
+	let selfplay = SelfPlay { seed: 5, random_plies: 0, score_depth: 1, ..SelfPlay::new(agents, 4) };
+	assert_eq!(selfplay.run().samples, selfplay.run().samples);
+}
@@ -0,0 +1,420 @@
+use quart_lib::Game;
+use std::collections::HashMap;
+use crate::position::{Ply, Position};
+use crate::runner::Control;
+use crate::search::{opponent_wins_next, CHECK_INTERVAL, ordered_plies, WIN, WIN_THRESHOLD};
+use crate::transposition::{from_table, to_table, Bound};
+
+/// Positions with at most this many empty cells are usually solved quickly
+pub const ENDGAME_EMPTY: u32 = 8;
+/// How many nodes agents let the solver visit before falling back to their own search
+pub const ENDGAME_NODES: u64 = 2_000_000;
+/// The transposition table is cleared once it holds this many entries
+const MAX_ENTRIES: usize = 1 << 22;
+/// Larger than any score
+const INFINITY: i32 = WIN + 1;
+
+/// The result of a game with perfect play, from the point of view of the player to move.
+/// The distance is the number of plies until the game ends, the last one included
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Outcome {
+	/// The player to move wins
+	Win(u32),
+	/// The player to move loses
+	Loss(u32),
+	/// Nobody completes a line
+	Draw(u32),
+}
+impl Outcome {
+	/// A score comparable to those of `search::Search`: faster wins and slower losses score higher
+	pub fn score(self) -> i32 {
+		match self {
+			Outcome::Win(distance) => WIN - distance as i32 + 1,
+			Outcome::Loss(distance) => -(WIN - distance as i32 + 1),
+			Outcome::Draw(_) => 0,
+		}
+	}
+
+	/// The outcome for the player who moved into the position, one ply earlier
+	pub fn before(self) -> Outcome {
+		match self {
+			Outcome::Win(distance) => Outcome::Loss(distance + 1),
+			Outcome::Loss(distance) => Outcome::Win(distance + 1),
+			Outcome::Draw(distance) => Outcome::Draw(distance + 1),
+		}
+	}
+}
+
+/// A solved position
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solution {
+	/// The result with perfect play
+	pub outcome: Outcome,
+	/// All plies reaching that result (winning as fast or losing as slowly as possible)
+	pub best: Vec<Ply>,
+}
+
+/// An entry of the transposition table. Win and loss scores are stored
+/// relative to the position, not to the root of the search
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+	score: i32,
+	bound: Bound,
+}
+
+/// Exact solver for (end game) positions: a full-depth negamax with alpha-beta pruning
+/// and a transposition table keyed by the symmetry-reduced position.
+/// The table is kept between calls, so reusing a `Solver` gets faster over a game
+#[derive(Debug)]
+pub struct Solver {
+	table: HashMap<u128, Entry>,
+	symmetries: Vec<[u8; 16]>,
+	node_limit: Option<u64>,
+	nodes: u64,
+	aborted: bool,
+}
+
+impl Solver {
+	/// A solver without node limit
+	pub fn new() -> Self {
+		Self {
+			table: HashMap::new(),
+			symmetries: symmetries(),
+			node_limit: None,
+			nodes: 0,
+			aborted: false,
+		}
+	}
+
+	/// Give up solving after visiting `limit` nodes
+	pub fn node_limit(mut self, limit: u64) -> Self {
+		self.node_limit = Some(limit);
+		self
+	}
+
+	/// How many nodes the last call to `solve` visited
+	pub fn nodes(&self) -> u64 {
+		self.nodes
+	}
+
+	/// Solve the position of `game`, see `solve`
+	pub fn solve_game(&mut self, game: &Game) -> Option<Solution> {
+		self.solve(&Position::from_game(game))
+	}
+
+	/// Solve `position`, `None` if the node limit was hit
+	pub fn solve(&mut self, position: &Position) -> Option<Solution> {
+		self.solve_controlled(position, None)
+	}
+
+	/// Solve `position`, `None` if the node limit was hit or `control` stopped the solver
+	pub fn solve_controlled(&mut self, position: &Position, control: Option<&Control>) -> Option<Solution> {
+		self.nodes = 0;
+		self.aborted = false;
+		if self.table.len() > MAX_ENTRIES {
+			self.table.clear();
+		}
+		let value = self.negamax(position, -INFINITY, INFINITY, 0, control);
+		let mut best = Vec::new();
+		for ply in position.legal_plies() {
+			// a null window around the value tells whether the ply reaches it
+			if self.score_ply(position, ply, value - 1, value, 0, control) >= value {
+				best.push(ply);
+			}
+		}
+		if self.aborted {
+			return None;
+		}
+		let outcome = if value > WIN_THRESHOLD {
+			Outcome::Win((WIN - value + 1) as u32)
+		} else if value < -WIN_THRESHOLD {
+			Outcome::Loss((WIN + value + 1) as u32)
+		} else {
+			Outcome::Draw(position.empty_count())
+		};
+		Some(Solution { outcome, best })
+	}
+
+	/// The score of playing `ply` in `position`, `height` plies below the root
+	fn score_ply(&mut self, position: &Position, ply: Ply, alpha: i32, beta: i32, height: i32, control: Option<&Control>) -> i32 {
+		if position.is_winning(ply) {
+			return WIN - height;
+		}
+		let child = position.play(ply);
+		if opponent_wins_next(&child) {
+			return -(WIN - height - 1);
+		}
+		-self.negamax(&child, -beta, -alpha, height + 1, control)
+	}
+
+	fn negamax(&mut self, position: &Position, mut alpha: i32, mut beta: i32, height: i32, control: Option<&Control>) -> i32 {
+		self.nodes += 1;
+		match self.node_limit {
+			Some(limit) if self.nodes > limit => self.aborted = true,
+			_ if self.nodes & (CHECK_INTERVAL - 1) == 0 => self.aborted = control.is_some_and(Control::is_stopped),
+			_ => {},
+		}
+		if self.aborted || position.is_full() {
+			return 0;
+		}
+
+		let key = self.key(position);
+		if let Some(entry) = self.table.get(&key) {
+			let score = from_table(entry.score, height);
+			match entry.bound {
+				Bound::Exact => return score,
+				Bound::Lower => alpha = alpha.max(score),
+				Bound::Upper => beta = beta.min(score),
+			}
+			if alpha >= beta {
+				return score;
+			}
+		}
+
+		let alpha_orig = alpha;
+		let mut best = -INFINITY;
+		for ply in ordered_plies(position) {
+			let score = self.score_ply(position, ply, alpha, beta, height, control);
+			best = best.max(score);
+			alpha = alpha.max(score);
+			if alpha >= beta {
+				break;
+			}
+		}
+
+		if !self.aborted {
+			let bound = if best <= alpha_orig {
+				Bound::Upper
+			} else if best >= beta {
+				Bound::Lower
+			} else {
+				Bound::Exact
+			};
+			self.table.insert(key, Entry { score: to_table(best, height), bound });
+		}
+		best
+	}
+
+	/// The key of `position` in the transposition table, see `canonical`
+	fn key(&self, position: &Position) -> u128 {
+		canonical(position, &self.symmetries)
+	}
+}
+impl Default for Solver {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// A key for `position`, the same for all positions that are equivalent
+/// under `symmetries` (see `symmetries()`) and flipping attributes
+pub fn canonical(position: &Position, symmetries: &[[u8; 16]]) -> u128 {
+	canonical_transform(position, symmetries).0
+}
+
+/// The canonical position a key of `canonical` stands for
+pub fn from_canonical(key: u128) -> Position {
+	let mut position = Position::new(key & 1 == 1);
+	for cell in 0..16u8 {
+		let piece = (key >> (1 + 5 * (16 - cell as u32))) as u8 & 0x1F;
+		if piece < 16 {
+			position = position.play(Ply { place: Some((cell, piece)), give: None });
+		}
+	}
+	match (key >> 1) as u8 & 0x1F {
+		16 => position,
+		in_hand => position.play(Ply { place: None, give: Some(in_hand) }),
+	}
+}
+
+/// Like `canonical`, together with the transformation leading to the canonical position
+pub fn canonical_transform(position: &Position, symmetries: &[[u8; 16]]) -> (u128, Transform) {
+	symmetries.iter()
+		.map(|symmetry| {
+			let (key, flip) = canonical_key(position, symmetry);
+			(key, Transform { symmetry: *symmetry, flip })
+		})
+		.min_by_key(|(key, _)| *key)
+		.unwrap_or((0, Transform { symmetry: symmetries[0], flip: 0 }))
+}
+
+/// Moves cells by a symmetry and flips (xor-s) the attributes of all pieces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transform {
+	symmetry: [u8; 16],
+	flip: u8,
+}
+impl Transform {
+	/// `ply` in the transformed position
+	pub fn apply(&self, ply: Ply) -> Ply {
+		Ply {
+			place: ply.place.map(|(cell, piece)| (self.symmetry[cell as usize], piece ^ self.flip)),
+			give: ply.give.map(|piece| piece ^ self.flip),
+		}
+	}
+
+	/// The inverse of `apply`: `ply` in the original position
+	pub fn revert(&self, ply: Ply) -> Ply {
+		let cell_of = |target: u8| self.symmetry.iter().position(|c| *c == target).unwrap_or(0) as u8;
+		Ply {
+			place: ply.place.map(|(target, piece)| (cell_of(target), piece ^ self.flip)),
+			give: ply.give.map(|piece| piece ^ self.flip),
+		}
+	}
+}
+
+/// `position` with its cells moved by `symmetry` and its pieces flipped (xor-ed),
+/// so the first occupied cell holds piece 0, packed into an integer. Also returns the flip
+fn canonical_key(position: &Position, symmetry: &[u8; 16]) -> (u128, u8) {
+	let mut cells = [None; 16];
+	for (cell, target) in symmetry.iter().enumerate() {
+		cells[*target as usize] = position.piece_at(cell as u8);
+	}
+	let flip = cells.iter().find_map(|c| *c).or_else(|| position.in_hand()).unwrap_or(0);
+	let mut key = 0u128;
+	for cell in cells.iter() {
+		key = key << 5 | cell.map_or(16, |piece| piece ^ flip) as u128;
+	}
+	key = key << 5 | position.in_hand().map_or(16, |piece| piece ^ flip) as u128;
+	(key << 1 | position.free_choice() as u128, flip)
+}
+
+/// All 32 permutations of the cells which map every line onto a line:
+/// the rotations and reflections of the board, swapping the inner with the outer
+/// rows and columns, and swapping the two inner and the two outer ones each
+pub fn symmetries() -> Vec<[u8; 16]> {
+	fn from_fn(f: impl Fn(u8, u8) -> (u8, u8)) -> [u8; 16] {
+		let mut perm = [0; 16];
+		for (cell, target) in perm.iter_mut().enumerate() {
+			let (x, y) = f(cell as u8 % 4, cell as u8 / 4);
+			*target = y * 4 + x;
+		}
+		perm
+	}
+	let inner_outer = [1, 0, 3, 2];
+	let swap_inner = [0, 2, 1, 3];
+	let generators = [
+		from_fn(|x, y| (3 - y, x)),
+		from_fn(|x, y| (y, x)),
+		from_fn(|x, y| (inner_outer[x as usize], inner_outer[y as usize])),
+		from_fn(|x, y| (swap_inner[x as usize], swap_inner[y as usize])),
+	];
+
+	let mut group = vec![from_fn(|x, y| (x, y))];
+	let mut i = 0;
+	while i < group.len() {
+		for generator in generators.iter() {
+			let mut composed = [0; 16];
+			for (cell, target) in composed.iter_mut().enumerate() {
+				*target = generator[group[i][cell] as usize];
+			}
+			if !group.contains(&composed) {
+				group.push(composed);
+			}
+		}
+		i += 1;
+	}
+	group
+}
+
+#[test]
+fn test_symmetries() {
+	use crate::position::LINES;
+	let symmetries = symmetries();
+	assert_eq!(symmetries.len(), 32);
+	let mut lines: Vec<[u8; 4]> = LINES.iter().map(|l| { let mut l = *l; l.sort_unstable(); l }).collect();
+	lines.sort_unstable();
+	for symmetry in symmetries.iter() {
+		let mut mapped: Vec<[u8; 4]> = LINES.iter()
+			.map(|line| {
+				let mut mapped = [0; 4];
+				for (m, cell) in mapped.iter_mut().zip(line.iter()) {
+					*m = symmetry[*cell as usize];
+				}
+				mapped.sort_unstable();
+				mapped
+			})
+			.collect();
+		mapped.sort_unstable();
+		assert_eq!(mapped, lines);
+	}
+}
+
+/// A random position with `empty` empty cells and no completed line, with a piece in hand
+#[cfg(test)]
+fn random_position(seed: u64, empty: u32, free_choice: bool) -> Position {
+	use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+	let mut rng = StdRng::seed_from_u64(seed);
+	'retry: loop {
+		let mut position = Position::new(free_choice);
+		while position.empty_count() > empty || (!free_choice && position.in_hand().is_none()) {
+			let plies: Vec<Ply> = position.legal_plies().into_iter()
+				.filter(|ply| !position.is_winning(*ply))
+				.collect();
+			match plies.choose(&mut rng) {
+				Some(ply) => position = position.play(*ply),
+				None => continue 'retry,
+			}
+		}
+		return position;
+	}
+}
+
+/// Plain negamax without any pruning, as reference, scored like `Solver::solve`
+#[cfg(test)]
+fn brute_force(position: &Position, height: i32) -> i32 {
+	position.legal_plies().into_iter()
+		.map(|ply| if position.is_winning(ply) { WIN - height } else { -brute_force(&position.play(ply), height + 1) })
+		.max()
+		.unwrap_or(0)
+}
+
+#[test]
+fn test_solver_matches_brute_force() {
+	let mut solver = Solver::new();
+	for seed in 0..12 {
+		let position = random_position(seed, 4, seed % 2 == 0);
+		let expected = brute_force(&position, 0);
+		let solution = solver.solve(&position).unwrap();
+		assert_eq!(solution.outcome.score(), expected, "{:?}", position);
+		assert!(!solution.best.is_empty());
+		for ply in solution.best {
+			let score = if position.is_winning(ply) { WIN } else { -brute_force(&position.play(ply), 1) };
+			assert_eq!(score, expected);
+		}
+	}
+}
+
+#[test]
+fn test_symmetric_positions_share_key() {
+	let symmetries = symmetries();
+	let position = Position::new(false)
+		.play(Ply { place: None, give: Some(3) })
+		.play(Ply { place: Some((6, 3)), give: Some(12) });
+	let key = canonical(&position, &symmetries);
+	assert_eq!(canonical(&from_canonical(key), &symmetries), key);
+
+	let solver = Solver::new();
+	let position = random_position(3, 9, false);
+	// rotate the board and flip the size of every piece
+	let mut rotated = Position::new(false);
+	for cell in 0..16u8 {
+		if let Some(piece) = position.piece_at(cell) {
+			let (x, y) = (cell % 4, cell / 4);
+			let target = x * 4 + (3 - y);
+			rotated = rotated.play(Ply { place: Some((target, piece ^ 0b1000)), give: None });
+		}
+	}
+	rotated = rotated.play(Ply { place: None, give: position.in_hand().map(|piece| piece ^ 0b1000) });
+	assert_eq!(solver.key(&rotated), solver.key(&position));
+	assert!(Solver::new().node_limit(ENDGAME_NODES).solve(&position).is_some());
+}
+
+#[test]
+fn test_solver_stopped() {
+	let position = random_position(5, 12, false);
+	let control = Control::new();
+	control.stop();
+	let mut solver = Solver::new();
+	assert!(solver.solve_controlled(&position, Some(&control)).is_none());
+}
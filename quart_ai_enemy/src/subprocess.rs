@@ -0,0 +1,337 @@
+use quart_lib::{Game, Piece};
+use std::ffi::OsStr;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command as Process, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::{AgentError, AiAgent, Control, Move, Placement, Progress};
+use crate::analysis::ScoredMove;
+use crate::position::Position;
+use crate::protocol::{Command, Reply};
+use crate::search::SearchLimits;
+
+/// How long an engine may take to start up and to answer `isready`
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long an engine may take to exit after `quit`
+const QUIT_TIMEOUT: Duration = Duration::from_secs(1);
+/// How often a search checks whether its `Control` stopped it
+const CONTROL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// An external engine, talked to over its stdin and stdout with the text
+/// protocol of `protocol::Command`. It may be written in any language.
+///
+/// An engine that crashes, times out or replies nonsense makes every move
+/// fail with `AgentError::Engine`, and is killed on drop if it doesn't quit
+pub struct SubprocessAgent {
+	child: Child,
+	stdin: ChildStdin,
+	lines: Receiver<String>,
+	name: String,
+	options: Vec<(String, String)>,
+	limits: Option<SearchLimits>,
+	timeout: Duration,
+	failed: bool,
+}
+impl SubprocessAgent {
+	/// Start the engine `program` with `args`, and do the handshake
+	///
+	/// Errors:
+	/// - the program can't be started
+	/// - TimedOut: the engine didn't finish the handshake in time
+	/// - UnexpectedEof: the engine exited
+	pub fn spawn<S: AsRef<OsStr>>(program: S, args: &[S]) -> io::Result<Self> {
+		let mut child = Process::new(program)
+			.args(args)
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.spawn()?;
+		let stdin = child.stdin.take().expect("stdin is piped");
+		let stdout = child.stdout.take().expect("stdout is piped");
+		let (sender, lines) = mpsc::channel();
+		// the channel disconnects when the engine exits
+		thread::spawn(move || {
+			for line in BufReader::new(stdout).lines() {
+				let line = match line {
+					Ok(line) => line,
+					Err(_) => break,
+				};
+				if sender.send(line).is_err() {
+					break;
+				}
+			}
+		});
+		let mut agent = Self {
+			child,
+			stdin,
+			lines,
+			name: String::new(),
+			options: Vec::new(),
+			limits: None,
+			timeout: Duration::from_secs(10),
+			failed: false,
+		};
+		agent.handshake()?;
+		Ok(agent)
+	}
+
+	/// Limit the search of every move, sent with `go`. By default the engine decides
+	pub fn limits(mut self, limits: SearchLimits) -> Self {
+		self.limits = Some(limits);
+		self
+	}
+
+	/// How long the engine may take for a move beyond the time limit, 10 seconds by default
+	pub fn timeout(mut self, timeout: Duration) -> Self {
+		self.timeout = timeout;
+		self
+	}
+
+	/// The name the engine told, empty if it didn't
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	/// The options of the engine with their default values
+	pub fn options(&self) -> &[(String, String)] {
+		&self.options
+	}
+
+	/// Set the option `name` of the engine to `value`, and wait for the engine to take it
+	pub fn set_option(&mut self, name: &str, value: &str) -> io::Result<()> {
+		self.send(&Command::SetOption { name: name.to_string(), value: value.to_string() })?;
+		self.sync()
+	}
+
+	fn handshake(&mut self) -> io::Result<()> {
+		self.send(&Command::Qei)?;
+		let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+		loop {
+			match self.receive(deadline)? {
+				Reply::Id { key, value } if key == "name" => self.name = value,
+				Reply::Option { name, default } => self.options.push((name, default)),
+				Reply::QeiOk => return Ok(()),
+				_ => {},
+			}
+		}
+	}
+
+	/// Wait for the engine to be done with all commands
+	fn sync(&mut self) -> io::Result<()> {
+		self.send(&Command::IsReady)?;
+		let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+		loop {
+			if let Reply::ReadyOk = self.receive(deadline)? {
+				return Ok(());
+			}
+		}
+	}
+
+	fn send(&mut self, command: &Command) -> io::Result<()> {
+		log::trace!("> {}", command);
+		writeln!(self.stdin, "{}", command)?;
+		self.stdin.flush()
+	}
+
+	/// The next reply of the engine. Lines that aren't replies are logged and skipped,
+	/// so are errors the engine reports
+	fn receive(&mut self, deadline: Instant) -> io::Result<Reply> {
+		loop {
+			let timeout = deadline.saturating_duration_since(Instant::now());
+			let line = match self.lines.recv_timeout(timeout) {
+				Ok(line) => line,
+				Err(RecvTimeoutError::Timeout) => return Err(io::Error::new(io::ErrorKind::TimedOut, "the engine didn't reply in time")),
+				Err(RecvTimeoutError::Disconnected) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the engine exited")),
+			};
+			log::trace!("< {}", line);
+			match line.parse() {
+				Ok(Reply::Error(e)) => log::warn!("Engine {:?} reported: {}", self.name, e),
+				Ok(reply) => return Ok(reply),
+				Err(e) => log::warn!("Engine {:?}: {}", self.name, e),
+			}
+		}
+	}
+
+	/// Send `position` and `command`, and collect the replies until `bestmove`.
+	/// If the engine takes too long, or `control` stops the search, it is told to stop.
+	/// `info` replies are reported to `control`. After an error, the engine can't be
+	/// trusted anymore and every search fails
+	fn search(&mut self, position: &Position, command: Command, time: Option<Duration>, control: Option<&Control>) -> io::Result<(Option<Move>, Vec<Reply>)> {
+		if self.failed {
+			return Err(io::Error::other("the engine failed before"));
+		}
+		let result = self.search_once(position, command, time, control);
+		self.failed = result.is_err();
+		result
+	}
+
+	fn search_once(&mut self, position: &Position, command: Command, time: Option<Duration>, control: Option<&Control>) -> io::Result<(Option<Move>, Vec<Reply>)> {
+		self.send(&Command::Position(*position))?;
+		self.send(&command)?;
+		let mut deadline = Instant::now() + time.unwrap_or_default() + self.timeout;
+		let mut stopped = false;
+		let mut replies = Vec::new();
+		loop {
+			if !stopped && control.is_some_and(Control::is_stopped) {
+				self.send(&Command::Stop)?;
+				stopped = true;
+				deadline = Instant::now() + self.timeout;
+			}
+			let until = match control {
+				Some(_) if !stopped => deadline.min(Instant::now() + CONTROL_INTERVAL),
+				_ => deadline,
+			};
+			let reply = match self.receive(until) {
+				Err(e) if e.kind() == io::ErrorKind::TimedOut && Instant::now() < deadline => continue,
+				Err(e) if e.kind() == io::ErrorKind::TimedOut && !stopped => {
+					log::warn!("Engine {:?} takes too long, stopping it", self.name);
+					self.send(&Command::Stop)?;
+					stopped = true;
+					deadline += self.timeout;
+					continue;
+				},
+				reply => reply?,
+			};
+			match reply {
+				Reply::BestMove(mov) => return Ok((mov, replies)),
+				Reply::Info { depth, score, nodes, ref pv } => {
+					if let Some(control) = control {
+						control.report(Progress::Info { depth, score, nodes, best: pv.first().copied() });
+					}
+					replies.push(reply);
+				},
+				reply => replies.push(reply),
+			}
+		}
+	}
+
+	/// The move of the engine in `position`
+	fn go(&mut self, position: &Position, control: Option<&Control>) -> Result<Move, AgentError> {
+		let time = self.limits.and_then(|limits| limits.time);
+		match self.search(position, Command::Go(self.limits), time, control) {
+			Ok((Some(mov), _)) => Ok(mov),
+			Ok((None, _)) => Err(AgentError::NoMove),
+			Err(e) => {
+				log::warn!("Engine {:?} failed: {}", self.name, e);
+				Err(AgentError::Engine(e))
+			},
+		}
+	}
+}
+impl AiAgent for SubprocessAgent {
+	fn choose_placement(&mut self, game: &Game) -> Option<Placement> {
+		self.go(&Position::from_game(game), None).ok()?.placement
+	}
+
+	fn choose_give(&mut self, game: &Game, placement: Option<Placement>) -> Option<Piece> {
+		let position = Position::from_game(game).before_give(placement.map(Placement::indices))?;
+		self.go(&position, None).ok()?.give
+	}
+
+	fn play(&mut self, game: &Game) -> Result<Move, AgentError> {
+		if game.is_over() {
+			return Err(AgentError::NoMove);
+		}
+		self.go(&Position::from_game(game), None)
+	}
+
+	fn play_controlled(&mut self, game: &Game, control: &Control) -> Result<Move, AgentError> {
+		if game.is_over() {
+			return Err(AgentError::NoMove);
+		}
+		self.go(&Position::from_game(game), Some(control))
+	}
+
+	fn analyze(&mut self, game: &Game, limits: &SearchLimits) -> Vec<ScoredMove> {
+		match self.search(&Position::from_game(game), Command::Analyze(*limits), limits.time, None) {
+			Ok((_, replies)) => replies.into_iter()
+				.filter_map(|reply| match reply {
+					Reply::Scored(scored) => Some(scored),
+					_ => None,
+				})
+				.collect(),
+			Err(e) => {
+				log::warn!("Engine {:?} failed to analyze: {}", self.name, e);
+				Vec::new()
+			},
+		}
+	}
+
+	fn game_over(&mut self, _game: &Game) {
+		if let Err(e) = self.send(&Command::NewGame) {
+			log::warn!("Engine {:?} failed: {}", self.name, e);
+		}
+	}
+}
+impl Drop for SubprocessAgent {
+	fn drop(&mut self) {
+		let _ = self.send(&Command::Quit);
+		let deadline = Instant::now() + QUIT_TIMEOUT;
+		while Instant::now() < deadline {
+			match self.child.try_wait() {
+				Ok(Some(_)) => return,
+				Ok(None) => thread::sleep(Duration::from_millis(10)),
+				Err(_) => break,
+			}
+		}
+		log::warn!("Engine {:?} didn't quit, killing it", self.name);
+		let _ = self.child.kill();
+		let _ = self.child.wait();
+	}
+}
+
+#[cfg(unix)]
+#[test]
+fn test_subprocess_agent() {
+	// an engine always giving the same piece, which hangs on `go depth 1`, exits on `go depth 2`
+	// and searches on `go depth 3` until it is stopped
+	let script = r#"
+		while read command; do
+			case "$command" in
+				qei) echo "id name echo engine"; echo "option name style default stubborn"; echo qeiok ;;
+				isready) echo readyok ;;
+				"go depth 1") ;;
+				"go depth 2") exit 1 ;;
+				"go depth 3") searching=1; echo "info depth 1 score 5 nodes 9 pv /bdrf" ;;
+				stop) [ -n "$searching" ] && echo "bestmove /bdrf"; searching= ;;
+				go*) echo "info depth 1 score 0 nodes 1"; echo "bestmove /bdrf" ;;
+				quit) exit 0 ;;
+			esac
+		done
+	"#;
+	let spawn = || SubprocessAgent::spawn("sh", &["-c", script]).unwrap().timeout(Duration::from_millis(200));
+	let game = Game::new();
+	let mut agent = spawn();
+	assert_eq!(agent.name(), "echo engine");
+	assert_eq!(agent.options(), &[("style".to_string(), "stubborn".to_string())]);
+	agent.set_option("style", "very stubborn").unwrap();
+	let mov = agent.play(&game).unwrap();
+	assert_eq!(mov.to_string(), "/bdrf");
+
+	let mut hanging = spawn().limits(SearchLimits::depth(1));
+	match hanging.play(&game) {
+		Err(AgentError::Engine(e)) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+		result => panic!("no timeout: {:?}", result),
+	}
+	let mut crashing = spawn().limits(SearchLimits::depth(2));
+	match crashing.play(&game) {
+		Err(AgentError::Engine(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+		result => panic!("no crash: {:?}", result),
+	}
+	assert!(crashing.play(&game).is_err());
+
+	let mut searching = spawn().limits(SearchLimits::depth(3)).timeout(Duration::from_secs(10));
+	let (sender, progress) = mpsc::channel();
+	let control = Control::with_progress(sender);
+	let started = Instant::now();
+	thread::scope(|scope| {
+		let control = &control;
+		scope.spawn(move || {
+			assert!(matches!(progress.recv(), Ok(Progress::Info { depth: 1, score: 5, nodes: 9, best: Some(_) })));
+			control.stop();
+		});
+		assert_eq!(searching.play_controlled(&game, control).unwrap().to_string(), "/bdrf");
+	});
+	assert!(started.elapsed() < Duration::from_secs(5));
+	assert!(SubprocessAgent::spawn("/nonexistent/engine", &[]).is_err());
+}
@@ -0,0 +1,463 @@
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use crate::position::{bits, Ply, Position};
+use crate::runner::Control;
+use crate::solver::{self, Outcome, Solver, ENDGAME_EMPTY, ENDGAME_NODES};
+
+/// Every tablebase file starts with these bytes
+const MAGIC: &[u8; 8] = b"QUARTTB\0";
+/// The version of the file format, increased on incompatible changes
+pub const VERSION: u16 = 1;
+/// Bytes of the header: magic, version, max empty cells, a reserved byte and the entry count
+const HEADER_LEN: u64 = 8 + 2 + 1 + 1 + 8;
+/// Bytes of an entry: the canonical key (see `solver::canonical`) and the outcome
+const ENTRY_LEN: u64 = 16 + 1;
+
+/// Pack an outcome into one byte: the distance, negated for a loss, 0 for a draw
+/// (the distance of a draw follows from the number of empty cells)
+fn encode(outcome: Outcome) -> i8 {
+	match outcome {
+		Outcome::Win(distance) => distance as i8,
+		Outcome::Loss(distance) => -(distance as i8),
+		Outcome::Draw(_) => 0,
+	}
+}
+
+/// The inverse of `encode`
+fn decode(value: i8, position: &Position) -> Outcome {
+	match value {
+		0 => Outcome::Draw(position.empty_count()),
+		d if d > 0 => Outcome::Win(d as u32),
+		d => Outcome::Loss(-d as u32),
+	}
+}
+
+/// Whether `position` belongs in a tablebase with up to `max_empty` empty cells:
+/// it is small enough, and the game isn't over yet
+fn in_scope(position: &Position, max_empty: u32) -> bool {
+	position.empty_count() <= max_empty && !position.is_full() && !position.is_won()
+		&& (position.free_choice() || position.in_hand().is_some())
+}
+
+/// A position reached by random plies which don't complete a line, with `empty`
+/// empty cells, or less if every ply would complete one
+fn random_position(rng: &mut StdRng, free_choice: bool, empty: u32) -> Position {
+	let mut position = Position::new(free_choice);
+	while position.empty_count() > empty || !in_scope(&position, empty) {
+		let plies: Vec<Ply> = position.legal_plies().into_iter()
+			.filter(|ply| !position.is_winning(*ply))
+			.collect();
+		match plies.choose(rng) {
+			Some(ply) => position = position.play(*ply),
+			None => break,
+		}
+	}
+	position
+}
+
+/// All canonical positions (see `solver::canonical`) with `empty` empty cells which
+/// belong in a tablebase: without a completed line, and with a piece in hand unless
+/// players choose their own pieces
+fn positions_with_empty(empty: u32, free_choice: bool, symmetries: &[[u8; 16]]) -> HashSet<u128> {
+	let mut keys = HashSet::new();
+	place_pieces(Position::new(free_choice), 0, 16 - empty.min(16), symmetries, &mut keys);
+	keys
+}
+
+/// Place `count` more pieces on the cells from `cell` on in every way that doesn't
+/// complete a line, adding the keys of the positions to `keys`
+fn place_pieces(position: Position, cell: u8, count: u32, symmetries: &[[u8; 16]], keys: &mut HashSet<u128>) {
+	if count == 0 {
+		if position.is_full() {
+			return;
+		} else if position.free_choice() {
+			keys.insert(solver::canonical(&position, symmetries));
+		} else {
+			for piece in bits(position.available()) {
+				keys.insert(solver::canonical(&position.play(Ply { place: None, give: Some(piece) }), symmetries));
+			}
+		}
+		return;
+	}
+	if 16 - (cell as u32) < count {
+		return;
+	}
+	// up to flipping attributes, the first piece is the same as any other
+	let pieces = if position.empty_count() == 16 { 1 } else { position.available() };
+	for piece in bits(pieces) {
+		if !position.completes_line(cell, piece) {
+			let placed = position.play(Ply { place: Some((cell, piece)), give: None });
+			place_pieces(placed, cell + 1, count - 1, symmetries, keys);
+		}
+	}
+	place_pieces(position, cell + 1, count, symmetries, keys);
+}
+
+/// A tablebase in memory, the outcomes with perfect play of positions with up to
+/// `max_empty` empty cells, keyed by the canonical position.
+///
+/// Every position is stored together with all positions below it, so the outcomes
+/// are derived from those of the children instead of solving every position
+#[derive(Debug)]
+pub struct Tablebase {
+	max_empty: u32,
+	entries: BTreeMap<u128, i8>,
+	symmetries: Vec<[u8; 16]>,
+}
+
+impl Tablebase {
+	/// An empty tablebase for positions with up to `max_empty` empty cells
+	pub fn new(max_empty: u32) -> Self {
+		Self {
+			max_empty,
+			entries: BTreeMap::new(),
+			symmetries: solver::symmetries(),
+		}
+	}
+
+	/// Number of stored positions
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	/// Whether no position is stored
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	/// Add all positions with up to `max_empty` empty cells reachable from `roots`.
+	/// Positions with more empty cells are expanded as well, so the roots should be
+	/// close to `max_empty`: enumerating everything below the empty board is out of reach
+	pub fn add(&mut self, roots: &[Position]) {
+		let mut levels: BTreeMap<u32, HashSet<u128>> = BTreeMap::new();
+		for root in roots.iter().filter(|root| !root.is_full() && !root.is_won()) {
+			levels.entry(root.empty_count()).or_default().insert(solver::canonical(root, &self.symmetries));
+		}
+		self.add_levels(levels);
+	}
+
+	/// Add all positions with up to `max_empty` empty cells: those with exactly `max_empty`
+	/// are enumerated, and the ones below them derived. There are more than 10^12 positions
+	/// with 4 empty cells even up to symmetry, and fewer empty cells don't make it much
+	/// smaller, so this only finishes for toy tables; `add` and `add_random` cover the
+	/// end games of chosen positions
+	pub fn generate(&mut self, free_choice: bool) {
+		let keys = positions_with_empty(self.max_empty, free_choice, &self.symmetries);
+		log::info!("Tablebase: {} positions with {} empty cells", keys.len(), self.max_empty);
+		self.add_levels(std::iter::once((self.max_empty, keys)).collect());
+	}
+
+	/// Add the positions below `games` random games, played until `max_empty` cells are left
+	pub fn add_random(&mut self, games: u32, free_choice: bool, seed: u64) {
+		let mut rng = StdRng::seed_from_u64(seed);
+		let roots: Vec<Position> = (0..games).map(|_| random_position(&mut rng, free_choice, self.max_empty)).collect();
+		self.add(&roots);
+	}
+
+	/// Add the positions of `levels` (canonical keys by their number of empty cells) with up to
+	/// `max_empty` empty cells, and all positions below them. The levels are expanded from the
+	/// most empty cells down, each position once, then the outcomes are derived from the
+	/// fewest empty cells up
+	fn add_levels(&mut self, mut levels: BTreeMap<u32, HashSet<u128>>) {
+		let mut added: Vec<Vec<u128>> = vec![Vec::new(); self.max_empty as usize + 1];
+		while let Some(empty) = levels.keys().next_back().copied() {
+			let keys = levels.remove(&empty).unwrap_or_default();
+			for key in keys {
+				if self.entries.contains_key(&key) {
+					// and so is everything below it
+					continue;
+				}
+				let position = solver::from_canonical(key);
+				if in_scope(&position, self.max_empty) {
+					added[empty as usize].push(key);
+				}
+				for ply in position.legal_plies() {
+					let child = position.play(ply);
+					if !position.is_winning(ply) && !child.is_full() {
+						// giving the first piece leads to the same level, which is expanded next
+						levels.entry(child.empty_count()).or_default().insert(solver::canonical(&child, &self.symmetries));
+					}
+				}
+			}
+		}
+		for keys in added {
+			for key in keys {
+				let outcome = self.derive(&solver::from_canonical(key));
+				self.entries.insert(key, encode(outcome));
+			}
+		}
+	}
+
+	/// The outcome of `position` from those of its children, which have to be stored
+	fn derive(&self, position: &Position) -> Outcome {
+		position.legal_plies().into_iter()
+			.map(|ply| {
+				let child = position.play(ply);
+				if position.is_winning(ply) {
+					Outcome::Win(1)
+				} else if child.is_full() {
+					Outcome::Draw(1)
+				} else {
+					self.get(&child).expect("the children are added first").before()
+				}
+			})
+			.max_by_key(|outcome| outcome.score())
+			.expect("a position in scope has plies")
+	}
+
+	/// The outcome of `position`, if it is stored
+	pub fn get(&self, position: &Position) -> Option<Outcome> {
+		let key = solver::canonical(position, &self.symmetries);
+		self.entries.get(&key).map(|value| decode(*value, position))
+	}
+
+	/// Write the tablebase in its on-disk format: a versioned header,
+	/// followed by the entries sorted by key, so they can be binary searched
+	pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+		writer.write_all(MAGIC)?;
+		writer.write_all(&VERSION.to_le_bytes())?;
+		writer.write_all(&[self.max_empty as u8, 0])?;
+		writer.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+		for (key, value) in self.entries.iter() {
+			writer.write_all(&key.to_le_bytes())?;
+			writer.write_all(&value.to_le_bytes())?;
+		}
+		writer.flush()
+	}
+
+	/// Write the tablebase to the file at `path`
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+		self.write(BufWriter::new(File::create(path)?))
+	}
+}
+
+/// Reads single entries of a tablebase file on demand, without loading it into memory
+#[derive(Debug)]
+pub struct TablebaseReader<R = BufReader<File>> {
+	reader: R,
+	max_empty: u32,
+	len: u64,
+	symmetries: Vec<[u8; 16]>,
+}
+
+impl TablebaseReader {
+	/// Open the tablebase file at `path`
+	pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		Self::new(BufReader::new(File::open(path)?))
+	}
+}
+
+impl<R: Read + Seek> TablebaseReader<R> {
+	/// Read the header of a tablebase
+	///
+	/// Errors:
+	/// - InvalidData: not a tablebase, or one of a different version
+	pub fn new(mut reader: R) -> io::Result<Self> {
+		let mut header = [0; HEADER_LEN as usize];
+		reader.seek(SeekFrom::Start(0))?;
+		reader.read_exact(&mut header)?;
+		if &header[0..8] != MAGIC {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "not a quart tablebase"));
+		}
+		let version = u16::from_le_bytes([header[8], header[9]]);
+		if version != VERSION {
+			let msg = format!("unsupported tablebase version {}, expected {}", version, VERSION);
+			return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+		}
+		let mut len = [0; 8];
+		len.copy_from_slice(&header[12..20]);
+		Ok(Self {
+			reader,
+			max_empty: header[10] as u32,
+			len: u64::from_le_bytes(len),
+			symmetries: solver::symmetries(),
+		})
+	}
+
+	/// The maximum number of empty cells of the stored positions
+	pub fn max_empty(&self) -> u32 {
+		self.max_empty
+	}
+
+	/// Number of stored positions
+	pub fn len(&self) -> u64 {
+		self.len
+	}
+
+	/// Whether no position is stored
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// The outcome of `position`, if it is stored
+	pub fn get(&mut self, position: &Position) -> io::Result<Option<Outcome>> {
+		if !in_scope(position, self.max_empty) {
+			return Ok(None);
+		}
+		let key = solver::canonical(position, &self.symmetries);
+		let (mut low, mut high) = (0, self.len);
+		let mut entry = [0; ENTRY_LEN as usize];
+		while low < high {
+			let mid = low + (high - low) / 2;
+			self.reader.seek(SeekFrom::Start(HEADER_LEN + mid * ENTRY_LEN))?;
+			self.reader.read_exact(&mut entry)?;
+			let mut stored = [0; 16];
+			stored.copy_from_slice(&entry[0..16]);
+			let stored = u128::from_le_bytes(stored);
+			if stored == key {
+				return Ok(Some(decode(entry[16] as i8, position)));
+			} else if stored < key {
+				low = mid + 1;
+			} else {
+				high = mid;
+			}
+		}
+		Ok(None)
+	}
+
+	/// The best ply in `position` according to the tablebase, `None` if some
+	/// position needed is missing
+	pub fn best_ply(&mut self, position: &Position) -> io::Result<Option<(Ply, Outcome)>> {
+		if self.get(position)?.is_none() {
+			return Ok(None);
+		}
+		let mut best: Option<(Ply, Outcome)> = None;
+		for ply in position.legal_plies() {
+			let child = position.play(ply);
+			let outcome = if position.is_winning(ply) {
+				Outcome::Win(1)
+			} else if child.is_full() {
+				Outcome::Draw(1)
+			} else {
+				match self.get(&child)? {
+					Some(outcome) => outcome.before(),
+					None => return Ok(None),
+				}
+			};
+			match best {
+				Some((_, b)) if b.score() >= outcome.score() => {},
+				_ => best = Some((ply, outcome)),
+			}
+		}
+		Ok(best)
+	}
+}
+
+/// Perfect play for agents in the end game: looked up in a tablebase, if one is
+/// set, or solved on the fly if the position is small enough
+#[derive(Debug)]
+pub(crate) struct Endgame {
+	solver: Solver,
+	/// Positions with more empty cells aren't solved
+	max_empty: u32,
+	tablebase: Option<TablebaseReader>,
+}
+impl Endgame {
+	/// Solve positions with up to `ENDGAME_EMPTY` empty cells, without a tablebase
+	pub fn new() -> Self {
+		Self {
+			solver: Solver::new().node_limit(ENDGAME_NODES),
+			max_empty: ENDGAME_EMPTY,
+			tablebase: None,
+		}
+	}
+
+	/// Neither look up nor solve anything
+	pub fn off() -> Self {
+		Self {
+			solver: Solver::new(),
+			max_empty: 0,
+			tablebase: None,
+		}
+	}
+
+	/// Solve positions with up to `max_empty` empty cells, giving up after `node_limit` nodes
+	pub fn set_limits(&mut self, max_empty: u32, node_limit: u64) {
+		self.max_empty = max_empty;
+		self.solver = Solver::new().node_limit(node_limit);
+	}
+
+	/// Look positions up in `tablebase`
+	pub fn set_tablebase(&mut self, tablebase: TablebaseReader) {
+		self.tablebase = Some(tablebase);
+	}
+
+	/// A perfect ply in `position`, if it is known or can be solved in time,
+	/// before `control` stops the solver
+	pub fn best_ply(&mut self, position: &Position, control: Option<&Control>) -> Option<Ply> {
+		if let Some(tablebase) = self.tablebase.as_mut() {
+			match tablebase.best_ply(position) {
+				Ok(Some((ply, outcome))) => {
+					log::debug!("Tablebase: {:?}", outcome);
+					return Some(ply);
+				},
+				Ok(None) => {},
+				Err(e) => log::warn!("Tablebase lookup failed: {}", e),
+			}
+		}
+		if position.empty_count() > self.max_empty {
+			return None;
+		}
+		let solution = self.solver.solve_controlled(position, control)?;
+		log::debug!("Solved end game: {:?}", solution.outcome);
+		solution.best.first().copied()
+	}
+}
+
+#[test]
+fn test_tablebase_roundtrip() {
+	let mut rng = StdRng::seed_from_u64(1);
+	let roots: Vec<Position> = (0..4).map(|i| random_position(&mut rng, i % 2 == 0, 4)).collect();
+	let mut tablebase = Tablebase::new(4);
+	tablebase.add(&roots);
+	assert!(!tablebase.is_empty());
+	let mut solver = Solver::new();
+
+	let mut file = Vec::new();
+	tablebase.write(&mut file).unwrap();
+	assert_eq!(file.len() as u64, HEADER_LEN + tablebase.len() as u64 * ENTRY_LEN);
+	let mut reader = TablebaseReader::new(io::Cursor::new(file)).unwrap();
+	assert_eq!(reader.len(), tablebase.len() as u64);
+	assert_eq!(reader.max_empty(), 4);
+
+	for root in roots.iter() {
+		let solution = solver.solve(root).unwrap();
+		assert_eq!(reader.get(root).unwrap(), Some(solution.outcome));
+		assert_eq!(tablebase.get(root), Some(solution.outcome));
+		let (ply, outcome) = reader.best_ply(root).unwrap().unwrap();
+		assert_eq!(outcome, solution.outcome);
+		assert!(solution.best.contains(&ply));
+	}
+	// not in the tablebase: too many empty cells
+	assert_eq!(reader.get(&random_position(&mut rng, false, 6)).unwrap(), None);
+}
+
+#[test]
+fn test_positions_with_empty() {
+	let symmetries = solver::symmetries();
+	let keys = positions_with_empty(15, false, &symmetries);
+	// every placed piece with every piece in hand, up to symmetry
+	let mut expected = HashSet::new();
+	for (cell, piece) in (0..16).flat_map(|cell| (0..16).map(move |piece| (cell, piece))) {
+		let placed = Position::new(false).play(Ply { place: Some((cell, piece)), give: None });
+		for in_hand in bits(placed.available()) {
+			expected.insert(solver::canonical(&placed.play(Ply { place: None, give: Some(in_hand) }), &symmetries));
+		}
+	}
+	assert_eq!(keys, expected);
+	assert!(keys.iter().all(|key| in_scope(&solver::from_canonical(*key), 15)));
+	assert_eq!(positions_with_empty(16, true, &symmetries).len(), 1);
+}
+
+#[test]
+fn test_tablebase_rejects_other_files() {
+	let mut file = Vec::new();
+	Tablebase::new(2).write(&mut file).unwrap();
+	file[8] = 99;
+	let error = TablebaseReader::new(io::Cursor::new(file)).unwrap_err();
+	assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+	assert!(TablebaseReader::new(io::Cursor::new(b"not a tablebase at all".to_vec())).is_err());
+}
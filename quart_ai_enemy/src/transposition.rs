@@ -0,0 +1,142 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::position::Position;
+use crate::search::WIN_THRESHOLD;
+
+/// What a stored score says about the real score
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Bound {
+	Exact,
+	/// The real score is at least the stored one
+	Lower,
+	/// The real score is at most the stored one
+	Upper,
+}
+
+/// An entry of a transposition table. Win and loss scores are stored
+/// relative to the position, not to the root of the search (see `to_table`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Entry {
+	pub score: i32,
+	pub bound: Bound,
+	/// How deep the position was searched
+	pub depth: u32,
+}
+impl Entry {
+	fn pack(self) -> u64 {
+		let bound = match self.bound {
+			Bound::Exact => 0,
+			Bound::Lower => 1,
+			Bound::Upper => 2,
+		};
+		(self.score as i16 as u16 as u64) | bound << 16 | (self.depth.min(255) as u64) << 18
+	}
+
+	fn unpack(data: u64) -> Self {
+		let bound = match (data >> 16) & 0b11 {
+			0 => Bound::Exact,
+			1 => Bound::Lower,
+			_ => Bound::Upper,
+		};
+		Self { score: data as u16 as i16 as i32, bound, depth: (data >> 18) as u32 & 0xFF }
+	}
+}
+
+/// A fixed-size transposition table which can be shared between threads without locking.
+/// Every slot holds the key xor-ed with the data next to the data, so a slot torn by
+/// concurrent writes is detected and treated as empty (lockless hashing)
+#[derive(Debug)]
+pub struct TranspositionTable {
+	slots: Vec<[AtomicU64; 2]>,
+}
+impl TranspositionTable {
+	/// A table with 2 to the power of `bits` slots, 16 bytes each
+	pub fn new(bits: u32) -> Self {
+		let slots = (0..1usize << bits).map(|_| [AtomicU64::new(0), AtomicU64::new(0)]).collect();
+		Self { slots }
+	}
+
+	/// Number of slots
+	pub fn len(&self) -> usize {
+		self.slots.len()
+	}
+
+	/// Whether the table has no slots
+	pub fn is_empty(&self) -> bool {
+		self.slots.is_empty()
+	}
+
+	/// Forget all entries
+	pub fn clear(&self) {
+		for slot in self.slots.iter() {
+			slot[0].store(0, Ordering::Relaxed);
+			slot[1].store(0, Ordering::Relaxed);
+		}
+	}
+
+	fn slot(&self, key: u64) -> &[AtomicU64; 2] {
+		&self.slots[key as usize & (self.slots.len() - 1)]
+	}
+
+	pub(crate) fn get(&self, key: u64) -> Option<Entry> {
+		let slot = self.slot(key);
+		let data = slot[1].load(Ordering::Relaxed);
+		if slot[0].load(Ordering::Relaxed) ^ data == key && data != 0 {
+			Some(Entry::unpack(data))
+		} else {
+			None
+		}
+	}
+
+	pub(crate) fn insert(&self, key: u64, entry: Entry) {
+		let slot = self.slot(key);
+		let data = entry.pack();
+		slot[0].store(key ^ data, Ordering::Relaxed);
+		slot[1].store(data, Ordering::Relaxed);
+	}
+}
+
+/// A hash of `position` (without symmetry reduction, which is too slow for the search)
+pub(crate) fn hash(position: &Position) -> u64 {
+	let mut hash = 0xcbf2_9ce4_8422_2325u64 ^ position.free_choice() as u64;
+	for cell in 0..16 {
+		let piece = position.piece_at(cell).map_or(16, u64::from);
+		hash = (hash ^ piece).wrapping_mul(0x0000_0100_0000_01b3);
+	}
+	let in_hand = position.in_hand().map_or(16, u64::from);
+	(hash ^ in_hand).wrapping_mul(0x0000_0100_0000_01b3)
+}
+
+/// Convert a score relative to the root into one relative to the position `height` plies below
+pub(crate) fn to_table(score: i32, height: i32) -> i32 {
+	if score > WIN_THRESHOLD {
+		score + height
+	} else if score < -WIN_THRESHOLD {
+		score - height
+	} else {
+		score
+	}
+}
+
+/// The inverse of `to_table`
+pub(crate) fn from_table(score: i32, height: i32) -> i32 {
+	if score > WIN_THRESHOLD {
+		score - height
+	} else if score < -WIN_THRESHOLD {
+		score + height
+	} else {
+		score
+	}
+}
+
+#[test]
+fn test_transposition_table() {
+	let table = TranspositionTable::new(4);
+	let entry = Entry { score: -9990, bound: Bound::Upper, depth: 3 };
+	table.insert(42, entry);
+	assert_eq!(table.get(42), Some(entry));
+	// same slot, other key
+	assert_eq!(table.get(42 + 16), None);
+	// a torn slot isn't returned
+	table.slots[42 & 15][1].store(Entry { score: 5, ..entry }.pack(), Ordering::Relaxed);
+	assert_eq!(table.get(42), None);
+}
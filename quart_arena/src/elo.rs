@@ -0,0 +1,77 @@
+/// Wins, draws and losses of an entrant, from its point of view
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Score {
+	/// Games won
+	pub wins: u32,
+	/// Games drawn
+	pub draws: u32,
+	/// Games lost
+	pub losses: u32,
+}
+
+impl Score {
+	/// Number of games played
+	pub fn games(&self) -> u32 {
+		self.wins + self.draws + self.losses
+	}
+
+	/// Points per game, a win counting 1 and a draw 1/2
+	pub fn ratio(&self) -> f64 {
+		if self.games() == 0 {
+			return 0.5;
+		}
+		(self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+	}
+
+	/// The same games, seen by the opponent
+	pub fn reversed(&self) -> Score {
+		Score { wins: self.losses, draws: self.draws, losses: self.wins }
+	}
+
+	/// The Elo difference to the opponents, with the margin of its 95% confidence
+	/// interval. Infinite if all games were won or lost
+	pub fn elo(&self) -> (f64, f64) {
+		let games = self.games() as f64;
+		let ratio = self.ratio();
+		if self.games() == 0 {
+			return (0.0, f64::INFINITY);
+		}
+		let variance = (self.wins as f64 * (1.0 - ratio).powi(2)
+			+ self.draws as f64 * (0.5 - ratio).powi(2)
+			+ self.losses as f64 * ratio.powi(2)) / games;
+		let deviation = (variance / games).sqrt() * 1.96;
+		let low = elo_difference((ratio - deviation).max(0.0));
+		let high = elo_difference((ratio + deviation).min(1.0));
+		let margin = (high - low) / 2.0;
+		// without any variance, all games were won or lost
+		(elo_difference(ratio), if margin.is_nan() { f64::INFINITY } else { margin })
+	}
+}
+impl std::ops::AddAssign for Score {
+	fn add_assign(&mut self, other: Score) {
+		self.wins += other.wins;
+		self.draws += other.draws;
+		self.losses += other.losses;
+	}
+}
+
+/// The Elo difference at which the expected points per game are `ratio`
+pub fn elo_difference(ratio: f64) -> f64 {
+	-400.0 * (1.0 / ratio - 1.0).log10()
+}
+
+#[test]
+fn test_elo() {
+	let even = Score { wins: 10, draws: 10, losses: 10 };
+	let (elo, margin) = even.elo();
+	assert!(elo.abs() < 1e-9);
+	assert!(margin > 0.0 && margin < 200.0);
+	// 3 of 4 points is about 191 Elo
+	let (elo, _) = Score { wins: 3, draws: 0, losses: 1 }.elo();
+	assert!((elo - 190.85).abs() < 0.1);
+	assert!((Score { wins: 3, draws: 0, losses: 1 }.reversed().elo().0 + elo).abs() < 1e-9);
+	assert_eq!(Score { wins: 5, draws: 0, losses: 0 }.elo().0, f64::INFINITY);
+	// more games, smaller error bars
+	let many = Score { wins: 100, draws: 100, losses: 100 };
+	assert!(many.elo().1 < margin);
+}
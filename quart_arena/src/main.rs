@@ -0,0 +1,133 @@
+#![warn(missing_docs)]
+//! Plays tournaments between AI agents and rates them
+
+/// Elo ratings with error bars
+pub mod elo;
+/// Scheduling and playing the games
+pub mod tournament;
+
+use quart_ai_enemy::{registry, AgentConfig, AgentSetup, Difficulty};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use self::elo::Score;
+use self::tournament::{Entrant, Format, GameResult, Tournament};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+const USAGE: &str = "usage: quart_arena [--gauntlet] [--games N] [--threads N] [--free] [--out DIR] AGENT AGENT...";
+
+fn main() -> Result<()> {
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	let (tournament, out) = tournament_from_args(&args)?;
+	fs::create_dir_all(&out)?;
+
+	let total = tournament.schedule().len();
+	let entrants = &tournament.entrants;
+	let save = |nr: usize, result: &GameResult| {
+		let name = |entrant: usize| entrants[entrant].name.as_str();
+		let (first, second) = (name(result.pairing.first), name(result.pairing.second));
+		let outcome = result.winner.map_or("draw", name);
+		eprintln!("game {}/{}: {} vs {}, {}", nr + 1, total, first, second, outcome);
+		let path = out.join(format!("{:04}-{}-{}.txt", nr + 1, first, second));
+		if let Err(e) = fs::write(&path, &result.record) {
+			eprintln!("couldn't save {}: {}", path.display(), e);
+		}
+	};
+	let results = tournament.run(&save);
+	print_results(&tournament, &results);
+	Ok(())
+}
+
+/// Create the tournament and the directory to save the games in from the command line:
+/// - `--gauntlet`: the first agent plays all others, instead of everybody playing everybody
+/// - `--games N`: games per pair of agents, 10 by default
+/// - `--threads N`: games played at the same time, one per CPU by default
+/// - `--free`: players choose their own pieces
+/// - `--out DIR`: where to save the games, `arena_games` by default
+/// - all other arguments are agents or difficulties of the registry
+fn tournament_from_args(args: &[String]) -> Result<(Tournament, PathBuf)> {
+	let mut entrants = Vec::new();
+	let mut games = 10;
+	let mut threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+	let mut format = Format::RoundRobin;
+	let mut free_choice = false;
+	let mut out = PathBuf::from("arena_games");
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		let mut value = || args.next().ok_or_else(|| format!("{} needs a value\n{}", arg, USAGE));
+		match arg.as_str() {
+			"--gauntlet" => format = Format::Gauntlet,
+			"--games" => games = value()?.parse()?,
+			"--threads" => threads = value()?.parse()?,
+			"--free" => free_choice = true,
+			"--out" => out = PathBuf::from(value()?),
+			name => match AgentConfig::by_name(name) {
+				Some(config) => entrants.push(Entrant { name: name.to_string(), config }),
+				None => {
+					let names: Vec<String> = registry::AGENTS.iter().map(|(name, _)| name.to_string())
+						.chain(Difficulty::ALL.iter().map(|d| d.to_string()))
+						.collect();
+					return Err(format!("unknown agent {:?}, choose one of: {}\n{}", name, names.join(", "), USAGE).into());
+				},
+			},
+		}
+	}
+	if entrants.len() < 2 {
+		return Err(format!("at least 2 agents are needed\n{}", USAGE).into());
+	}
+	let tournament = Tournament { entrants, format, games, free_choice, threads, setup: AgentSetup::from_env() };
+	Ok((tournament, out))
+}
+
+/// Print the standings, and the results of every pair of entrants
+fn print_results(tournament: &Tournament, results: &[GameResult]) {
+	let entrants = &tournament.entrants;
+	let mut standings: Vec<(usize, Score, Duration, u32)> = (0..entrants.len())
+		.map(|entrant| {
+			let mut score = Score::default();
+			let (mut time, mut moves) = (Duration::default(), 0);
+			for result in results {
+				score += result.score(entrant);
+				for (side, player) in [result.pairing.first, result.pairing.second].iter().enumerate() {
+					if *player == entrant {
+						time += result.time[side];
+						moves += result.moves[side];
+					}
+				}
+			}
+			(entrant, score, time, moves)
+		})
+		.collect();
+	standings.sort_by(|a, b| b.1.ratio().partial_cmp(&a.1.ratio()).unwrap_or(std::cmp::Ordering::Equal));
+
+	println!("{:<4} {:<12} {:>6} {:>6} {:>6} {:>6} {:>7} {:>14} {:>10}",
+		"rank", "agent", "games", "wins", "draws", "losses", "score", "elo", "avg move");
+	for (rank, (entrant, score, time, moves)) in standings.iter().enumerate() {
+		let (elo, margin) = score.elo();
+		let avg = if *moves == 0 { Duration::default() } else { *time / *moves };
+		println!("{:<4} {:<12} {:>6} {:>6} {:>6} {:>6} {:>6.1}% {:>+6.0} ± {:<5.0} {:>8.1}ms",
+			rank + 1, entrants[*entrant].name, score.games(), score.wins, score.draws, score.losses,
+			score.ratio() * 100.0, elo, margin, avg.as_secs_f64() * 1000.0);
+	}
+
+	println!();
+	let forfeits = results.iter().filter(|result| result.forfeited.is_some()).count();
+	for a in 0..entrants.len() {
+		for b in a + 1..entrants.len() {
+			let mut score = Score::default();
+			for result in results.iter().filter(|r| r.pairing.first == b || r.pairing.second == b) {
+				score += result.score(a);
+			}
+			if score.games() == 0 {
+				continue;
+			}
+			let (elo, margin) = score.elo();
+			println!("{} vs {}: +{} ={} -{}, elo {:+.0} ± {:.0}",
+				entrants[a].name, entrants[b].name, score.wins, score.draws, score.losses, elo, margin);
+		}
+	}
+	if forfeits > 0 {
+		println!("{} games were forfeited with illegal moves", forfeits);
+	}
+}
@@ -0,0 +1,209 @@
+use quart_ai_enemy::{AgentConfig, AgentError, AgentSetup, AiAgent, Checked};
+use quart_lib::{Game, Players, TurnStructure, Winner};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::elo::Score;
+
+/// An agent taking part in a tournament
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entrant {
+	/// The name shown in the results
+	pub name: String,
+	/// The agent
+	pub config: AgentConfig,
+}
+
+/// Who plays whom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+	/// Every entrant plays every other one
+	RoundRobin,
+	/// The first entrant plays every other one
+	Gauntlet,
+}
+
+/// One game, by the indices of the entrants
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pairing {
+	/// The entrant making the first turn
+	pub first: usize,
+	/// Its opponent
+	pub second: usize,
+}
+
+/// How a game ended
+#[derive(Debug, Clone)]
+pub struct GameResult {
+	/// Who played
+	pub pairing: Pairing,
+	/// The entrant who won, `None` on a draw
+	pub winner: Option<usize>,
+	/// The entrant who lost because it played an illegal move or none at all
+	pub forfeited: Option<usize>,
+	/// How many moves the first and the second entrant made
+	pub moves: [u32; 2],
+	/// How long the first and the second entrant thought, in total
+	pub time: [Duration; 2],
+	/// The record of the game, see `Game::record`
+	pub record: String,
+}
+impl GameResult {
+	/// The game from the point of view of `entrant`, an empty score if it didn't play
+	pub fn score(&self, entrant: usize) -> Score {
+		let mut score = Score::default();
+		if self.pairing.first != entrant && self.pairing.second != entrant {
+			return score;
+		}
+		match self.winner {
+			Some(winner) if winner == entrant => score.wins += 1,
+			Some(_) => score.losses += 1,
+			None => score.draws += 1,
+		}
+		score
+	}
+}
+
+/// A set of games between agents, played in parallel
+#[derive(Debug, Clone)]
+pub struct Tournament {
+	/// The agents taking part
+	pub entrants: Vec<Entrant>,
+	/// Who plays whom
+	pub format: Format,
+	/// Games per pair of entrants, who moves first alternates
+	pub games: u32,
+	/// Whether players choose their own pieces, see `TurnStructure::FreeChoice`
+	pub free_choice: bool,
+	/// How many games are played at the same time
+	pub threads: usize,
+	/// What the agents are created with besides their config
+	pub setup: AgentSetup,
+}
+
+impl Tournament {
+	/// A round robin of `games` games per pair of `entrants`
+	pub fn new(entrants: Vec<Entrant>, games: u32) -> Self {
+		Self {
+			entrants,
+			format: Format::RoundRobin,
+			games,
+			free_choice: false,
+			threads: 1,
+			setup: AgentSetup::default(),
+		}
+	}
+
+	/// All games to play, in order
+	pub fn schedule(&self) -> Vec<Pairing> {
+		let count = self.entrants.len();
+		let opponents: Vec<(usize, usize)> = match self.format {
+			Format::RoundRobin => (0..count).flat_map(|a| (a + 1..count).map(move |b| (a, b))).collect(),
+			Format::Gauntlet => (1..count).map(|b| (0, b)).collect(),
+		};
+		let mut schedule = Vec::new();
+		for (a, b) in opponents {
+			for game in 0..self.games {
+				schedule.push(match game & 1 {
+					0 => Pairing { first: a, second: b },
+					_ => Pairing { first: b, second: a },
+				});
+			}
+		}
+		schedule
+	}
+
+	/// Play all games, calling `on_result` with the number of every game
+	/// (see `schedule`) as soon as it ended. The results are in the order of `schedule`
+	pub fn run(&self, on_result: &(dyn Fn(usize, &GameResult) + Sync)) -> Vec<GameResult> {
+		let schedule = self.schedule();
+		let next = AtomicUsize::new(0);
+		let results = Mutex::new(Vec::with_capacity(schedule.len()));
+		std::thread::scope(|scope| {
+			for _ in 0..self.threads.max(1) {
+				scope.spawn(|| loop {
+					let nr = next.fetch_add(1, Ordering::Relaxed);
+					let pairing = match schedule.get(nr) {
+						Some(pairing) => *pairing,
+						None => break,
+					};
+					let result = self.play(pairing);
+					on_result(nr, &result);
+					results.lock().unwrap().push((nr, result));
+				});
+			}
+		});
+		let mut results = results.into_inner().unwrap();
+		results.sort_by_key(|(nr, _)| *nr);
+		results.into_iter().map(|(_, result)| result).collect()
+	}
+
+	/// Play a single game
+	pub fn play(&self, pairing: Pairing) -> GameResult {
+		let turn_structure = if self.free_choice { TurnStructure::FreeChoice } else { TurnStructure::GivePiece };
+		let mut game = Game::with_players(turn_structure, Players::two());
+		let sides = [pairing.first, pairing.second];
+		let mut agents: Vec<Checked<Box<dyn AiAgent + Send>>> = sides.iter()
+			.map(|entrant| Checked::new(self.entrants[*entrant].config.create_with(&game, &self.setup)).forfeit())
+			.collect();
+		let mut moves = [0; 2];
+		let mut time = [Duration::default(); 2];
+		let mut forfeited = None;
+		while !game.is_over() {
+			// player 1 moves first
+			let side = if game.player_turn == 1 { 0 } else { 1 };
+			let start = Instant::now();
+			let result = agents[side].play(&game);
+			time[side] += start.elapsed();
+			moves[side] += 1;
+			let result = result.and_then(|mov| mov.apply(&mut game).map_err(|e| AgentError::IllegalMove(mov, e)));
+			if let Err(e) = result {
+				log::warn!("{} loses: {}", self.entrants[sides[side]].name, e);
+				forfeited = Some(sides[side]);
+				break;
+			}
+		}
+		let winner = match (forfeited, game.winner()) {
+			(Some(loser), _) => sides.iter().copied().find(|entrant| *entrant != loser),
+			(None, Some(Winner::Player(player))) => Some(sides[player as usize - 1]),
+			_ => None,
+		};
+		let mut record = format!("Player 1: {}, Player 2: {}\n", self.entrants[sides[0]].name, self.entrants[sides[1]].name);
+		record.push_str(&game.record().to_string());
+		if let Some(loser) = forfeited {
+			record.push_str(&format!("{} forfeited\n", self.entrants[loser].name));
+		}
+		GameResult { pairing, winner, forfeited, moves, time, record }
+	}
+}
+
+#[test]
+fn test_tournament() {
+	let entrants = ["random", "greedy", "search"].iter()
+		.map(|name| Entrant { name: name.to_string(), config: AgentConfig::by_name(name).unwrap() })
+		.collect();
+	let mut tournament = Tournament::new(entrants, 2);
+	assert_eq!(tournament.schedule().len(), 6);
+	tournament.format = Format::Gauntlet;
+	let schedule = tournament.schedule();
+	assert_eq!(schedule, vec![
+		Pairing { first: 0, second: 1 },
+		Pairing { first: 1, second: 0 },
+		Pairing { first: 0, second: 2 },
+		Pairing { first: 2, second: 0 },
+	]);
+
+	tournament.entrants.truncate(2);
+	tournament.threads = 2;
+	let played = AtomicUsize::new(0);
+	let results = tournament.run(&|_, _| { played.fetch_add(1, Ordering::Relaxed); });
+	assert_eq!(played.into_inner(), 2);
+	for (result, pairing) in results.iter().zip(schedule) {
+		assert_eq!(result.pairing, pairing);
+		assert!(result.forfeited.is_none());
+		let mut score = result.score(0);
+		score += result.score(1);
+		assert_eq!(score.wins, score.losses);
+		assert_eq!(score.games(), 2);
+	}
+}
@@ -0,0 +1,192 @@
+use quart_ai_enemy::{registry, AgentConfig, AgentRunner, AgentSetup, AiAgent, Difficulty, Progress};
+use quart_ai_enemy::position::Position;
+use quart_ai_enemy::protocol::{self, Command, Reply};
+use quart_ai_enemy::search::SearchLimits;
+use std::io::{self, Write};
+use std::time::Instant;
+
+/// The option choosing the agent
+const AGENT_OPTION: &str = "agent";
+
+/// Answers the commands of `protocol::Command` with a registered agent.
+///
+/// `go` searches in the background with the configured agent, within the limits
+/// if there are any: `poll` reports the `info` lines and the `bestmove`, `stop`
+/// ends the search early. Other commands, except `isready`, finish the search first.
+/// `analyze` replies when it is done
+pub struct Engine {
+	agent_name: String,
+	config: AgentConfig,
+	setup: AgentSetup,
+	/// The limits the agent of `runner` was created with, `None` for the plain `config`
+	limits: Option<SearchLimits>,
+	runner: AgentRunner<Box<dyn AiAgent + Send>>,
+	/// When the search of `go` has to be stopped, if it has a time limit
+	deadline: Option<Instant>,
+	position: Position,
+}
+impl Engine {
+	/// An engine playing with the registered agent or difficulty `agent_name`, created
+	/// with `setup`. `None` if there is none
+	pub fn new(agent_name: &str, setup: AgentSetup) -> Option<Self> {
+		let position = Position::new(false);
+		let config = AgentConfig::by_name(agent_name)?;
+		let runner = AgentRunner::new(config.create_with(&position.to_game(), &setup));
+		Some(Self { agent_name: agent_name.to_string(), config, setup, limits: None, runner, deadline: None, position })
+	}
+
+	/// Whether a `go` is searching
+	pub fn is_searching(&self) -> bool {
+		self.runner.is_thinking()
+	}
+
+	/// Carry out `command`, writing the replies to `out`. `false` after `quit`
+	pub fn handle<W: Write>(&mut self, command: Command, out: &mut W) -> io::Result<bool> {
+		match command {
+			Command::IsReady => {},
+			Command::Quit => self.runner.cancel(),
+			_ => self.stop(out)?,
+		}
+		match command {
+			Command::Qei => {
+				writeln!(out, "{}", Reply::Id { key: "name".to_string(), value: format!("quart_engine {}", env!("CARGO_PKG_VERSION")) })?;
+				writeln!(out, "{}", Reply::Id { key: "author".to_string(), value: env!("CARGO_PKG_AUTHORS").to_string() })?;
+				writeln!(out, "{}", Reply::Id { key: "protocol".to_string(), value: protocol::VERSION.to_string() })?;
+				writeln!(out, "{}", Reply::Option { name: AGENT_OPTION.to_string(), default: self.agent_name.clone() })?;
+				writeln!(out, "{}", Reply::QeiOk)?;
+			},
+			Command::IsReady => writeln!(out, "{}", Reply::ReadyOk)?,
+			Command::SetOption { name, value } => {
+				if name != AGENT_OPTION {
+					writeln!(out, "{}", Reply::Error(format!("unknown option {:?}", name)))?;
+				} else if let Some(engine) = Engine::new(&value, self.setup.clone()) {
+					*self = Engine { position: self.position, ..engine };
+				} else {
+					let names: Vec<String> = registry::AGENTS.iter().map(|(name, _)| name.to_string())
+						.chain(Difficulty::ALL.iter().map(|d| d.to_string()))
+						.collect();
+					writeln!(out, "{}", Reply::Error(format!("unknown agent {:?}, choose one of: {}", value, names.join(", "))))?;
+				}
+			},
+			Command::NewGame => {
+				let game = self.position.to_game();
+				if let Some(agent) = self.runner.agent_mut() {
+					agent.game_over(&game);
+				}
+				self.position = Position::new(false);
+			},
+			Command::Position(position) => self.position = position,
+			// the configured agent decides, within the limits
+			Command::Go(limits) => {
+				if limits != self.limits {
+					let config = limits.map_or(self.config, |limits| self.config.with_limits(&limits));
+					self.runner = AgentRunner::new(config.create_with(&self.position.to_game(), &self.setup));
+					self.limits = limits;
+				}
+				self.deadline = limits.and_then(|limits| limits.time).map(|time| Instant::now() + time);
+				self.runner.start(&self.position.to_game());
+			},
+			Command::Analyze(limits) => {
+				let game = self.position.to_game();
+				let scored = self.runner.agent_mut().map(|agent| agent.analyze(&game, &limits)).unwrap_or_default();
+				for scored in scored.iter() {
+					writeln!(out, "{}", Reply::Scored(scored.clone()))?;
+				}
+				writeln!(out, "{}", Reply::BestMove(scored.first().map(|best| best.mov)))?;
+			},
+			Command::Stop => {},
+			Command::Quit => return Ok(false),
+		}
+		out.flush()?;
+		Ok(true)
+	}
+
+	/// Write what the search of `go` reported so far, and its `bestmove` once it is done.
+	/// Stops the search when its time is up
+	pub fn poll<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+		if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+			self.runner.stop();
+		}
+		while let Some(progress) = self.runner.poll() {
+			self.report(progress, out)?;
+		}
+		out.flush()
+	}
+
+	/// Wait for the search of `go` to be done, writing its replies
+	pub fn wait<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+		while let Some(progress) = self.runner.next_progress() {
+			self.report(progress, out)?;
+		}
+		out.flush()
+	}
+
+	/// Stop the search of `go`, writing its replies
+	fn stop<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+		self.runner.stop();
+		self.wait(out)
+	}
+
+	fn report<W: Write>(&mut self, progress: Progress, out: &mut W) -> io::Result<()> {
+		match progress {
+			Progress::Info { depth, score, nodes, best } => {
+				writeln!(out, "{}", Reply::Info { depth, score, nodes, pv: best.into_iter().collect() })
+			},
+			Progress::Done(result) => {
+				self.deadline = None;
+				if let Err(ref e) = result {
+					writeln!(out, "{}", Reply::Error(e.to_string()))?;
+				}
+				writeln!(out, "{}", Reply::BestMove(result.ok()))
+			},
+		}
+	}
+}
+
+#[test]
+fn test_engine() {
+	let mut engine = Engine::new("greedy", AgentSetup::default()).unwrap();
+	let mut session = |input: &str| {
+		let mut out = Vec::new();
+		for line in input.lines() {
+			engine.handle(line.parse().unwrap(), &mut out).unwrap();
+			engine.wait(&mut out).unwrap();
+		}
+		String::from_utf8(out).unwrap()
+	};
+	let handshake = session("qei\nisready");
+	assert!(handshake.contains("option name agent default greedy\nqeiok\nreadyok\n"));
+	assert!(session("setoption name agent value Deep Blue").starts_with("error unknown agent"));
+
+	// the top row holds 3 big pieces, a big piece is in hand
+	let position = "position classic BDrf,BDrF,BDRf,-,-,-,-,-,-,-,-,-,-,-,-,- Bdrf";
+	let reply = session(&format!("{}\ngo", position));
+	assert!(reply.starts_with("info depth 1 score 10000"), "{}", reply);
+	assert!(reply.ends_with("bestmove Bdrf@D4\n"), "{}", reply);
+	let reply = session(&format!("setoption name agent value search\n{}\nanalyze depth 1", position));
+	let lines: Vec<&str> = reply.lines().collect();
+	assert!(lines[0].starts_with("scored Bdrf@D4 score 10000 outcome win 1"), "{}", reply);
+	assert!(lines.last().unwrap().starts_with("bestmove Bdrf@D4"));
+	assert!(session("newgame\ngo depth 1").lines().last().unwrap().starts_with("bestmove /"));
+}
+
+#[test]
+fn test_engine_stop() {
+	use std::time::Duration;
+
+	// the MCTS searches with the node limit, which takes long
+	let mut engine = Engine::new("mcts", AgentSetup::default()).unwrap();
+	let mut out = Vec::new();
+	engine.handle("go nodes 100000000".parse().unwrap(), &mut out).unwrap();
+	assert!(engine.is_searching() && out.is_empty());
+	while !String::from_utf8_lossy(&out).starts_with("info depth") {
+		std::thread::sleep(Duration::from_millis(5));
+		engine.poll(&mut out).unwrap();
+	}
+	let started = Instant::now();
+	engine.handle(Command::Stop, &mut out).unwrap();
+	assert!(started.elapsed() < Duration::from_secs(1));
+	assert!(!engine.is_searching());
+	let reply = String::from_utf8(out).unwrap();
+	assert!(reply.lines().last().unwrap().starts_with("bestmove /"), "{}", reply);
+}
@@ -0,0 +1,84 @@
+#![warn(missing_docs)]
+//! Lets other programs play with the built-in agents: reads the commands of
+//! `quart_ai_enemy::protocol` from stdin and answers on stdout
+
+/// Carrying out the commands
+pub mod engine;
+
+use quart_ai_enemy::AgentSetup;
+use quart_ai_enemy::protocol::{Command, Reply};
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use self::engine::Engine;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+const USAGE: &str = "usage: quart_engine [AGENT]";
+/// How often a search reports its progress
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Answer the commands on stdin until `quit` or the end of input. The agent is the
+/// registered agent or difficulty given as argument, `search` by default, with the
+/// weights and tablebase of the environment (see `AgentSetup::from_env`)
+fn main() -> Result<()> {
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	let agent_name = match args.as_slice() {
+		[] => "search",
+		[name] => name.as_str(),
+		_ => return Err(USAGE.into()),
+	};
+	let mut engine = Engine::new(agent_name, AgentSetup::from_env()).ok_or_else(|| format!("unknown agent {:?}\n{}", agent_name, USAGE))?;
+
+	// stdin is read on a thread of its own, so `stop` arrives while searching
+	let (sender, lines) = mpsc::channel();
+	thread::spawn(move || {
+		let stdin = io::stdin();
+		for line in stdin.lock().lines() {
+			let failed = line.is_err();
+			if sender.send(line).is_err() || failed {
+				break;
+			}
+		}
+	});
+
+	let stdout = io::stdout();
+	let mut out = stdout.lock();
+	loop {
+		let line = if engine.is_searching() {
+			match lines.recv_timeout(POLL_INTERVAL) {
+				Ok(line) => line?,
+				Err(RecvTimeoutError::Timeout) => {
+					engine.poll(&mut out)?;
+					continue;
+				},
+				// the end of input, but the search is still replied to
+				Err(RecvTimeoutError::Disconnected) => {
+					engine.wait(&mut out)?;
+					break;
+				},
+			}
+		} else {
+			match lines.recv() {
+				Ok(line) => line?,
+				Err(_) => break,
+			}
+		};
+		if line.trim().is_empty() {
+			continue;
+		}
+		match line.parse::<Command>() {
+			Ok(command) => {
+				if !engine.handle(command, &mut out)? {
+					break;
+				}
+			},
+			Err(e) => {
+				writeln!(out, "{}", Reply::Error(e))?;
+				out.flush()?;
+			},
+		}
+	}
+	Ok(())
+}
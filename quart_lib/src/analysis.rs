@@ -0,0 +1,283 @@
+use crate::{BPos, Board, Game, Piece};
+use std::fmt;
+
+/// One of the 4 properties every piece has
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Attribute {
+	/// Big or small
+	Size,
+	/// Dark or light
+	Color,
+	/// Round or straight
+	Shape,
+	/// Flat on top or with a hole
+	Top,
+}
+impl Attribute {
+	/// All 4 attributes
+	pub const ALL: [Attribute; 4] = [Attribute::Size, Attribute::Color, Attribute::Shape, Attribute::Top];
+
+	/// The value `piece` has for this attribute
+	pub fn of(self, piece: Piece) -> bool {
+		match self {
+			Attribute::Size => piece.big,
+			Attribute::Color => piece.dark,
+			Attribute::Shape => piece.round,
+			Attribute::Top => piece.flat,
+		}
+	}
+}
+impl fmt::Display for Attribute {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+/// An attribute together with its value, e.g. "big" is `Size` = `true`
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct SharedAttribute {
+	/// The attribute
+	pub attribute: Attribute,
+	/// Its value
+	pub value: bool,
+}
+impl SharedAttribute {
+	/// Whether `piece` has this attribute value as well
+	pub fn matches(self, piece: Piece) -> bool {
+		self.attribute.of(piece) == self.value
+	}
+}
+
+/// Which kind of line
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum LineKind {
+	/// The row with the given y coordinate
+	Row(u16),
+	/// The column with the given x coordinate
+	Column(u16),
+	/// From the top left to the bottom right
+	Diagonal,
+	/// From the bottom left to the top right
+	AntiDiagonal,
+}
+
+/// A row, column or diagonal of the board
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Line {
+	/// Which line this is
+	pub kind: LineKind,
+	/// The 4 cells on this line
+	pub cells: [BPos; 4],
+}
+impl Line {
+	/// All 10 lines of the board: 4 rows, 4 columns and 2 diagonals
+	pub fn all() -> Vec<Line> {
+		let rows = (0..4).map(|y| Line {
+			kind: LineKind::Row(y),
+			cells: [BPos::new(0, y), BPos::new(1, y), BPos::new(2, y), BPos::new(3, y)],
+		});
+		let cols = (0..4).map(|x| Line {
+			kind: LineKind::Column(x),
+			cells: [BPos::new(x, 0), BPos::new(x, 1), BPos::new(x, 2), BPos::new(x, 3)],
+		});
+		let diags = vec![
+			Line {
+				kind: LineKind::Diagonal,
+				cells: [BPos::new(0, 0), BPos::new(1, 1), BPos::new(2, 2), BPos::new(3, 3)],
+			},
+			Line {
+				kind: LineKind::AntiDiagonal,
+				cells: [BPos::new(0, 3), BPos::new(1, 2), BPos::new(2, 1), BPos::new(3, 0)],
+			},
+		];
+		rows.chain(cols).chain(diags).collect()
+	}
+}
+
+/// A line with 3 pieces sharing at least one attribute:
+/// whoever places a matching piece on the missing cell wins
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Threat {
+	/// The line in question
+	pub line: Line,
+	/// The empty cell of the line
+	pub missing: BPos,
+	/// The attribute values all 3 pieces have in common
+	pub shared: Vec<SharedAttribute>,
+}
+impl Threat {
+	/// Whether placing `piece` on the missing cell completes the line
+	pub fn completed_by(&self, piece: Piece) -> bool {
+		self.shared.iter().any(|s| s.matches(piece))
+	}
+}
+
+/// A piece that lets the player who receives it win immediately
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LosingGive {
+	/// The piece in question
+	pub piece: Piece,
+	/// The threats it would complete
+	pub threats: Vec<Threat>,
+}
+
+impl Board {
+	/// All lines that are one piece away from being completed
+	pub fn threats(&self) -> Vec<Threat> {
+		Line::all()
+			.into_iter()
+			.filter_map(|line| {
+				let mut pieces = Vec::with_capacity(4);
+				let mut missing = None;
+				for pos in line.cells.iter() {
+					match self[*pos] {
+						Some(piece) => pieces.push(piece),
+						None => missing = Some(*pos),
+					}
+				}
+				if pieces.len() != 3 {
+					return None;
+				}
+				let shared: Vec<SharedAttribute> = Attribute::ALL
+					.iter()
+					.map(|attribute| SharedAttribute { attribute: *attribute, value: attribute.of(pieces[0]) })
+					.filter(|s| pieces.iter().all(|p| s.matches(*p)))
+					.collect();
+				if shared.is_empty() {
+					None
+				} else {
+					Some(Threat { line, missing: missing?, shared })
+				}
+			})
+			.collect()
+	}
+
+	/// All pieces which are not on the board yet
+	pub fn available_pieces(&self) -> Vec<Piece> {
+		Piece::all().filter(|p| !self.contains(*p)).collect()
+	}
+
+	/// The cells where placing `piece` completes a line
+	pub fn winning_cells(&self, piece: Piece) -> Vec<BPos> {
+		let mut cells = Vec::new();
+		for threat in self.threats().iter().filter(|t| t.completed_by(piece)) {
+			if !cells.contains(&threat.missing) {
+				cells.push(threat.missing);
+			}
+		}
+		cells
+	}
+
+	/// The pieces (not on the board yet, except for `in_hand`) which can be
+	/// given to the opponent without him being able to win immediately
+	pub fn safe_pieces(&self, in_hand: Option<Piece>) -> Vec<Piece> {
+		let threats = self.threats();
+		self.available_pieces()
+			.into_iter()
+			.filter(|p| Some(*p) != in_hand)
+			.filter(|p| !threats.iter().any(|t| t.completed_by(*p)))
+			.collect()
+	}
+}
+
+impl Game {
+	/// The pieces that can be given to the next player without him being able to win
+	/// immediately (not considering the selected piece, if any)
+	pub fn safe_pieces(&self) -> Vec<Piece> {
+		self.board.safe_pieces(self.selected_piece)
+	}
+
+	/// The pieces that let the next player win immediately, together with the
+	/// threats they complete (not considering the selected piece, if any)
+	pub fn losing_gives(&self) -> Vec<LosingGive> {
+		let threats = self.board.threats();
+		self.board
+			.available_pieces()
+			.into_iter()
+			.filter(|p| Some(*p) != self.selected_piece)
+			.filter_map(|piece| {
+				let threats: Vec<Threat> = threats.iter().filter(|t| t.completed_by(piece)).cloned().collect();
+				if threats.is_empty() {
+					None
+				} else {
+					Some(LosingGive { piece, threats })
+				}
+			})
+			.collect()
+	}
+
+	/// The cells where placing the selected piece wins the game
+	pub fn winning_cells(&self) -> Vec<BPos> {
+		match self.selected_piece {
+			Some(piece) => self.board.winning_cells(piece),
+			None => Vec::new(),
+		}
+	}
+
+	/// The cells that are "poisoned" for the selected piece: placing it there
+	/// doesn't win, but leaves no safe piece to give to the next player
+	pub fn poisoned_cells(&self) -> Vec<BPos> {
+		let piece = match self.selected_piece {
+			Some(piece) => piece,
+			None => return Vec::new(),
+		};
+		let winning = self.board.winning_cells(piece);
+		let mut poisoned = Vec::new();
+		for y in 0..4 {
+			for x in 0..4 {
+				let pos = BPos::new(x, y);
+				if self.board[pos].is_some() || winning.contains(&pos) {
+					continue;
+				}
+				let mut board = self.board.clone();
+				board[pos] = Some(piece);
+				// the last piece doesn't have to be given anymore
+				if board.piece_count() < 16 && board.safe_pieces(None).is_empty() {
+					poisoned.push(pos);
+				}
+			}
+		}
+		poisoned
+	}
+}
+
+#[test]
+fn test_threats() {
+	let p = |big, dark, round, flat| Some(Piece { big, dark, round, flat });
+	let (t, f) = (true, false);
+	let board = Board([
+		[None, None, None, p(t, t, t, t)],
+		[None, None, None, p(t, t, t, f)],
+		[None, None, None, p(t, t, f, t)],
+		[None, None, None, None],
+	]);
+	let threats = board.threats();
+	assert_eq!(threats.len(), 1);
+	assert_eq!(threats[0].line.kind, LineKind::Column(3));
+	assert_eq!(threats[0].missing, BPos::new(3, 3));
+	assert_eq!(threats[0].shared, vec![
+		SharedAttribute { attribute: Attribute::Size, value: true },
+		SharedAttribute { attribute: Attribute::Color, value: true },
+	]);
+
+	// only small, light pieces are safe
+	let safe = board.safe_pieces(None);
+	assert_eq!(safe.len(), 4);
+	assert!(safe.iter().all(|p| !p.big && !p.dark));
+	assert_eq!(board.winning_cells(Piece { big: false, dark: true, round: false, flat: false }), vec![BPos::new(3, 3)]);
+}
+
+#[test]
+fn test_losing_gives() {
+	let mut game = Game::new();
+	for (x, piece) in Piece::all().filter(|p| p.big).take(3).enumerate() {
+		game.select_next_piece(piece).unwrap();
+		game.place_piece(BPos::new(x as u16, 0)).unwrap();
+	}
+	// the pieces in the top row are all big and light
+	let losing = game.losing_gives();
+	assert_eq!(losing.len(), 9);
+	assert!(losing.iter().all(|l| (l.piece.big || !l.piece.dark) && l.threats[0].missing == BPos::new(3, 0)));
+	assert_eq!(game.safe_pieces().len(), 4);
+	assert!(game.safe_pieces().iter().all(|p| !p.big && p.dark));
+}
@@ -1,10 +1,11 @@
 use std::{
     fmt,
     ops::{Index, IndexMut},
+    str::FromStr,
 };
 
 /// A position on the board
-#[derive(PartialEq, Eq, Clone, Copy)]
+#[derive(PartialEq, Eq, Clone, Copy, Hash)]
 pub struct BPos {
     /// The x coordinate of this [`BPos`] (left to right)
     pub x: u16,
@@ -19,6 +20,16 @@ impl BPos {
         	y : y % 4,
         }
     }
+
+    /// The index of this position (0..16), row by row
+    pub fn index(self) -> u8 {
+        (self.y * 4 + self.x) as u8
+    }
+
+    /// The position with the given `index`, see [`BPos::index`]
+    pub fn from_index(index: u8) -> Self {
+        Self::new(u16::from(index % 4), u16::from(index / 4))
+    }
 }
 impl fmt::Debug for BPos {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -30,8 +41,34 @@ impl fmt::Debug for BPos {
     }
 }
 
+impl fmt::Display for BPos {
+    /// Column letter and row number as printed next to the board, e.g. "A4" for (0,0)
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", (b'A' + self.x as u8) as char, 4 - self.y)
+    }
+}
+impl FromStr for BPos {
+    type Err = String;
+
+    /// Parse the format of `Display`, like "A4", ignoring case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.as_bytes() {
+            [column, row] => {
+                let x = column.to_ascii_uppercase().wrapping_sub(b'A');
+                let row = row.wrapping_sub(b'0');
+                if x < 4 && (1..=4).contains(&row) {
+                    Ok(BPos::new(u16::from(x), u16::from(4 - row)))
+                } else {
+                    Err(format!("no such cell {:?}", s))
+                }
+            },
+            _ => Err(format!("no such cell {:?}", s)),
+        }
+    }
+}
+
 /// One game piece, with 4 distinctive properties
-#[derive(PartialEq, Eq, Clone, Copy, Default)]
+#[derive(PartialEq, Eq, Clone, Copy, Default, Hash)]
 pub struct Piece {
     /// Big or small
     pub big: bool,
@@ -57,17 +94,67 @@ impl fmt::Debug for Piece {
                 big, dark, round, flat
             )
         } else {
-            let b_str = if *big { "B" } else { "b" };
-            let d_str = if *big { "D" } else { "d" };
-            let r_str = if *big { "R" } else { "r" };
-            let f_str = if *big { "F" } else { "f" };
-            write!(f, "P{{{}{}{}{}}}", b_str, d_str, r_str, f_str)
+            write!(f, "P{{{}}}", self)
+        }
+    }
+}
+impl fmt::Display for Piece {
+    /// Upper case letters for big, dark, round and flat, lower case otherwise
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let b_str = if self.big { "B" } else { "b" };
+        let d_str = if self.dark { "D" } else { "d" };
+        let r_str = if self.round { "R" } else { "r" };
+        let f_str = if self.flat { "F" } else { "f" };
+        write!(f, "{}{}{}{}", b_str, d_str, r_str, f_str)
+    }
+}
+impl FromStr for Piece {
+    type Err = String;
+
+    /// Parse the format of `Display`, like "bDrF"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let chars: Vec<char> = s.chars().collect();
+        let property = |i: usize, name: char| match chars.get(i) {
+            Some(c) if *c == name => Ok(true),
+            Some(c) if *c == name.to_ascii_lowercase() => Ok(false),
+            _ => Err(format!("no such piece {:?}", s)),
+        };
+        if chars.len() != 4 {
+            return Err(format!("no such piece {:?}", s));
+        }
+        Ok(Piece {
+            big: property(0, 'B')?,
+            dark: property(1, 'D')?,
+            round: property(2, 'R')?,
+            flat: property(3, 'F')?,
+        })
+    }
+}
+
+impl Piece {
+    /// An iterator over all 16 pieces in the game, ordered by [`Piece::index`]
+    pub fn all() -> impl Iterator<Item = Piece> {
+        (0..16u8).map(Piece::from_index)
+    }
+
+    /// The properties as bits (0..16): big, dark, round, flat from high to low
+    pub fn index(self) -> u8 {
+        (self.big as u8) << 3 | (self.dark as u8) << 2 | (self.round as u8) << 1 | self.flat as u8
+    }
+
+    /// The piece with the given `index`, see [`Piece::index`]
+    pub fn from_index(index: u8) -> Self {
+        Piece {
+            big: index & 0b1000 != 0,
+            dark: index & 0b0100 != 0,
+            round: index & 0b0010 != 0,
+            flat: index & 0b0001 != 0,
         }
     }
 }
 
 /// A game board (array of rows)
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
 pub struct Board(pub [[Option<Piece>; 4]; 4]);
 impl Index<BPos> for Board {
     type Output = Option<Piece>;
@@ -194,10 +281,25 @@ impl Board {
     }
 }
 
+/// All rows, columns and diagonals going through `pos`
+pub(crate) fn lines_through(pos: BPos) -> Vec<[BPos; 4]> {
+    let mut lines = vec![
+        [BPos::new(0, pos.y), BPos::new(1, pos.y), BPos::new(2, pos.y), BPos::new(3, pos.y)],
+        [BPos::new(pos.x, 0), BPos::new(pos.x, 1), BPos::new(pos.x, 2), BPos::new(pos.x, 3)],
+    ];
+    if pos.x == pos.y {
+        lines.push([BPos::new(0, 0), BPos::new(1, 1), BPos::new(2, 2), BPos::new(3, 3)]);
+    }
+    if pos.x + pos.y == 3 {
+        lines.push([BPos::new(0, 3), BPos::new(1, 2), BPos::new(2, 1), BPos::new(3, 0)]);
+    }
+    lines
+}
+
 /// Check the first 4 fields whether they satisfy the conditions
 /// (at least 1 property has to be equal on all 4)
 /// Returns None if no property matched and Some if at least one matched
-fn check_fields(fields: &[Option<Piece>]) -> Option<String> {
+pub(crate) fn check_fields(fields: &[Option<Piece>]) -> Option<String> {
     // Insted of a Vector of Options, Option allows us to collect into a
     // Option of Vec instead, being None if one of the elements was None (short-circuit)
     let first4: Option<Vec<Piece>> = fields.iter().take(4).cloned().collect();
@@ -229,7 +331,7 @@ fn check_fields(fields: &[Option<Piece>]) -> Option<String> {
 }
 
 /// Details to why the game is over
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct GameOverInfo {
     /// Positions of the matching pieces
     pub positions: Vec<BPos>,
@@ -246,14 +348,14 @@ fn test_check_fields() {
     let p_BDrf = _new_piece(1, 1, 0, 0);
     let p_BDrF = _new_piece(1, 1, 0, 1);
 
-    assert!(check_fields(&[p_bdrf, p_bdrf, p_bdrf, p_bdrf]), "all equal");
+    assert!(check_fields(&[p_bdrf, p_bdrf, p_bdrf, p_bdrf]).is_some(), "all equal");
     assert!(
-        check_fields(&[p_bdrf, p_BdRf, p_bDrf, p_BDrf]),
+        check_fields(&[p_bdrf, p_BdRf, p_bDrf, p_BDrf]).is_some(),
         "all not flat"
     );
-    assert!(!check_fields(&[p_bdrf, p_bdrf, p_bdrf, None]), "1 none");
+    assert!(check_fields(&[p_bdrf, p_bdrf, p_bdrf, None]).is_none(), "1 none");
     assert!(
-        !check_fields(&[p_bdrf, p_BdRf, p_bDrf, p_BDrF]),
+        check_fields(&[p_bdrf, p_BdRf, p_bDrf, p_BDrF]).is_none(),
         "no equals"
     );
 }
@@ -266,3 +368,18 @@ fn _new_piece(big: u8, dark: u8, round: u8, flat: u8) -> Option<Piece> {
         flat: flat != 0,
     })
 }
+
+#[test]
+fn test_parse() {
+    for piece in Piece::all() {
+        assert_eq!(piece.to_string().parse(), Ok(piece));
+    }
+    for index in 0..16 {
+        let pos = BPos::from_index(index);
+        assert_eq!(pos.to_string().parse(), Ok(pos));
+    }
+    assert_eq!("a4".parse(), Ok(BPos::new(0, 0)));
+    assert!("E1".parse::<BPos>().is_err());
+    assert!("bdr".parse::<Piece>().is_err());
+    assert!("bdrx".parse::<Piece>().is_err());
+}
@@ -15,6 +15,8 @@ pub enum GameError {
 	PieceAlreadySelected,
 	/// An action couldn't be fulfilled because the game was over
 	GameIsOver,
+	/// A game needs 2 to 4 different players (or a single one in solo mode)
+	InvalidPlayers,
 }
 impl fmt::Display for GameError {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -24,6 +26,7 @@ impl fmt::Display for GameError {
 			NoPieceSelected      => write!(f, "An action required `self.selected_piece` to be `Some(_)`, but is was `None`"),
 			PieceAlreadySelected => write!(f, "An action required `self.selected_piece` to be `None`, but it was `Some(_)`"),
 			GameIsOver           => write!(f, "Game Over"),
+			InvalidPlayers       => write!(f, "A game needs 2 to 4 different players"),
 		}
 	}
 }
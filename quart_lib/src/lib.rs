@@ -5,9 +5,22 @@
 pub mod board;
 /// Contains `GameError`, the core game error type
 pub mod error;
+/// Solver for the single player puzzle mode
+pub mod solo;
+/// Turn order and teams
+pub mod players;
+/// Human readable game records
+pub mod record;
+/// Threats, safe pieces and other properties of a position
+pub mod analysis;
+/// Typestate API which makes calling game actions in the wrong order impossible
+pub mod typed;
 
 pub use self::board::*;
 pub use self::error::GameError;
+pub use self::players::{Players, Winner};
+pub use self::record::GameRecord;
+pub use self::analysis::{Attribute, Line, LineKind, LosingGive, SharedAttribute, Threat};
 
 /// The state the game is in
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -20,11 +33,58 @@ pub enum GameState {
     GameOver,
 }
 
+/// Which kind of game is played
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GameMode {
+    /// Two players, each one selects the piece his opponent has to place
+    Versus,
+    /// A single player places all 16 pieces in any order,
+    /// without ever completing a row, column or diagonal
+    Solo,
+}
+
+/// How a turn is structured
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TurnStructure {
+    /// Classic rules: the player selects the piece his opponent has to place
+    GivePiece,
+    /// Beginner variant: the player selects a piece and places it himself
+    FreeChoice,
+}
+
+/// How a game of `GameMode::Solo` ended
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SoloResult {
+    /// All 16 pieces were placed without completing a line
+    Solved,
+    /// A line with a common property was completed
+    Failed,
+}
+
+/// One turn of the game: a piece was selected and (possibly) placed
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Turn {
+    /// The player who selected the piece
+    pub selected_by: u32,
+    /// The player who has to place the piece
+    pub placed_by: u32,
+    /// The piece that was selected
+    pub piece: Piece,
+    /// Where the piece was placed, `None` while it wasn't placed yet
+    pub pos: Option<BPos>,
+}
+
 /// The central data structure of the game
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Game {
+    /// The kind of game that is played
+    pub mode: GameMode,
+    /// Whether players give pieces to each other or choose their own
+    pub turn_structure: TurnStructure,
     /// The current state of the game
     pub state: GameState,
+    /// Which players take part, in which order
+    pub players: Players,
     /// Which player's turn it is
     pub player_turn: u32,
     /// The board on which will be played
@@ -33,46 +93,102 @@ pub struct Game {
     pub selected_piece: Option<Piece>,
     /// In case of Game Over, this contains a description
     pub game_over_info: Option<GameOverInfo>,
+    /// All turns played so far, used for undoing them
+    pub history: Vec<Turn>,
 }
 impl Game {
     /// Create a new `Game`
     pub fn new() -> Self {
+        Self::with_mode(GameMode::Versus)
+    }
+
+    /// Create a new single player `Game`, see `GameMode::Solo`
+    pub fn new_solo() -> Self {
+        Self::with_mode(GameMode::Solo)
+    }
+
+    /// Create a new `Game` of the given `mode`.
+    /// In `GameMode::Solo` the player always chooses his own pieces
+    pub fn with_mode(mode: GameMode) -> Self {
+        match mode {
+            GameMode::Versus => Self::with_rules(mode, TurnStructure::GivePiece, Players::two()),
+            GameMode::Solo => Self::with_rules(mode, TurnStructure::FreeChoice, Players::solo()),
+        }
+    }
+
+    /// Create a new two player `Game` with the given `turn_structure`
+    pub fn with_turn_structure(turn_structure: TurnStructure) -> Self {
+        Self::with_players(turn_structure, Players::two())
+    }
+
+    /// Create a new `Game` for the given `players`
+    pub fn with_players(turn_structure: TurnStructure, players: Players) -> Self {
+        Self::with_rules(GameMode::Versus, turn_structure, players)
+    }
+
+    fn with_rules(mode: GameMode, turn_structure: TurnStructure, players: Players) -> Self {
         Self {
+            mode,
+            turn_structure,
             state: GameState::SelectPiece,
-            player_turn: 1,
+            player_turn: players.first(),
+            players,
             board: Board::default(),
             selected_piece: None,
             game_over_info: None,
+            history: Vec::new(),
         }
     }
 
 	/// Place selected piece on the board at `place_pos`
 	///
 	/// Errors:
+	/// - GameIsOver: when method is called after GameOver
 	/// - NoPieceSelected: `self.selected_piece` is `None`
 	/// - CellOccupied:	`place_pos` is already occupied with a piece
 	pub fn place_piece(&mut self, place_pos: BPos) -> Result<(), GameError> {
-		if self.selected_piece.is_none() {
+		if self.is_over() {
+			Err(GameError::GameIsOver)
+		} else if self.selected_piece.is_none() {
 			Err(GameError::NoPieceSelected)
 		} else if self.board[place_pos].is_some() {
 			Err(GameError::CellOccupied)
 		} else {
-			self.board[place_pos] = self.selected_piece;
-			self.state = GameState::SelectPiece;
-			self.check();
+			self.commit_place(self.selected_piece.unwrap(), place_pos);
 			Ok(())
 		}
 	}
 
+	/// Actually place `piece`, without any checks
+	fn commit_place(&mut self, piece: Piece, place_pos: BPos) {
+		self.board[place_pos] = Some(piece);
+		if let Some(turn) = self.history.iter_mut().rev().find(|turn| turn.piece == piece) {
+			turn.pos = Some(place_pos);
+		}
+		self.selected_piece = None;
+		self.state = GameState::SelectPiece;
+		if !self.check() && self.turn_structure == TurnStructure::FreeChoice {
+			self.next_turn();
+		}
+	}
+
+	/// It's the next player's turn now
+	fn next_turn(&mut self) {
+		self.player_turn = self.players.after(self.player_turn);
+	}
+
 	/// Check if selected piece can be placed on the board at `place_pos`
 	/// Returns a `PlacePieceTransaction` on success which can be used to perform
 	/// the real placing later on
 	///
 	/// Errors:
+	/// - GameIsOver: when method is called after GameOver
 	/// - NoPieceSelected: `self.selected_piece` is `None`
 	/// - CellOccupied:	`place_pos` is already occupied with a piece
 	pub fn probe_place_piece(&mut self, place_pos: BPos) -> Result<PlacePieceTransaction, GameError> {
-		if self.selected_piece.is_none() {
+		if self.is_over() {
+			Err(GameError::GameIsOver)
+		} else if self.selected_piece.is_none() {
 			Err(GameError::NoPieceSelected)
 		} else if self.board[place_pos].is_some() {
 			Err(GameError::CellOccupied)
@@ -83,36 +199,113 @@ impl Game {
 			})
 		}
 	}
-	/// Select `next_piece` for the next player, it's his turn now
+	/// Select `next_piece` for the next player, it's his turn now.
+	/// With `TurnStructure::FreeChoice`, the current player has to place it himself
 	///
 	/// Errors:
 	/// - GameIsOver: when method is called after GameOver
+	/// - PieceAlreadySelected: the selected piece wasn't placed yet
 	/// - PieceInUse: `next_piece` is already on the board
 	pub fn select_next_piece(&mut self, next_piece: Piece) -> Result<(), GameError> {
 		if self.is_over() {
 			Err(GameError::GameIsOver)
+		} else if self.selected_piece.is_some() {
+			Err(GameError::PieceAlreadySelected)
 		} else if self.board.contains(next_piece) {
 			Err(GameError::PieceInUse)
 		} else {
+			let selected_by = self.player_turn;
 			self.selected_piece = Some(next_piece);
 			self.state = GameState::PlacePiece;
-			self.player_turn = 3 - self.player_turn;
+			if self.turn_structure == TurnStructure::GivePiece {
+				self.next_turn();
+			}
+			self.history.push(Turn {
+				selected_by,
+				placed_by: self.player_turn,
+				piece: next_piece,
+				pos: None,
+			});
 			Ok(())
 		}
 	}
 
+	/// Undo the last action (placing or selecting a piece).
+	/// Returns `false` if there was nothing to undo
+	pub fn undo(&mut self) -> bool {
+		let turn = match self.history.last_mut() {
+			Some(turn) => turn,
+			None => return false,
+		};
+		if let Some(pos) = turn.pos.take() {
+			// take the piece back into the hand
+			self.board[pos] = None;
+			self.selected_piece = Some(turn.piece);
+			self.player_turn = turn.placed_by;
+			self.state = GameState::PlacePiece;
+		} else {
+			// put the selected piece back
+			self.player_turn = turn.selected_by;
+			self.history.pop();
+			self.selected_piece = None;
+			self.state = GameState::SelectPiece;
+		}
+		self.game_over_info = None;
+		true
+	}
+
+	/// How the game ended, only available for `GameMode::Solo`
+	pub fn solo_result(&self) -> Option<SoloResult> {
+		if self.mode != GameMode::Solo || !self.is_over() {
+			None
+		} else if self.game_over_info.is_some() {
+			Some(SoloResult::Failed)
+		} else {
+			Some(SoloResult::Solved)
+		}
+	}
+
+	/// Whether the pieces left can still be placed without completing a line,
+	/// see `solo::can_complete`
+	pub fn solo_solvable(&self) -> bool {
+		!self.is_over() && solo::can_complete(&self.board)
+	}
+
+	/// Who won the game, `None` while it's running, on a draw and in `GameMode::Solo`.
+	/// The player who completed a line (or his team) wins
+	pub fn winner(&self) -> Option<Winner> {
+		if self.mode == GameMode::Versus && self.is_over() && self.game_over_info.is_some() {
+			Some(self.players.winner(self.player_turn))
+		} else {
+			None
+		}
+	}
+
+	/// A label for the player whose turn it is, like "Player 3 (Team 1)"
+	pub fn player_label(&self) -> String {
+		self.players.label(self.player_turn)
+	}
+
+	/// A human readable record of all turns played so far
+	pub fn record(&self) -> GameRecord<'_> {
+		GameRecord::new(self)
+	}
+
     /// Whether the game is over
     pub fn is_over(&self) -> bool {
         self.state == GameState::GameOver
     }
 
     /// Check if the game is over (delegate from main_board)
-    /// Returns true on GameOver
+    /// Returns true on GameOver, which is also the case if the board is full
     pub fn check(&mut self) -> bool {
         if let Some(info) = self.board.check() {
             self.state = GameState::GameOver;
             self.game_over_info = Some(info);
             true
+        } else if self.board.piece_count() == 16 {
+            self.state = GameState::GameOver;
+            true
         } else {
             false
         }
@@ -126,8 +319,8 @@ impl Default for Game {
 }
 
 /// Transaction returned by `probe_place_piece`, allows delaying committing changes.
-/// Is used by AI_Agents so if the following call to `select_next_piece` fails, we
-/// can choose to not run the entire transaction
+/// Is used by AI_Agents so if the piece they want to select next is invalid, we
+/// can choose to not run the entire transaction, see `run_and_select`
 pub struct PlacePieceTransaction {
 	selected_piece: Option<Piece>,
 	place_pos: BPos,
@@ -135,8 +328,47 @@ pub struct PlacePieceTransaction {
 impl PlacePieceTransaction {
 	/// Run the transaction
 	pub fn run(self, game: &mut Game) {
-		game.board[self.place_pos] = self.selected_piece;
-		game.state = GameState::SelectPiece;
-		game.check();
+		if let Some(piece) = self.selected_piece {
+			game.commit_place(piece, self.place_pos);
+		}
 	}
+
+	/// Run the transaction and select `next_piece` afterwards.
+	/// If placing the piece ends the game, `next_piece` is ignored.
+	/// Nothing is changed on error
+	///
+	/// Errors:
+	/// - NoPieceSelected: there was no piece to place
+	/// - PieceInUse: `next_piece` is on the board or is the piece being placed
+	pub fn run_and_select(self, game: &mut Game, next_piece: Piece) -> Result<(), GameError> {
+		let piece = self.selected_piece.ok_or(GameError::NoPieceSelected)?;
+		let mut board = game.board.clone();
+		board[self.place_pos] = Some(piece);
+		let ends_game = board.check().is_some() || board.piece_count() == 16;
+		if !ends_game && board.contains(next_piece) {
+			return Err(GameError::PieceInUse);
+		}
+		self.run(game);
+		if !game.is_over() {
+			game.select_next_piece(next_piece)?;
+		}
+		Ok(())
+	}
+}
+
+#[test]
+fn test_free_choice_turns() {
+	let mut game = Game::with_turn_structure(TurnStructure::FreeChoice);
+	let piece = Piece::default();
+	game.select_next_piece(piece).unwrap();
+	assert_eq!(game.player_turn, 1, "the player places his own piece");
+	game.place_piece(BPos::new(1, 2)).unwrap();
+	assert_eq!(game.player_turn, 2);
+	assert_eq!(game.state, GameState::SelectPiece);
+
+	assert!(game.undo());
+	assert_eq!((game.player_turn, game.state), (1, GameState::PlacePiece));
+	assert!(game.undo());
+	assert_eq!((game.player_turn, game.state), (1, GameState::SelectPiece));
+	assert!(!game.undo());
 }
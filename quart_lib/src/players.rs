@@ -0,0 +1,141 @@
+use crate::GameError;
+use std::fmt;
+
+/// The players taking part in a game, in the order they take turns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Players {
+	/// Player numbers, in turn order
+	order: Vec<u32>,
+	/// The team of each player in `order`, if playing in teams
+	teams: Option<Vec<u32>>,
+}
+impl Players {
+	/// The classic two player setup, player 1 begins
+	pub fn two() -> Self {
+		Self { order: vec![1, 2], teams: None }
+	}
+
+	/// A single player, used for `GameMode::Solo`
+	pub fn solo() -> Self {
+		Self { order: vec![1], teams: None }
+	}
+
+	/// 2 to 4 players, taking turns in the given `order`
+	///
+	/// Errors:
+	/// - InvalidPlayers: not 2 to 4 players, or some player number is used twice
+	pub fn new(order: Vec<u32>) -> Result<Self, GameError> {
+		let mut sorted = order.clone();
+		sorted.sort_unstable();
+		sorted.dedup();
+		if order.len() < 2 || order.len() > 4 || sorted.len() != order.len() {
+			Err(GameError::InvalidPlayers)
+		} else {
+			Ok(Self { order, teams: None })
+		}
+	}
+
+	/// Two teams of two players, the players of team 1 are `order[0]` and `order[2]`,
+	/// so the teams take turns alternately. Teammates share the win
+	///
+	/// Errors:
+	/// - InvalidPlayers: some player number is used twice
+	pub fn teams(order: [u32; 4]) -> Result<Self, GameError> {
+		let players = Self::new(order.to_vec())?;
+		Ok(Self {
+			teams: Some(vec![1, 2, 1, 2]),
+			..players
+		})
+	}
+
+	/// Player numbers, in turn order
+	pub fn order(&self) -> &[u32] {
+		&self.order
+	}
+
+	/// Whether the players play in teams
+	pub fn has_teams(&self) -> bool {
+		self.teams.is_some()
+	}
+
+	/// The player who starts the game
+	pub fn first(&self) -> u32 {
+		self.order[0]
+	}
+
+	/// The player whose turn it is after `player`
+	pub fn after(&self, player: u32) -> u32 {
+		let idx = self.order.iter().position(|p| *p == player).unwrap_or(0);
+		self.order[(idx + 1) % self.order.len()]
+	}
+
+	/// The team of `player`, if playing in teams
+	pub fn team_of(&self, player: u32) -> Option<u32> {
+		let idx = self.order.iter().position(|p| *p == player)?;
+		self.teams.as_ref().map(|teams| teams[idx])
+	}
+
+	/// All players of `team`
+	pub fn members(&self, team: u32) -> Vec<u32> {
+		match self.teams.as_ref() {
+			Some(teams) => self.order.iter().zip(teams).filter(|(_, t)| **t == team).map(|(p, _)| *p).collect(),
+			None => Vec::new(),
+		}
+	}
+
+	/// A label for `player`, like "Player 3 (Team 1)"
+	pub fn label(&self, player: u32) -> String {
+		match self.team_of(player) {
+			Some(team) => format!("Player {} (Team {})", player, team),
+			None => format!("Player {}", player),
+		}
+	}
+
+	/// Who wins if `player` completes a line
+	pub fn winner(&self, player: u32) -> Winner {
+		match self.team_of(player) {
+			Some(team) => Winner::Team(team, self.members(team)),
+			None => Winner::Player(player),
+		}
+	}
+}
+impl Default for Players {
+	fn default() -> Self {
+		Self::two()
+	}
+}
+
+/// Who won the game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Winner {
+	/// A single player won
+	Player(u32),
+	/// A team won, together with its players
+	Team(u32, Vec<u32>),
+}
+impl fmt::Display for Winner {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Winner::Player(player) => write!(f, "Player {}", player),
+			Winner::Team(team, members) => {
+				let members: Vec<String> = members.iter().map(|p| p.to_string()).collect();
+				write!(f, "Team {} (players {})", team, members.join(" and "))
+			},
+		}
+	}
+}
+
+#[test]
+fn test_turn_order() {
+	let players = Players::new(vec![3, 1, 2]).unwrap();
+	assert_eq!(players.first(), 3);
+	assert_eq!(players.after(3), 1);
+	assert_eq!(players.after(2), 3);
+	assert!(Players::new(vec![1, 1]).is_err());
+	assert!(Players::new(vec![1, 2, 3, 4, 5]).is_err());
+
+	let teams = Players::teams([1, 2, 3, 4]).unwrap();
+	assert_eq!(teams.team_of(3), Some(1));
+	assert_eq!(teams.winner(4), Winner::Team(2, vec![2, 4]));
+	assert_eq!(teams.winner(4).to_string(), "Team 2 (players 2 and 4)");
+}
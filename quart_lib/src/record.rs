@@ -0,0 +1,84 @@
+use crate::{Game, GameMode, TurnStructure};
+use std::fmt;
+
+/// A human readable record of a game, created by `Game::record`.
+///
+/// ```text
+/// Quart, classic rules, players: 1 2 3 4, teams: 1 2 1 2
+/// 1. Player 1 (Team 1) gives bDrF to Player 2 (Team 2), placed at B3
+/// 2. Player 2 (Team 2) gives BDRf to Player 3 (Team 1)
+/// Running, Player 3 (Team 1) has to place BDRf
+/// ```
+pub struct GameRecord<'g> {
+	game: &'g Game,
+}
+impl<'g> GameRecord<'g> {
+	/// Create a record of `game`
+	pub fn new(game: &'g Game) -> Self {
+		Self { game }
+	}
+}
+impl fmt::Display for GameRecord<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let game = self.game;
+		let players = &game.players;
+		let rules = match (game.mode, game.turn_structure) {
+			(GameMode::Solo, _) => "solo",
+			(_, TurnStructure::GivePiece) => "classic rules",
+			(_, TurnStructure::FreeChoice) => "free choice",
+		};
+		let order: Vec<String> = players.order().iter().map(|p| p.to_string()).collect();
+		write!(f, "Quart, {}, players: {}", rules, order.join(" "))?;
+		if players.has_teams() {
+			let teams: Vec<String> = players.order().iter()
+				.filter_map(|p| players.team_of(*p))
+				.map(|t| t.to_string())
+				.collect();
+			write!(f, ", teams: {}", teams.join(" "))?;
+		}
+		writeln!(f)?;
+
+		for (i, turn) in game.history.iter().enumerate() {
+			if turn.selected_by == turn.placed_by {
+				write!(f, "{}. {} chooses {}", i + 1, players.label(turn.selected_by), turn.piece)?;
+			} else {
+				write!(f, "{}. {} gives {} to {}", i + 1, players.label(turn.selected_by), turn.piece, players.label(turn.placed_by))?;
+			}
+			if let Some(pos) = turn.pos {
+				write!(f, ", placed at {}", pos)?;
+			}
+			writeln!(f)?;
+		}
+
+		if let Some(result) = game.solo_result() {
+			write!(f, "{:?}", result)?;
+		} else if let Some(winner) = game.winner() {
+			write!(f, "{} won", winner)?;
+		} else if game.is_over() {
+			write!(f, "Draw")?;
+		} else if let Some(piece) = game.selected_piece {
+			write!(f, "Running, {} has to place {}", game.player_label(), piece)?;
+		} else {
+			write!(f, "Running, {} has to select a piece", game.player_label())?;
+		}
+		writeln!(f)
+	}
+}
+
+#[test]
+fn test_team_record() {
+	use crate::{BPos, Piece, Players};
+	let players = Players::teams([1, 2, 3, 4]).unwrap();
+	let mut game = Game::with_players(TurnStructure::GivePiece, players);
+	let pieces: Vec<Piece> = Piece::all().filter(|p| p.big).collect();
+	for (x, piece) in pieces.iter().take(4).enumerate() {
+		game.select_next_piece(*piece).unwrap();
+		game.place_piece(BPos::new(x as u16, 0)).unwrap();
+	}
+	// player 1 gave the first piece, so player 1 (Team 1) placed the last one
+	assert_eq!(game.player_turn, 1);
+	let record = game.record().to_string();
+	assert!(record.starts_with("Quart, classic rules, players: 1 2 3 4, teams: 1 2 1 2\n"));
+	assert!(record.contains("4. Player 4 (Team 2) gives BdRF to Player 1 (Team 1), placed at D4\n"));
+	assert!(record.ends_with("Team 1 (players 1 and 3) won\n"));
+}
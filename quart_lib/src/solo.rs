@@ -0,0 +1,115 @@
+use crate::board::{check_fields, lines_through};
+use crate::{BPos, Board, Piece};
+
+/// Whether the pieces which are not on `board` yet can be placed on the empty
+/// cells without ever completing a row, column or diagonal
+pub fn can_complete(board: &Board) -> bool {
+	complete(board).is_some()
+}
+
+/// Find a way to place all pieces which are not on `board` yet without
+/// completing a line. Returns the full board on success
+pub fn complete(board: &Board) -> Option<Board> {
+	if board.check().is_some() {
+		return None;
+	}
+	let mut board = board.clone();
+	let mut pieces: Vec<Piece> = Piece::all().filter(|p| !board.contains(*p)).collect();
+	if fill(&mut board, &mut pieces) {
+		Some(board)
+	} else {
+		None
+	}
+}
+
+/// Whether `piece` can be placed at the empty cell `pos` without completing a line
+fn fits(board: &mut Board, pos: BPos, piece: Piece) -> bool {
+	board[pos] = Some(piece);
+	let fits = lines_through(pos).iter().all(|line| {
+		let fields: Vec<Option<Piece>> = line.iter().map(|p| board[*p]).collect();
+		check_fields(&fields).is_none()
+	});
+	board[pos] = None;
+	fits
+}
+
+/// Backtracking: always fill the empty cell with the least fitting pieces first
+fn fill(board: &mut Board, pieces: &mut Vec<Piece>) -> bool {
+	let mut best: Option<(BPos, Vec<Piece>)> = None;
+	for y in 0..4 {
+		for x in 0..4 {
+			let pos = BPos::new(x, y);
+			if board[pos].is_some() {
+				continue;
+			}
+			let candidates: Vec<Piece> = pieces.iter().cloned().filter(|p| fits(board, pos, *p)).collect();
+			if candidates.is_empty() {
+				// this cell can't be filled anymore
+				return false;
+			}
+			let better = match &best {
+				Some((_, best_candidates)) => candidates.len() < best_candidates.len(),
+				None => true,
+			};
+			if better {
+				best = Some((pos, candidates));
+			}
+		}
+	}
+	let (pos, candidates) = match best {
+		Some(best) => best,
+		// no empty cell left
+		None => return true,
+	};
+	for piece in candidates {
+		let idx = pieces.iter().position(|p| *p == piece).unwrap();
+		pieces.swap_remove(idx);
+		board[pos] = Some(piece);
+		if fill(board, pieces) {
+			return true;
+		}
+		board[pos] = None;
+		pieces.push(piece);
+	}
+	false
+}
+
+#[test]
+fn test_complete_empty_board() {
+	let full = complete(&Board::default()).expect("the empty board is solvable");
+	assert_eq!(full.piece_count(), 16);
+	assert!(full.check().is_none());
+	assert!(Piece::all().all(|p| full.contains(p)));
+}
+
+#[test]
+fn test_complete_lost_board() {
+	let mut board = Board::default();
+	for (x, piece) in Piece::all().filter(|p| p.big).take(4).enumerate() {
+		board[(x as u16, 0)] = Some(piece);
+	}
+	assert!(!can_complete(&board));
+}
+
+#[test]
+fn test_solo_game() {
+	use crate::{Game, SoloResult};
+	let solution = complete(&Board::default()).unwrap();
+	let mut game = Game::new_solo();
+	for y in 0..4 {
+		for x in 0..4 {
+			let pos = BPos::new(x, y);
+			game.select_next_piece(solution[pos].unwrap()).unwrap();
+			game.place_piece(pos).unwrap();
+			assert_eq!(game.player_turn, 1);
+		}
+	}
+	assert_eq!(game.solo_result(), Some(SoloResult::Solved));
+
+	// undo placing the last piece and selecting it
+	assert!(game.undo());
+	assert!(game.undo());
+	assert_eq!(game.solo_result(), None);
+	assert!(game.solo_solvable());
+	assert_eq!(game.board.piece_count(), 15);
+}
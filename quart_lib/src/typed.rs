@@ -0,0 +1,212 @@
+use crate::{BPos, Board, GameError, GameOverInfo, GameState, Piece, Winner};
+use std::{fmt, marker::PhantomData};
+
+/// State marker: the current player has to select a piece
+/// (for the next player, or for himself with `TurnStructure::FreeChoice`)
+#[derive(Debug)]
+pub struct NeedsGive;
+
+/// State marker: the current player has to place the selected piece
+#[derive(Debug)]
+pub struct NeedsPlace;
+
+/// A `crate::Game` whose state is known at compile time.
+/// Every method consumes the game and returns it in its next state,
+/// so calling them in the wrong order doesn't compile:
+///
+/// ```compile_fail
+/// use quart_lib::typed;
+/// let game = typed::Game::new();
+/// // a piece has to be given before it can be placed
+/// game.place(quart_lib::BPos::new(0, 0));
+/// ```
+#[derive(Debug)]
+pub struct Game<S> {
+	game: Box<crate::Game>,
+	state: PhantomData<S>,
+}
+impl<S> Game<S> {
+	fn wrap(game: Box<crate::Game>) -> Self {
+		Self { game, state: PhantomData }
+	}
+
+	/// The underlying dynamic game
+	pub fn inner(&self) -> &crate::Game {
+		&self.game
+	}
+
+	/// Convert back into the dynamic game
+	pub fn into_inner(self) -> crate::Game {
+		*self.game
+	}
+
+	/// The board on which is played
+	pub fn board(&self) -> &Board {
+		&self.game.board
+	}
+
+	/// Which player's turn it is
+	pub fn player_turn(&self) -> u32 {
+		self.game.player_turn
+	}
+}
+
+impl Game<NeedsGive> {
+	/// Create a new two player game with classic rules
+	pub fn new() -> Self {
+		Self::wrap(Box::default())
+	}
+
+	/// Select `piece`, see `crate::Game::select_next_piece`
+	pub fn give(mut self, piece: Piece) -> Result<Game<NeedsPlace>, Rejected<Self>> {
+		match self.game.select_next_piece(piece) {
+			Ok(()) => Ok(Game::wrap(self.game)),
+			Err(error) => Err(Rejected { game: self, error }),
+		}
+	}
+}
+impl Default for Game<NeedsGive> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Game<NeedsPlace> {
+	/// The piece that has to be placed
+	pub fn piece(&self) -> Piece {
+		self.game.selected_piece.expect("`NeedsPlace` always has a selected piece")
+	}
+
+	/// Place the selected piece at `pos`, see `crate::Game::place_piece`
+	pub fn place(mut self, pos: BPos) -> Result<Placed, Rejected<Self>> {
+		match self.game.place_piece(pos) {
+			Ok(()) if self.game.is_over() => Ok(Placed::Finished(Finished { game: self.game })),
+			Ok(()) => Ok(Placed::Continue(Game::wrap(self.game))),
+			Err(error) => Err(Rejected { game: self, error }),
+		}
+	}
+}
+
+/// The result of placing a piece
+#[derive(Debug)]
+pub enum Placed {
+	/// The game goes on, the next piece has to be selected
+	Continue(Game<NeedsGive>),
+	/// The game is over
+	Finished(Finished),
+}
+
+/// A game that is over
+#[derive(Debug)]
+pub struct Finished {
+	game: Box<crate::Game>,
+}
+impl Finished {
+	/// The underlying dynamic game
+	pub fn inner(&self) -> &crate::Game {
+		&self.game
+	}
+
+	/// Convert back into the dynamic game
+	pub fn into_inner(self) -> crate::Game {
+		*self.game
+	}
+
+	/// Who won, `None` on a draw (or in solo mode)
+	pub fn winner(&self) -> Option<Winner> {
+		self.game.winner()
+	}
+
+	/// Which line was completed, `None` if the board was filled without completing one
+	pub fn game_over_info(&self) -> Option<&GameOverInfo> {
+		self.game.game_over_info.as_ref()
+	}
+}
+
+/// An action was rejected, the game is handed back unchanged
+#[derive(Debug)]
+pub struct Rejected<G> {
+	/// The unchanged game
+	pub game: G,
+	/// Why the action was rejected
+	pub error: GameError,
+}
+impl<G> fmt::Display for Rejected<G> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.error)
+	}
+}
+impl<G: fmt::Debug> std::error::Error for Rejected<G> {}
+impl<G> From<Rejected<G>> for GameError {
+	fn from(rejected: Rejected<G>) -> Self {
+		rejected.error
+	}
+}
+
+/// A typed game in any state, created from a dynamic `crate::Game`
+#[derive(Debug)]
+pub enum AnyGame {
+	/// A piece has to be selected
+	NeedsGive(Game<NeedsGive>),
+	/// The selected piece has to be placed
+	NeedsPlace(Game<NeedsPlace>),
+	/// The game is over
+	Finished(Finished),
+}
+impl From<crate::Game> for AnyGame {
+	fn from(game: crate::Game) -> Self {
+		let mut game = Box::new(game);
+		game.check();
+		match game.state {
+			GameState::GameOver => AnyGame::Finished(Finished { game }),
+			GameState::PlacePiece if game.selected_piece.is_some() => AnyGame::NeedsPlace(Game::wrap(game)),
+			_ => {
+				game.state = GameState::SelectPiece;
+				game.selected_piece = None;
+				AnyGame::NeedsGive(Game::wrap(game))
+			},
+		}
+	}
+}
+impl crate::Game {
+	/// Convert into the typestate API, see `AnyGame`
+	pub fn into_typed(self) -> AnyGame {
+		AnyGame::from(self)
+	}
+}
+impl From<Placed> for AnyGame {
+	fn from(placed: Placed) -> Self {
+		match placed {
+			Placed::Continue(game) => AnyGame::NeedsGive(game),
+			Placed::Finished(finished) => AnyGame::Finished(finished),
+		}
+	}
+}
+
+#[test]
+fn test_typed_game() {
+	let pieces: Vec<Piece> = Piece::all().filter(|p| p.big).collect();
+	let mut game = Game::new();
+	for (x, piece) in pieces.iter().take(3).enumerate() {
+		game = match game.give(*piece).unwrap().place(BPos::new(x as u16, 0)).unwrap() {
+			Placed::Continue(game) => game,
+			Placed::Finished(_) => panic!("no line completed yet"),
+		};
+	}
+
+	// the piece is in use, we get the game back
+	let rejected = game.give(pieces[0]).unwrap_err();
+	assert_eq!(rejected.error.to_string(), GameError::PieceInUse.to_string());
+	let game = rejected.game.give(pieces[3]).unwrap();
+	assert_eq!(game.piece(), pieces[3]);
+
+	// converting to a dynamic game and back keeps the state
+	let game = match game.into_inner().into_typed() {
+		AnyGame::NeedsPlace(game) => game,
+		other => panic!("unexpected state {:?}", other),
+	};
+	match game.place(BPos::new(3, 0)).unwrap() {
+		Placed::Finished(finished) => assert_eq!(finished.winner(), Some(Winner::Player(1))),
+		Placed::Continue(_) => panic!("the row is complete"),
+	}
+}
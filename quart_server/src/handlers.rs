@@ -1,5 +1,7 @@
-use quart_lib::BPos;
+use quart_lib::{BPos, Game, Players, TurnStructure};
+use quart_ai_enemy::AgentConfig;
 use std::path::PathBuf;
+use std::time::Duration;
 use actix_web::{
 	web::{self, Data},
 	Result,
@@ -10,8 +12,12 @@ use actix_web::{
 };
 use actix_files::NamedFile;
 use super::AppState;
+use crate::ui_state::UiState;
 use crate::render::render;
 
+/// How often a request waiting for the AI looks whether it is done
+const AI_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
 // HANDLER FUNCTIONS
 pub async fn file(req: HttpRequest) -> Result<NamedFile> {
 	let path: PathBuf = req.match_info().query("filename").parse()?;
@@ -47,21 +53,93 @@ pub async fn mov_cur_to(app_state: Data<AppState>, pos: web::Path<(u8,u8)>) -> H
 
 pub async fn enter(app_state: Data<AppState>) -> HttpResponse {
 	log::info!("Requested: Enter");
-	let mut ui_state = app_state.ui_state.lock().unwrap();
-	ui_state.enter();
-	ui_state.game.check();
+	{
+		let mut ui_state = app_state.ui_state.lock().unwrap();
+		// nothing is played for the AI while it thinks
+		if !ui_state.is_ai_thinking() {
+			ui_state.enter();
+			ui_state.game.check();
+			ui_state.play_ai();
+		}
+	}
+	render_after_ai(&app_state).await
+}
 
-	let s = render(&ui_state);
-	HttpResponse::Ok().content_type("text/html").body(s)
+pub async fn new_game(app_state: Data<AppState>, variant: web::Path<String>) -> HttpResponse {
+	log::info!("Requested: New Game {:?}", variant);
+	{
+		let mut ui_state = app_state.ui_state.lock().unwrap();
+		// "free" is the beginner variant where players choose their own pieces,
+		// "3" and "4" are played by that many players, "teams" by two teams of two players
+		let game = match variant.as_str() {
+			"free" => Game::with_turn_structure(TurnStructure::FreeChoice),
+			"3" => Game::with_players(TurnStructure::GivePiece, Players::new(vec![1, 2, 3]).unwrap()),
+			"4" => Game::with_players(TurnStructure::GivePiece, Players::new(vec![1, 2, 3, 4]).unwrap()),
+			"teams" => Game::with_players(TurnStructure::GivePiece, Players::teams([1, 2, 3, 4]).unwrap()),
+			_ => Game::new(),
+		};
+		let (ai_config, ai_player) = (ui_state.ai_config, ui_state.ai_player);
+		let ai_setup = std::mem::take(&mut ui_state.ai_setup);
+		ui_state.cancel_ai();
+		*ui_state = UiState::new(game);
+		ui_state.ai_setup = ai_setup;
+		// the AI keeps its player if they take part in the new game
+		ui_state.set_ai_player(ai_player);
+		ui_state.set_ai(ai_config);
+		ui_state.play_ai();
+	}
+	render_after_ai(&app_state).await
+}
+
+pub async fn set_ai_player(app_state: Data<AppState>, player: web::Path<u32>) -> HttpResponse {
+	log::info!("Requested: AI player {:?}", player);
+	{
+		let mut ui_state = app_state.ui_state.lock().unwrap();
+		ui_state.set_ai_player(*player);
+		ui_state.play_ai();
+	}
+	render_after_ai(&app_state).await
+}
+
+pub async fn set_ai(app_state: Data<AppState>, name: web::Path<String>) -> HttpResponse {
+	log::info!("Requested: AI {:?}", name);
+	{
+		let mut ui_state = app_state.ui_state.lock().unwrap();
+		// agents and difficulties are looked up in the registry, "human" plays without AI
+		match AgentConfig::by_name(name.as_str()) {
+			Some(config) => ui_state.set_ai(Some(config)),
+			None if name.as_str() == "human" => ui_state.set_ai(None),
+			None => log::warn!("Unknown AI {:?}", name),
+		}
+		ui_state.play_ai();
+	}
+	render_after_ai(&app_state).await
 }
 
 pub async fn show(app_state: Data<AppState>) -> HttpResponse {
 	log::info!("Requested: Show");
-	let ui_state = app_state.ui_state.lock().unwrap();
+	let mut ui_state = app_state.ui_state.lock().unwrap();
+	ui_state.poll_ai();
 	let s = render(&ui_state);
 	HttpResponse::Ok().content_type("text/html").body(s)
 }
 
+/// Wait for the AI to move and render the game. The AI thinks on a thread
+/// of its own, and other requests are served while waiting
+async fn render_after_ai(app_state: &AppState) -> HttpResponse {
+	loop {
+		{
+			let mut ui_state = app_state.ui_state.lock().unwrap();
+			ui_state.poll_ai();
+			if !ui_state.is_ai_thinking() {
+				let s = render(&ui_state);
+				return HttpResponse::Ok().content_type("text/html").body(s);
+			}
+		}
+		actix_rt::time::delay_for(AI_POLL_INTERVAL).await;
+	}
+}
+
 // GUARD FUNCTION
 pub fn board_pos_guard(req: &RequestHead) -> bool {
 	let path: &str = req.uri.path();
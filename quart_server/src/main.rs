@@ -6,6 +6,7 @@ mod render;
 mod ui_state;
 
 use quart_lib::Game;
+use quart_ai_enemy::AgentSetup;
 use std::sync::Mutex;
 use actix_web::{
 	web::{self, Data},
@@ -31,8 +32,10 @@ async fn main() -> std::io::Result<()>{
         .start()
         .unwrap();
 
+	let mut ui_state = UiState::new(Game::new());
+	ui_state.ai_setup = AgentSetup::from_env();
 	let game = Data::new(AppState {
-		ui_state: Mutex::new(UiState::new(Game::new()))
+		ui_state: Mutex::new(ui_state)
 	});
 
 	// for live reloading
@@ -54,6 +57,9 @@ async fn main() -> std::io::Result<()>{
 				)
 			)
 			.route("/enter", web::get().to(handlers::enter))
+			.route("/new/{variant}", web::get().to(handlers::new_game))
+			.route("/ai/{name}", web::get().to(handlers::set_ai))
+			.route("/ai_player/{player}", web::get().to(handlers::set_ai_player))
 			.route("/", web::get().to(handlers::show))
 			.route("/s/{filename:.*}", web::get().to(handlers::file))
 	});
@@ -1,18 +1,34 @@
 use quart_lib::{Board, BPos, GameState, Piece};
 use crate::ui_state::UiState;
+use quart_ai_enemy::Difficulty;
 use itertools::join;
 
 pub fn render(ui_state: &UiState) -> String {
 	// whether the main board is active
 	let main_act = ui_state.game.state != GameState::SelectPiece;
-	let status_msg = if ui_state.game.state == GameState::GameOver {
-		format!("<h2 class='msg_game_over'>Game Over, player {} won!</h2>", ui_state.game.player_turn)
+	let status_msg = if ui_state.game.state == GameState::GameOver && ui_state.game.game_over_info.is_none() {
+		"<h2 class='msg_game_over'>Game Over, draw!</h2>".to_owned()
+	} else if let Some(winner) = ui_state.game.winner() {
+		format!("<h2 class='msg_game_over'>Game Over, {} won!</h2>", winner)
 	} else {
-		format!("<h2 class='msg_player_turn'>Player {}s turn</h2>", ui_state.game.player_turn)
+		format!("<h2 class='msg_player_turn'>{}s turn</h2>", ui_state.game.player_label())
 	};
+	let ai_player = ui_state.game.players.label(ui_state.ai_player);
+	let opponent = match ui_state.ai_config {
+		Some(config) => format!("{} is the AI ({})", ai_player, config.name()),
+		None => format!("{} is human", ai_player),
+	};
+	let ai_player_links = join(
+		ui_state.game.players.order().iter().map(|p| format!("<a href='/ai_player/{p}'>Player {p}</a>", p = p)),
+		"\n",
+	);
+	let ai_links = join(
+		Difficulty::ALL.iter().map(|d| format!("<a href='/ai/{d}'>{d}</a>", d = d)),
+		"\n",
+	);
 	let cursor = ui_state.cursor_pos;
 	let board1 = render_board(&ui_state.game.board, "main_board", cursor, main_act);
-	let selected_piece = render_selected_piece(ui_state.selected_piece);
+	let selected_piece = render_selected_piece(ui_state.game.selected_piece);
 	let board2 = render_board(&ui_state.pieces_board, "pieces_board", cursor, !main_act);
 	format!(r#"
       <html>
@@ -33,8 +49,24 @@ pub fn render(ui_state: &UiState) -> String {
 		  <div id="status_msg">
 			{status_msg}
 		  </div>
+		  <div id="new_game">
+			<a href="/new/classic">New game</a>
+			<a href="/new/free">New beginner game</a>
+			<a href="/new/3">New 3 player game</a>
+			<a href="/new/4">New 4 player game</a>
+			<a href="/new/teams">New team game</a>
+		  </div>
+		  <div id="opponent">
+			{opponent}:
+			<a href='/ai/human'>human</a>
+			{ai_links}
+		  </div>
+		  <div id="ai_player">
+			The AI plays as:
+			{ai_player_links}
+		  </div>
         </body>
-      </html>"#, status_msg=status_msg, board1=board1, selected_piece=selected_piece, board2=board2)
+      </html>"#, status_msg=status_msg, opponent=opponent, ai_links=ai_links, ai_player_links=ai_player_links, board1=board1, selected_piece=selected_piece, board2=board2)
 }
 
 fn render_board(board: &Board, id: &'static str, cursor: BPos, sel: bool) -> String {
@@ -1,4 +1,5 @@
-use quart_lib::{Game, Board, BPos, GameState, Piece};
+use quart_lib::{Game, Board, BPos, GameMode, GameState};
+use quart_ai_enemy::{AgentConfig, AgentRunner, AgentSetup, AiAgent, Checked, Progress};
 
 /// Current User Interface State (cursor position, highlighted fields, ...)
 pub struct UiState {
@@ -11,8 +12,13 @@ pub struct UiState {
     /// The board where the rest of the pieces is
     pub pieces_board: Board,
 
-    /// If there was a piece selected, it will be stored here
-    pub selected_piece: Option<Piece>,
+	/// Which agent plays as `ai_player`, `None` if a human does
+	pub ai_config: Option<AgentConfig>,
+	/// Which player the agent plays
+	pub ai_player: u32,
+	/// What the agent is created with besides its config
+	pub ai_setup: AgentSetup,
+	ai: Option<AgentRunner<Checked<Box<dyn AiAgent + Send>>>>,
 }
 
 impl UiState {
@@ -22,9 +28,80 @@ impl UiState {
 			game,
 			cursor_pos: BPos::new(0, 0),
 			pieces_board: Board::full(),
-			selected_piece: None,
+			ai_config: None,
+			ai_player: 2,
+			ai_setup: AgentSetup::default(),
+			ai: None,
 		}
 	}
+
+	/// Let the agent of `config` play as `ai_player`, or a human if `None`.
+	/// The agent playing before is cancelled
+	pub fn set_ai(&mut self, config: Option<AgentConfig>) {
+		self.cancel_ai();
+		let (game, setup) = (&self.game, &self.ai_setup);
+		self.ai = config.map(|config| AgentRunner::new(Checked::new(config.create_with(game, setup))));
+		self.ai_config = config;
+	}
+
+	/// Let the agent play as `player`, if they take part in the game.
+	/// The move the agent was thinking about isn't played
+	pub fn set_ai_player(&mut self, player: u32) {
+		if self.game.players.order().contains(&player) {
+			self.cancel_ai();
+			self.ai_player = player;
+		}
+	}
+
+	/// Stop the agent thinking about a move, its move isn't played
+	pub fn cancel_ai(&mut self) {
+		if let Some(ai) = self.ai.as_mut() {
+			ai.cancel();
+		}
+	}
+
+	/// If it's the agent's turn, let it start thinking, see `poll_ai`
+	pub fn play_ai(&mut self) {
+		let ai = match self.ai.as_mut() {
+			Some(ai) => ai,
+			None => return,
+		};
+		if ai.is_thinking() || self.game.is_over() || self.game.mode != GameMode::Versus || self.game.player_turn != self.ai_player {
+			return;
+		}
+		ai.start(&self.game);
+	}
+
+	/// Whether the agent is thinking about its move
+	pub fn is_ai_thinking(&self) -> bool {
+		self.ai.as_ref().is_some_and(AgentRunner::is_thinking)
+	}
+
+	/// Play the move of the agent once it is done. Whether it moved
+	pub fn poll_ai(&mut self) -> bool {
+		let ai = match self.ai.as_mut() {
+			Some(ai) => ai,
+			None => return false,
+		};
+		while let Some(progress) = ai.poll() {
+			match progress {
+				Progress::Info { depth, score, nodes, .. } => log::trace!("AI thinks: depth {}, score {}, {} nodes", depth, score, nodes),
+				// moves of the `Checked` agent are valid
+				Progress::Done(Ok(mov)) => {
+					log::debug!("AI plays {:?}", mov);
+					if mov.apply(&mut self.game).is_err() {
+						return false;
+					}
+					for piece in mov.placement.map(|p| p.piece).iter().chain(mov.give.iter()) {
+						self.pieces_board.remove(*piece);
+					}
+					return true;
+				},
+				Progress::Done(Err(e)) => log::warn!("AI couldn't move: {}", e),
+			}
+		}
+		false
+	}
 	
     /// Move the cursor position by some given deltas
     pub fn move_cursor(&mut self, dx: i32, dy: i32) {
@@ -49,16 +126,15 @@ impl UiState {
     pub fn enter(&mut self) {
         match self.game.state {
             GameState::SelectPiece => {
-                self.selected_piece = self.pieces_board[self.cursor_pos].take();
-                if self.selected_piece.is_some() {
-                    self.game.state = GameState::PlacePiece;
-                    self.game.player_turn = 3 - self.game.player_turn;
+                if let Some(piece) = self.pieces_board[self.cursor_pos] {
+                    if self.game.select_next_piece(piece).is_ok() {
+                        self.pieces_board[self.cursor_pos] = None;
+                    }
                 }
             }
             GameState::PlacePiece => {
-                if self.game.board[self.cursor_pos].is_none() {
-                    self.game.board[self.cursor_pos] = self.selected_piece.take();
-                    self.game.state = GameState::SelectPiece;
+                if let Err(e) = self.game.place_piece(self.cursor_pos) {
+                    log::warn!("GameError: {:?}", e);
                 }
             }
             GameState::GameOver => {}
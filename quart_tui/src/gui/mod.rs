@@ -34,13 +34,19 @@ pub enum Event {
 	CursorToY(u16),
 	/// Move cursor to specific position
 	CursorToPos(BPos),
+	/// Undo the last action
+	Undo,
+	/// Ask whether the game can still be won (solo mode)
+	Hint,
+	/// Nothing happened for a moment, e.g. to look whether the AI is done
+	Tick,
 }
 
 /// A generic user interface
 pub trait Gui {
 	/// Draw Gui
 	fn draw(&mut self, ui_state: &UiState) -> Result<()>;
-	/// Poll pending events
+	/// Poll pending events, `Event::Tick` if there is none for a moment
 	fn poll_event(&mut self, ui_state: &UiState) -> Option<Event>;
 }
 
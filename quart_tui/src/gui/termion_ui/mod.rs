@@ -6,9 +6,12 @@ mod util;
 
 pub use self::util::*;
 use super::{Gui, Event};
-use quart_lib::{GameState::*, BPos};
+use quart_lib::{GameState::*, BPos, GameMode, SoloResult};
 use crate::UiState;
 use std::io::{self, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
 use termion::{
     clear,
     cursor,
@@ -18,9 +21,14 @@ use termion::{
     raw::{IntoRawMode, RawTerminal},
 };
 
+/// How long `poll_event` waits for input before it returns `Event::Tick`
+const TICK: Duration = Duration::from_millis(100);
+
 /// TUI based on termion
 pub struct TermionGui {
 	out: MouseTerminal<RawTerminal<io::Stdout>>,
+	/// Input read on a thread of its own, so waiting for it can time out
+	events: Receiver<TEvent>,
 }
 impl TermionGui {
 	/// Create new instance, switch to alternate screen
@@ -37,8 +45,18 @@ impl TermionGui {
 			log::error!("{}", info);
 			default_panic_hook(info);
 	    }));
+	    // the thread is blocked reading stdin until the process exits
+	    let (sender, events) = mpsc::channel();
+	    thread::spawn(move || {
+			for event in io::stdin().events().filter_map(Result::ok) {
+				if sender.send(event).is_err() {
+					break;
+				}
+			}
+	    });
 		Ok(Self {
-			out: stdout
+			out: stdout,
+			events,
 		})
 	}
 }
@@ -47,12 +65,18 @@ impl Gui for TermionGui{
 		draw(&mut self.out, ui_state, None)
 	}
 	fn poll_event(&mut self, ui_state: &UiState) -> Option<Event> {
-		io::stdin()
-			.events()
-			.filter_map(Result::ok)
-			.inspect(|e| log::debug!("Event: {:?}", e))
-			.filter_map(|te| event_from_termion_event(te, ui_state))
-			.next()
+		loop {
+			match self.events.recv_timeout(TICK) {
+				Ok(te) => {
+					log::debug!("Event: {:?}", te);
+					if let Some(event) = event_from_termion_event(te, ui_state) {
+						return Some(event);
+					}
+				},
+				Err(RecvTimeoutError::Timeout) => return Some(Event::Tick),
+				Err(RecvTimeoutError::Disconnected) => return None,
+			}
+		}
 	}
 }
 impl Drop for TermionGui {
@@ -77,6 +101,8 @@ fn event_from_termion_event(e: TEvent, ui_state: &UiState) -> Option<Event> {
                 Event::CursorToY(3 - "1234".find(n).unwrap() as u16)
             }
             Key::Char('\n') => Event::Enter,
+            Key::Char('u') => Event::Undo,
+            Key::Char('?') => Event::Hint,
             _ => return None,
         },
         TEvent::Mouse(m) => match m {
@@ -153,15 +179,22 @@ fn draw<W: Write>(mut out: W, ui_state: &UiState, layout: Option<&Layout>) -> io
     let label_pos = layout.pieces_board.translated_i32(2, -1);
     draw_label(&mut out, label_pos, 25, PIECES_BOARD_LABEL)?;
 
-    let status_str = if ui_state.game.is_over() {
-	    if let Some(goi) = ui_state.game.game_over_info.as_ref() {
-	        format!("Player {} won because of {}", ui_state.game.player_turn, goi.property)
+    let status_str = if let Some(msg) = ui_state.message.as_ref() {
+		msg.clone()
+    } else if ui_state.game.mode == GameMode::Solo {
+		match (ui_state.game.solo_result(), ui_state.game.game_over_info.as_ref()) {
+			(Some(SoloResult::Solved), _) => "Solved, no line completed!".to_owned(),
+			(Some(SoloResult::Failed), Some(goi)) => format!("Failed, line of common {} ('u' to undo)", goi.property),
+			_ => "Place all pieces without completing a line".to_owned(),
+		}
+    } else if ui_state.game.is_over() {
+	    if let (Some(winner), Some(goi)) = (ui_state.game.winner(), ui_state.game.game_over_info.as_ref()) {
+	        format!("{} won because of {}", winner, goi.property)
 	    } else {
-			log::error!("Should be Some(..)");
-			String::new()
+			"Draw, the board is full".to_owned()
 	    }
     } else {
-        format!("Player {}'s turn!", ui_state.game.player_turn)
+        format!("{}'s turn!", ui_state.game.player_label())
     };
     draw_label(&mut out, layout.status_label, std::cmp::max(25,status_str.len() as u16), &status_str)?;
 
@@ -6,7 +6,7 @@ pub mod gui;
 /// Contains the User Interface State
 pub mod ui_state;
 
-use quart_lib::{board::*, Game, GameState::*};
+use quart_lib::{board::*, Game, GameState::*, Players, SoloResult, TurnStructure};
 use self::gui::{Gui, Event};
 use self::ui_state::UiState;
 
@@ -20,6 +20,143 @@ fn main() -> Result<()> {
 	log::info!("{:?}", res);
 	res
 }
+/// Create the game from the command line arguments:
+/// - `--solo`: the single player puzzle
+/// - `--free`: the beginner variant where players choose their own pieces
+/// - `--players N`: N players (2 to 4) taking turns
+/// - `--teams`: two teams of two players
+fn game_from_args(args: &[String]) -> Result<Game> {
+	if args.iter().any(|arg| arg == "--solo") {
+		return Ok(Game::new_solo());
+	}
+	let turn_structure = if args.iter().any(|arg| arg == "--free") {
+		TurnStructure::FreeChoice
+	} else {
+		TurnStructure::GivePiece
+	};
+	let players = if args.iter().any(|arg| arg == "--teams") {
+		Players::teams([1, 2, 3, 4])?
+	} else if let Some(idx) = args.iter().position(|arg| arg == "--players") {
+		let count: u32 = args.get(idx + 1).ok_or("--players needs a number")?.parse()?;
+		Players::new((1..=count).collect())?
+	} else {
+		Players::two()
+	};
+	Ok(Game::with_players(turn_structure, players))
+}
+
+/// Where the skills of the players against the adaptive AI are kept: `--skills PATH`,
+/// else the file in `QUART_SKILLS`, else `skills.txt` in the current directory
+#[cfg(feature = "ai_enemy")]
+fn skills_path_from_args(args: &[String]) -> Result<std::path::PathBuf> {
+	if let Some(idx) = args.iter().position(|arg| arg == "--skills") {
+		return Ok(args.get(idx + 1).ok_or("--skills needs a path")?.into());
+	}
+	Ok(std::env::var_os("QUART_SKILLS").map_or_else(|| "skills.txt".into(), Into::into))
+}
+
+/// Create the AI opponent from the command line arguments:
+/// `--ai NAME` chooses an agent or a difficulty from the registry,
+/// `--adaptive PLAYER` an AI adapting to the skill of PLAYER (see `AdaptiveAgent`
+/// and `skills_path_from_args`),
+/// otherwise the default agent is used. Agents of the registry get the weights and
+/// tablebase of the environment, see `AgentSetup::from_env`
+#[cfg(feature = "ai_enemy")]
+fn ai_from_args(args: &[String], game: &Game) -> Result<Box<dyn AiAgent + Send>> {
+	if let Some(idx) = args.iter().position(|arg| arg == "--adaptive") {
+		let player = args.get(idx + 1).ok_or("--adaptive needs a player name")?;
+		return Ok(Box::new(AdaptiveAgent::load(skills_path_from_args(args)?, player)?));
+	}
+	let idx = match args.iter().position(|arg| arg == "--ai") {
+		Some(idx) => idx,
+		None => return Ok(registry::AgentConfig::default().create_with(game, &AgentSetup::from_env())),
+	};
+	let name = args.get(idx + 1).ok_or("--ai needs a name")?;
+	match registry::AgentConfig::by_name(name) {
+		Some(config) => Ok(config.create_with(game, &AgentSetup::from_env())),
+		None => {
+			let names: Vec<String> = registry::AGENTS.iter().map(|(name, _)| name.to_string())
+				.chain(Difficulty::ALL.iter().map(|d| d.to_string()))
+				.collect();
+			Err(format!("unknown AI {:?}, choose one of: {}", name, names.join(", ")).into())
+		},
+	}
+}
+
+/// Which player the AI plays: `--ai-player N`, player 2 by default
+#[cfg(feature = "ai_enemy")]
+fn ai_player_from_args(args: &[String]) -> Result<u32> {
+	match args.iter().position(|arg| arg == "--ai-player") {
+		Some(idx) => Ok(args.get(idx + 1).ok_or("--ai-player needs a number")?.parse()?),
+		None => Ok(2),
+	}
+}
+
+/// The AI opponent, thinking on a thread of its own
+#[cfg(feature = "ai_enemy")]
+type AiRunner = AgentRunner<Checked<Box<dyn AiAgent + Send>>>;
+
+/// If it's the turn of `ai_player`, let the AI start thinking
+#[cfg(feature = "ai_enemy")]
+fn ai_turn(ai: &mut AiRunner, ai_player: u32, ui_state: &mut UiState) {
+	use quart_lib::GameMode;
+	if ai.is_thinking() || ui_state.game.is_over() || ui_state.game.mode != GameMode::Versus || ui_state.game.player_turn != ai_player {
+		return;
+	}
+	// after an illegal move, the AI gives up instead of trying again
+	if ai.agent_mut().is_some_and(|agent| agent.forfeited()) {
+		ui_state.message = Some("The AI forfeited the game".to_string());
+		return;
+	}
+	ai.start(&ui_state.game);
+	ui_state.message = Some("The AI is thinking".to_string());
+}
+
+/// Undo the last action, and the moves of `ai_player` before it, so it's the
+/// human's turn again instead of the AI replaying its move
+#[cfg(feature = "ai_enemy")]
+fn ai_undo(ui_state: &mut UiState, ai_player: u32) {
+	use quart_lib::GameMode;
+	ui_state.undo();
+	if ui_state.game.mode == GameMode::Versus {
+		while ui_state.game.player_turn == ai_player && ui_state.game.undo() {}
+		ui_state.sync_pieces_board();
+	}
+}
+
+/// Show the progress of the AI, and play its move once it is done.
+/// With `ponder`, the AI goes on thinking during the opponent's turn.
+/// Whether something changed
+#[cfg(feature = "ai_enemy")]
+fn ai_poll(ai: &mut AiRunner, ponder: bool, ui_state: &mut UiState) -> bool {
+	let result = match ai.poll() {
+		Some(Progress::Info { depth, nodes, best, .. }) => {
+			let best = best.map(|mov| mov.to_string()).unwrap_or_default();
+			ui_state.message = Some(format!("The AI is thinking: depth {}, {} positions, best {}", depth, nodes, best));
+			return true;
+		},
+		Some(Progress::Done(result)) => result,
+		None => return false,
+	};
+	let result = result
+		.and_then(|mov| mov.apply(&mut ui_state.game).map_err(|e| AgentError::IllegalMove(mov, e)));
+	match result {
+		Ok(()) => {
+			ui_state.sync_pieces_board();
+			ui_state.message = None;
+			if ponder {
+				ai.ponder(&ui_state.game);
+			}
+		},
+		Err(AgentError::Cancelled) => return false,
+		Err(e) => {
+			log::warn!("AgentError: {}", e);
+			ui_state.message = Some(format!("The AI couldn't move: {}", e));
+		},
+	}
+	true
+}
+
 fn run() -> Result<()> {
     flexi_logger::Logger::with_env_or_str("info, quart_tui::gui=info, quart_ai_enemy=trace")
         .log_to_file()
@@ -30,19 +167,47 @@ fn run() -> Result<()> {
         .unwrap();
 
     // game state
-    let mut ui_state = UiState::new(Game::new());
+    let args: Vec<String> = std::env::args().collect();
+    let game = game_from_args(&args)?;
+    let mut ui_state = UiState::new(game);
     log::debug!("Created game");
 
 	#[cfg(feature = "ai_enemy")]
-    let mut ai_agent: Box<dyn AiAgent> = get_ai_agent(&ui_state.game);
+    let mut ai = AgentRunner::new(Checked::new(ai_from_args(&args, &ui_state.game)?).forfeit());
+	#[cfg(feature = "ai_enemy")]
+    let ai_player = ai_player_from_args(&args)?;
+	// `--ponder`: the AI thinks ahead during the human's turn
+	#[cfg(feature = "ai_enemy")]
+    let ponder = args.iter().any(|arg| arg == "--ponder");
 
     let mut gui = gui::create_default()?;
 
     // initial drawing
     gui.draw(&ui_state)?;
 
+	// the AI may have the first turn
+	#[cfg(feature = "ai_enemy")] {
+		ai_turn(&mut ai, ai_player, &mut ui_state);
+		gui.draw(&ui_state)?;
+	}
+
     // game loop
     while let Some(event) = gui.poll_event(&ui_state) {
+		#[cfg(feature = "ai_enemy")] {
+			match event {
+				// nothing is played for the AI while it thinks
+				Event::Enter if ai.is_thinking() => continue,
+				Event::Undo => ai.cancel(),
+				_ => {},
+			}
+			if ai_poll(&mut ai, ponder, &mut ui_state) {
+				gui.draw(&ui_state)?;
+			}
+		}
+		if let Event::Tick = event {
+			continue;
+		}
+		ui_state.message = None;
         match event {
 			Event::Exit 			=> break,
 			Event::CursorUp 		=> ui_state.move_cursor(0, -1),
@@ -53,55 +218,53 @@ fn run() -> Result<()> {
 			Event::CursorToY(y) 	=> ui_state.set_cursor_y(y),
 			Event::Enter 			=> ui_state.enter(),
 			Event::CursorToPos(pos) => ui_state.set_cursor_pos(pos),
+			#[cfg(feature = "ai_enemy")]
+			Event::Undo 			=> ai_undo(&mut ui_state, ai_player),
+			#[cfg(not(feature = "ai_enemy"))]
+			Event::Undo 			=> ui_state.undo(),
+			Event::Hint 			=> ui_state.hint(),
+			Event::Tick 			=> {},
         }
 
         if ui_state.game.check() {
 	        log::info!("Game Over: {:?}", ui_state.game.game_over_info);
         }
 
-		#[cfg(feature = "ai_enemy")] {
-			use quart_lib::GameError;
-			if !ui_state.game.is_over() && ui_state.game.player_turn == 2 {
-				gui.draw(&ui_state)?; // redraw boards and piece preview
-
-				loop { // we let the ai_agent try again and again until he does a valid move
-					let (pos, piece) = ai_agent.play(&ui_state.game);
-					log::trace!("AI_Agent wants to put the selected piece at {:?} and select {:?} afterwards", pos, piece);
-
-					let place_piece_transaction = match ui_state.game.probe_place_piece(pos) {
-						Ok(transact) => transact,
-						e @ Err(GameError::NoPieceSelected) => e?, // propagate critical error
-						Err(e) => { // other GameErrors are less important
-							log::warn!("GameError: {:?}", e);
-							continue
-						},
-					};
-					match ui_state.game.select_next_piece(piece) {
-						Ok(()) => {
-							// now we can actually execute the transaction
-							place_piece_transaction.run(&mut ui_state.game);
-							ui_state.pieces_board.remove(piece);
-							break
-						},
-						Err(GameError::GameIsOver) => break, // game over
-						Err(e) => log::warn!("GameError: {:?}", e), // other GameErrors are less important
-					}
-		        }
-	        }
-		}
+		#[cfg(feature = "ai_enemy")]
+		ai_turn(&mut ai, ai_player, &mut ui_state);
 
         // redraw boards and piece preview
         gui.draw(&ui_state)?;
     }
     log::trace!("After game loop");
 
+	#[cfg(feature = "ai_enemy")] {
+		ai.cancel();
+		if ui_state.game.is_over() {
+			if let Some(ai_agent) = ai.agent_mut() {
+				ai_agent.game_over(&ui_state.game);
+			}
+		}
+	}
+
     std::mem::drop(gui);
 
 	log::info!("End, {:?}", ui_state.game);
-    if ui_state.game.state == GameOver {
+	log::info!("Game record:\n{}", ui_state.game.record());
+    if let Some(result) = ui_state.game.solo_result() {
+        println!("+++ GAME OVER +++");
+        match result {
+			SoloResult::Solved => println!("Solved!"),
+			SoloResult::Failed => println!("Failed: {:?}", ui_state.game.game_over_info),
+        }
+    } else if ui_state.game.state == GameOver {
         println!("+++ GAME OVER +++");
-        println!("Player {} won", ui_state.game.player_turn);
-        println!("{:?}", ui_state.game.game_over_info);
+        if let Some(winner) = ui_state.game.winner() {
+			println!("{} won", winner);
+			println!("{:?}", ui_state.game.game_over_info);
+        } else {
+			println!("Draw");
+        }
     }
 
     Ok(())
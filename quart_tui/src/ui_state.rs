@@ -10,6 +10,9 @@ pub struct UiState {
 
     /// The board where the rest of the pieces is
     pub pieces_board: Board,
+
+    /// A message shown instead of the usual status, until the next action
+    pub message: Option<String>,
 }
 
 impl UiState {
@@ -19,6 +22,7 @@ impl UiState {
 			game,
 			cursor_pos: BPos::new(0, 0),
 			pieces_board: Board::full(),
+			message: None,
 		}
 	}
 	
@@ -49,20 +53,43 @@ impl UiState {
 					match self.game.select_next_piece(piece) {
 						Ok(()) => self.pieces_board[self.cursor_pos] = None,
 						Err(GameError::PieceInUse) => {
-							log::error!("Something went terribly wrong, we tried to place a piece that was already on the board");
-							panic!("Duplicate pieces on same board");
+							log::error!("Something went wrong, the pieces board contained a piece that was already on the board");
+							self.sync_pieces_board();
 						},
-						Err(_) => {},
+						Err(e) => log::warn!("GameError: {}", e),
 		            }
 	            }
             },
             GameState::PlacePiece => {
-	            if let Err(GameError::NoPieceSelected) = self.game.place_piece(self.cursor_pos) {
-					log::error!("Something went terribly wrong, we tried to place a piece while none was selected");
-					panic!("Place without selected piece");
+	            if let Err(e) = self.game.place_piece(self.cursor_pos) {
+					log::warn!("GameError: {}", e);
 	            }
             },
             GameState::GameOver => {},
         }
     }
+
+    /// Undo the last action, putting pieces back to the `pieces_board` as necessary
+    pub fn undo(&mut self) {
+		if self.game.undo() {
+			self.sync_pieces_board();
+		}
+    }
+
+    /// Rebuild the `pieces_board` from the pieces that are neither on the board nor selected
+    pub fn sync_pieces_board(&mut self) {
+		self.pieces_board = Board::full();
+		for piece in self.game.board.0.iter().flatten().chain(&[self.game.selected_piece]).flatten() {
+			self.pieces_board.remove(*piece);
+		}
+    }
+
+    /// Show whether the remaining pieces can still be placed without completing a line
+    pub fn hint(&mut self) {
+		self.message = Some(if self.game.solo_solvable() {
+			"The remaining pieces still fit".to_owned()
+		} else {
+			"The remaining pieces won't fit anymore".to_owned()
+		});
+    }
 }